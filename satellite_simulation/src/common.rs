@@ -1,27 +1,84 @@
-use crate::simulation::satellite::Satellite;
+use std::ops::{Add, Mul, Neg, Sub};
 
-pub fn calculate_euclid_distance(pos1: &(f64, f64), pos2: &(f64, f64)) -> f64 {
-    let dx = pos1.0 - pos2.0;
-    let dy = pos1.1 - pos2.1;
+use crate::simulation::{coordinates::Eci, satellite::Satellite};
 
-    (dx.powi(2) + dy.powi(2)).sqrt()
+/**
+ * Predicts where the satellite will be `time_step` seconds after its current simulation
+ * time by propagating its orbital elements.
+ */
+pub fn calculate_future_satellite_position(satellite: &Satellite, time_step: f64) -> Eci {
+    satellite.eci_at(satellite.sim_time + time_step)
 }
 
-// Since, v = w * r => w = v / r
-pub fn calculate_angular_velocity(velocity: f64, radius: f64) -> f64 {
-    return velocity / radius;
+/**
+ * Plain 3D vector used for positions (meters) and velocities (m/s) in space.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
 }
 
-pub fn calculate_future_satellite_position(satellite: &Satellite, time_step: f64) -> (f64, f64) {
-    let angular_velocity = satellite.get_current_speed() / satellite.orbital_radius;
-    let theta_change = angular_velocity * time_step;
-    let predicted_x =
-        satellite.orbital_radius * (satellite.position.0.to_radians() + theta_change).cos();
-    let predicted_y =
-        satellite.orbital_radius * (satellite.position.1.to_radians() + theta_change).sin();
+impl Vector3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
 
-    (predicted_x, predicted_y)
+    pub fn dot(&self, other: &Vector3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: &Vector3) -> Vector3 {
+        Vector3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn distance_to(&self, other: &Vector3) -> f64 {
+        (*self - *other).norm()
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Vector3;
+
+    fn sub(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Neg for Vector3 {
+    type Output = Vector3;
+
+    fn neg(self) -> Vector3 {
+        Vector3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl Mul<f64> for Vector3 {
+    type Output = Vector3;
+
+    fn mul(self, scalar: f64) -> Vector3 {
+        Vector3::new(self.x * scalar, self.y * scalar, self.z * scalar)
+    }
 }
 
 pub const TIME_LOOKAHEAD_SECS: f64 = 10.0;
 pub const SPEED_OF_LIGHT: f64 = 299_792.458;
+pub const EARTH_RADIUS: f64 = 6_371_000.0; // Earth radius in meters
+pub const EARTH_MU: f64 = 3.986_004_418e14; // Earth's gravitational parameter (G * M) in m^3/s^2
@@ -0,0 +1,199 @@
+/*
+ * Adaptive coding and modulation. Rather than running one fixed rate that has to close at the
+ * worst range of a pass, the modem switches to the most efficient MODCOD the current Es/N0
+ * supports with the configured margin left over, at a fixed symbol rate. As a satellite climbs
+ * toward a station or closes in on a neighbor the rate steps up, and back down as it leaves.
+ *
+ * How a rate changes over a contact is kept as a RateProfile, so the contact plan and the
+ * downlink drain can integrate the actual volume instead of assuming a constant rate.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Modcod {
+    pub name: &'static str,
+    pub spectral_efficiency: f64, // information bits per symbol
+    pub required_esn0: f64,       // dB, quasi-error-free threshold
+}
+
+const fn modcod(name: &'static str, spectral_efficiency: f64, required_esn0: f64) -> Modcod {
+    Modcod {
+        name,
+        spectral_efficiency,
+        required_esn0,
+    }
+}
+
+// ETSI EN 302 307 (DVB-S2) normal frames without pilots, ideal AWGN thresholds
+const DVB_S2_MODCODS: [Modcod; 28] = [
+    modcod("QPSK 1/4", 0.490_243, -2.35),
+    modcod("QPSK 1/3", 0.656_448, -1.24),
+    modcod("QPSK 2/5", 0.789_412, -0.30),
+    modcod("QPSK 1/2", 0.988_858, 1.00),
+    modcod("QPSK 3/5", 1.188_304, 2.23),
+    modcod("QPSK 2/3", 1.322_253, 3.10),
+    modcod("QPSK 3/4", 1.487_473, 4.03),
+    modcod("QPSK 4/5", 1.587_196, 4.68),
+    modcod("QPSK 5/6", 1.654_663, 5.18),
+    modcod("QPSK 8/9", 1.766_451, 6.20),
+    modcod("QPSK 9/10", 1.788_612, 6.42),
+    modcod("8PSK 3/5", 1.779_991, 5.50),
+    modcod("8PSK 2/3", 1.980_636, 6.62),
+    modcod("8PSK 3/4", 2.228_124, 7.91),
+    modcod("8PSK 5/6", 2.478_562, 9.35),
+    modcod("8PSK 8/9", 2.646_012, 10.69),
+    modcod("8PSK 9/10", 2.679_207, 10.98),
+    modcod("16APSK 2/3", 2.637_201, 8.97),
+    modcod("16APSK 3/4", 2.966_728, 10.21),
+    modcod("16APSK 4/5", 3.165_623, 11.03),
+    modcod("16APSK 5/6", 3.300_184, 11.61),
+    modcod("16APSK 8/9", 3.523_143, 12.89),
+    modcod("16APSK 9/10", 3.567_342, 13.13),
+    modcod("32APSK 3/4", 3.703_295, 12.73),
+    modcod("32APSK 4/5", 3.951_571, 13.64),
+    modcod("32APSK 5/6", 4.119_540, 14.28),
+    modcod("32APSK 8/9", 4.397_854, 15.69),
+    modcod("32APSK 9/10", 4.453_027, 16.05),
+];
+
+// Rate-1/2 and 3/4 convolutional codes (K=7, Viterbi) at 1e-5 BER, and 2/3 trellis-coded 8PSK
+const PSK_LADDER: [Modcod; 4] = [
+    modcod("BPSK 1/2", 0.5, 1.4),
+    modcod("QPSK 1/2", 1.0, 4.4),
+    modcod("QPSK 3/4", 1.5, 7.7),
+    modcod("8PSK 2/3", 2.0, 10.0),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcmTable {
+    modcods: Vec<Modcod>,
+}
+
+impl AcmTable {
+    pub const NAMES: [&'static str; 2] = ["dvb-s2", "psk"];
+
+    pub fn new(modcods: Vec<Modcod>) -> Self {
+        Self { modcods }
+    }
+
+    pub fn dvb_s2() -> Self {
+        Self::new(DVB_S2_MODCODS.to_vec())
+    }
+
+    pub fn psk_ladder() -> Self {
+        Self::new(PSK_LADDER.to_vec())
+    }
+
+    // Table by its CLI name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dvb-s2" => Some(Self::dvb_s2()),
+            "psk" => Some(Self::psk_ladder()),
+            _ => None,
+        }
+    }
+
+    // The MODCOD with the lowest threshold, the last one to give up as a link fades
+    pub fn most_robust(&self) -> Option<Modcod> {
+        self.modcods
+            .iter()
+            .min_by(|a, b| a.required_esn0.total_cmp(&b.required_esn0))
+            .copied()
+    }
+
+    /**
+     * The highest-throughput MODCOD that still leaves `margin` dB at `esn0`, None when even
+     * the most robust one doesn't close.
+     */
+    pub fn select(&self, esn0: f64, margin: f64) -> Option<Modcod> {
+        self.modcods
+            .iter()
+            .filter(|modcod| modcod.required_esn0 + margin <= esn0)
+            .max_by(|a, b| a.spectral_efficiency.total_cmp(&b.spectral_efficiency))
+            .copied()
+    }
+}
+
+/**
+ * A piecewise-constant data rate: each step holds from its time until the next one, the last
+ * one indefinitely, and nothing flows before the first.
+ */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateProfile {
+    steps: Vec<(f64, f64)>, // (time the rate takes effect, bits per second), in time order
+}
+
+impl RateProfile {
+    pub fn constant(start: f64, rate: f64) -> Self {
+        Self {
+            steps: vec![(start, rate)],
+        }
+    }
+
+    // Adds a step from `time` on, unless the rate doesn't actually change
+    pub fn push(&mut self, time: f64, rate: f64) {
+        if self.steps.last().is_some_and(|(_, last)| *last == rate) {
+            return;
+        }
+        self.steps.push((time, rate));
+    }
+
+    pub fn rate_at(&self, time: f64) -> f64 {
+        let next = self.steps.partition_point(|(start, _)| *start <= time);
+        next.checked_sub(1).map_or(0.0, |index| self.steps[index].1)
+    }
+
+    // Bits that can be sent between `start` and `end`
+    pub fn volume_between(&self, start: f64, end: f64) -> f64 {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(index, (from, rate))| {
+                let to = self.steps.get(index + 1).map_or(f64::INFINITY, |s| s.0);
+                rate * (to.min(end) - from.max(start)).max(0.0)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_most_efficient_modcod_that_keeps_the_margin() {
+        let table = AcmTable::dvb_s2();
+        // 8PSK 2/3 needs more than QPSK 9/10 but carries more per symbol
+        assert_eq!(table.select(7.0, 0.0).unwrap().name, "8PSK 2/3");
+        assert_eq!(table.select(7.0, 3.0).unwrap().name, "QPSK 2/3");
+        assert_eq!(table.select(20.0, 0.0).unwrap().name, "32APSK 9/10");
+        // Exactly on the threshold still closes
+        assert_eq!(table.select(1.0, 0.0).unwrap().name, "QPSK 1/2");
+    }
+
+    #[test]
+    fn nothing_closes_below_the_most_robust_modcod() {
+        let table = AcmTable::psk_ladder();
+        let robust = table.most_robust().unwrap();
+        assert_eq!(robust.name, "BPSK 1/2");
+        assert_eq!(table.select(robust.required_esn0 - 0.01, 0.0), None);
+        assert_eq!(AcmTable::new(Vec::new()).select(30.0, 0.0), None);
+    }
+
+    #[test]
+    fn rate_profile_integrates_each_step() {
+        let mut profile = RateProfile::constant(10.0, 1e6);
+        profile.push(20.0, 1e6); // same rate, no new step
+        profile.push(30.0, 2e6);
+        assert_eq!(profile.steps.len(), 2);
+
+        assert_eq!(profile.volume_between(0.0, 10.0), 0.0);
+        assert_eq!(profile.volume_between(0.0, 30.0), 20e6);
+        assert_eq!(profile.volume_between(25.0, 40.0), 5e6 + 20e6);
+
+        assert_eq!(profile.rate_at(5.0), 0.0);
+        assert_eq!(profile.rate_at(10.0), 1e6);
+        assert_eq!(profile.rate_at(29.9), 1e6);
+        assert_eq!(profile.rate_at(30.0), 2e6);
+        assert_eq!(profile.rate_at(1e9), 2e6);
+    }
+}
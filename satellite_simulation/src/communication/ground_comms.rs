@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use super::acm::RateProfile;
+use crate::simulation::{ground_station::is_ground_station, tracking::Contact};
+
+/*
+ * Downlink scheduling. A station's antenna tracks one satellite at a time and a satellite has
+ * one downlink radio, yet passes overlap all the time: several satellites over the same
+ * station, or one satellite seen by two stations. The scheduler hands out the contested time
+ * so that as much data as possible reaches the ground, favouring high-priority satellites.
+ *
+ * Time is cut into slots. Slot by slot, every (station, satellite) pair in view is valued at
+ * priority * volume it could move in that slot at the pass's ACM rates, and pairs are granted
+ * greedily best-first while neither side is taken. Switching a station to another satellite
+ * costs `setup_time` of that slot (slew and acquisition), so a station sticks with a satellite
+ * unless another is worth clearly more. Among equally valuable satellites the one with fewer
+ * passes left goes first, since the others will get another chance.
+ */
+
+pub const STORAGE_UNIT_BITS: f64 = 8_000.0; // Satellite::storage_on_board counts kilobytes
+pub const DEFAULT_SCHEDULE_SLOT: f64 = 10.0; // seconds
+pub const DEFAULT_SETUP_TIME: f64 = 5.0; // seconds lost when a station switches satellites
+pub const DEFAULT_PRIORITY: f64 = 1.0;
+
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    pub slot: f64,
+    pub setup_time: f64,
+    pub priorities: HashMap<u32, f64>, // per satellite, DEFAULT_PRIORITY when missing
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            slot: DEFAULT_SCHEDULE_SLOT,
+            setup_time: DEFAULT_SETUP_TIME,
+            priorities: HashMap::new(),
+        }
+    }
+}
+
+impl SchedulerConfig {
+    pub fn priority(&self, satellite: u32) -> f64 {
+        self.priorities
+            .get(&satellite)
+            .copied()
+            .unwrap_or(DEFAULT_PRIORITY)
+    }
+}
+
+/**
+ * A stretch of time a station is dedicated to one satellite. `volume` is what the schedule
+ * expects to move in bits, `sent` what the simulation has moved so far, and the rate over the
+ * window follows the pass it was cut from.
+ */
+#[derive(Debug, Clone)]
+pub struct DownlinkWindow {
+    pub station: u32,
+    pub satellite: u32,
+    pub start: f64,
+    pub end: f64,
+    pub rate_profile: RateProfile,
+    pub volume: f64,
+    pub priority: f64,
+    pub sent: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DownlinkSchedule {
+    timelines: HashMap<u32, Vec<DownlinkWindow>>, // per station, in time order
+}
+
+impl DownlinkSchedule {
+    pub fn timeline(&self, station: u32) -> &[DownlinkWindow] {
+        self.timelines.get(&station).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn stations(&self) -> Vec<u32> {
+        let mut stations: Vec<u32> = self.timelines.keys().copied().collect();
+        stations.sort();
+        stations
+    }
+
+    pub fn windows(&self) -> impl Iterator<Item = &DownlinkWindow> {
+        self.timelines.values().flatten()
+    }
+
+    pub fn windows_mut(&mut self) -> impl Iterator<Item = &mut DownlinkWindow> {
+        self.timelines.values_mut().flatten()
+    }
+
+    // Bits the schedule expects to bring down
+    pub fn total_volume(&self) -> f64 {
+        self.windows().map(|window| window.volume).sum()
+    }
+
+    // The objective the scheduler maximizes: volume weighted by satellite priority
+    pub fn weighted_volume(&self) -> f64 {
+        self.windows()
+            .map(|window| window.volume * window.priority)
+            .sum()
+    }
+
+    pub fn volume_for(&self, satellite: u32) -> f64 {
+        self.windows()
+            .filter(|window| window.satellite == satellite)
+            .map(|window| window.volume)
+            .sum()
+    }
+}
+
+/**
+ * Builds a conflict-free schedule over [start, end] from the predicted ground `passes`
+ * (satellite to station contacts, anything else is ignored) and the bits each satellite has
+ * queued.
+ */
+pub fn schedule_downlinks(
+    passes: &[Contact],
+    queued: &HashMap<u32, f64>,
+    start: f64,
+    end: f64,
+    config: &SchedulerConfig,
+) -> DownlinkSchedule {
+    let passes: Vec<&Contact> = passes
+        .iter()
+        .filter(|pass| is_ground_station(pass.destination) && !is_ground_station(pass.source))
+        .filter(|pass| pass.end_time > start && pass.start_time < end)
+        .collect();
+    let mut remaining = queued.clone();
+    let mut schedule = DownlinkSchedule::default();
+    let mut serving: HashMap<u32, u32> = HashMap::new(); // station -> satellite of the last slot
+
+    let mut slot_start = start;
+    while slot_start < end {
+        let slot_end = (slot_start + config.slot).min(end);
+        let mut candidates = Vec::new();
+        for pass in &passes {
+            let (from, to) = (pass.start_time.max(slot_start), pass.end_time.min(slot_end));
+            let left = remaining.get(&pass.source).copied().unwrap_or(0.0);
+            if from >= to || left <= 0.0 {
+                continue;
+            }
+            let setup = if serving.get(&pass.destination) == Some(&pass.source) {
+                0.0
+            } else {
+                config.setup_time
+            };
+            let volume = left.min(pass.volume_between(from + setup, to));
+            if volume <= 0.0 {
+                continue;
+            }
+            let priority = config.priority(pass.source);
+            let passes_left = passes
+                .iter()
+                .filter(|other| other.source == pass.source && other.start_time >= slot_end)
+                .count();
+            candidates.push((
+                priority * volume,
+                passes_left,
+                pass,
+                from,
+                to,
+                volume,
+                priority,
+            ));
+        }
+        candidates.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then(a.1.cmp(&b.1))
+                .then(a.2.destination.cmp(&b.2.destination))
+                .then(a.2.source.cmp(&b.2.source))
+        });
+
+        let mut granted: HashMap<u32, u32> = HashMap::new();
+        for (_, _, pass, from, to, volume, priority) in candidates {
+            let (station, satellite) = (pass.destination, pass.source);
+            if granted.contains_key(&station) || granted.values().any(|sat| *sat == satellite) {
+                continue;
+            }
+            granted.insert(station, satellite);
+            *remaining.entry(satellite).or_default() -= volume;
+
+            let timeline = schedule.timelines.entry(station).or_default();
+            match timeline.last_mut() {
+                Some(last) if last.satellite == satellite && last.end >= from => {
+                    last.end = to;
+                    last.volume += volume;
+                }
+                _ => timeline.push(DownlinkWindow {
+                    station,
+                    satellite,
+                    start: from,
+                    end: to,
+                    rate_profile: pass.rate_profile.clone(),
+                    volume,
+                    priority,
+                    sent: 0.0,
+                }),
+            }
+        }
+        serving = granted;
+        slot_start = slot_end;
+    }
+
+    schedule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::ground_station::FIRST_GROUND_STATION_ID;
+
+    const STATION_A: u32 = FIRST_GROUND_STATION_ID;
+    const STATION_B: u32 = FIRST_GROUND_STATION_ID + 1;
+
+    fn pass(satellite: u32, station: u32, start_time: f64, end_time: f64) -> Contact {
+        Contact::constant(satellite, station, start_time, end_time, 1e6)
+    }
+
+    fn overlap(a: &DownlinkWindow, b: &DownlinkWindow) -> bool {
+        a.start < b.end && b.start < a.end
+    }
+
+    #[test]
+    fn nothing_is_booked_twice_at_once() {
+        let passes = vec![
+            pass(1, STATION_A, 0.0, 300.0),
+            pass(2, STATION_A, 100.0, 400.0),
+            pass(3, STATION_A, 50.0, 250.0),
+            pass(1, STATION_B, 150.0, 500.0),
+            pass(2, STATION_B, 0.0, 200.0),
+            pass(3, STATION_B, 200.0, 450.0),
+        ];
+        let queued: HashMap<u32, f64> = [(1, 1e8), (2, 1e8), (3, 1e8)].into();
+        let schedule =
+            schedule_downlinks(&passes, &queued, 0.0, 600.0, &SchedulerConfig::default());
+
+        let windows: Vec<&DownlinkWindow> = schedule.windows().collect();
+        assert!(!windows.is_empty());
+        for (i, a) in windows.iter().enumerate() {
+            for b in &windows[i + 1..] {
+                let shared = a.station == b.station || a.satellite == b.satellite;
+                assert!(!(shared && overlap(a, b)), "{:?} overlaps {:?}", a, b);
+            }
+        }
+        for station in schedule.stations() {
+            let timeline = schedule.timeline(station);
+            assert!(timeline.windows(2).all(|pair| pair[0].end <= pair[1].start));
+        }
+    }
+
+    #[test]
+    fn higher_priority_goes_down_first() {
+        let passes = vec![
+            pass(1, STATION_A, 0.0, 100.0),
+            pass(2, STATION_A, 0.0, 100.0),
+        ];
+        let queued: HashMap<u32, f64> = [(1, 30e6), (2, 30e6)].into();
+        let config = SchedulerConfig {
+            priorities: [(2, 3.0)].into(),
+            ..Default::default()
+        };
+        let schedule = schedule_downlinks(&passes, &queued, 0.0, 100.0, &config);
+
+        // Satellite 2 loses the setup time once, then satellite 1 once when the station switches
+        let timeline = schedule.timeline(STATION_A);
+        assert_eq!(timeline.len(), 2);
+        assert_eq!((timeline[0].satellite, timeline[0].start), (2, 0.0));
+        assert_eq!((timeline[0].end, timeline[0].volume), (40.0, 30e6));
+        assert_eq!((timeline[1].satellite, timeline[1].start), (1, 40.0));
+        assert_eq!((timeline[1].end, timeline[1].volume), (80.0, 30e6));
+        assert_eq!(schedule.total_volume(), 60e6);
+        assert_eq!(schedule.weighted_volume(), 30e6 * 3.0 + 30e6);
+        assert_eq!(schedule.volume_for(2), 30e6);
+        assert_eq!(schedule.volume_for(3), 0.0);
+    }
+}
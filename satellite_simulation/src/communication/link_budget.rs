@@ -0,0 +1,249 @@
+use std::f64::consts::PI;
+
+use crate::common::SPEED_OF_LIGHT;
+
+use super::acm::{AcmTable, Modcod};
+
+/*
+ * RF link budgets. Every contact is closed through the usual chain
+ *      C/N0 = EIRP - free-space loss - other losses + G_rx - 10 log10(T_sys) - 10 log10(k)
+ * and the data rate we can actually run over it is the one that leaves the required Eb/N0
+ * plus the target margin, capped at what the modem can do. Shorter range or bigger antennas
+ * give faster contacts, which is what lets CGR tell a strong link from a marginal one.
+ * With an ACM table the rate instead comes from the best MODCOD that closes at the
+ * transmitter's symbol rate.
+ */
+
+pub const BOLTZMANN_DB: f64 = -228.6; // 10 log10(k), dBW/K/Hz
+pub const DEFAULT_REQUIRED_EBN0: f64 = 4.4; // dB, QPSK with rate-1/2 convolutional code at 1e-5 BER
+pub const DEFAULT_LINK_MARGIN: f64 = 3.0; // dB
+pub const DEFAULT_OTHER_LOSSES: f64 = 2.0; // dB of pointing, polarization and cable losses
+
+/**
+ * What a node transmits and receives with. The antenna gain is used both ways and the noise
+ * temperature is that of the whole receive chain, antenna included.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Radio {
+    pub frequency: f64,         // Hz
+    pub transmit_power: f64,    // W
+    pub antenna_gain: f64,      // dBi
+    pub noise_temperature: f64, // K
+    pub symbol_rate: f64,       // symbols per second the modem runs ACM at
+}
+
+impl Default for Radio {
+    // An S-band small-satellite transceiver with a patch array
+    fn default() -> Self {
+        Self {
+            frequency: 2.2e9,
+            transmit_power: 1.0,
+            antenna_gain: 15.0,
+            noise_temperature: 600.0,
+            symbol_rate: 5e6,
+        }
+    }
+}
+
+impl Radio {
+    // A ground station dish of `antenna_gain` dBi with a cooled LNA
+    pub fn ground_station(antenna_gain: f64) -> Self {
+        Self {
+            frequency: 2.2e9,
+            transmit_power: 10.0,
+            antenna_gain,
+            noise_temperature: 150.0,
+            symbol_rate: 1e6,
+        }
+    }
+
+    // Effective isotropic radiated power, dBW
+    pub fn eirp(&self) -> f64 {
+        10.0 * self.transmit_power.log10() + self.antenna_gain
+    }
+
+    // Receive figure of merit G/T, dB/K
+    pub fn figure_of_merit(&self) -> f64 {
+        self.antenna_gain - 10.0 * self.noise_temperature.log10()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkBudgetConfig {
+    pub required_ebn0: f64, // dB the modulation and coding need, unused with ACM
+    pub margin: f64,        // dB kept on top of it
+    pub other_losses: f64,  // dB
+    pub acm: Option<AcmTable>,
+}
+
+impl Default for LinkBudgetConfig {
+    fn default() -> Self {
+        Self {
+            required_ebn0: DEFAULT_REQUIRED_EBN0,
+            margin: DEFAULT_LINK_MARGIN,
+            other_losses: DEFAULT_OTHER_LOSSES,
+            acm: Some(AcmTable::dvb_s2()),
+        }
+    }
+}
+
+/**
+ * One direction of a link at one range. `ebn0` and `margin` are what we get at `data_rate`;
+ * the margin only drops below the configured one when even the lowest usable rate can't
+ * close the link, in which case `data_rate` is zero. Under ACM the margin is over the chosen
+ * MODCOD's Es/N0 threshold instead, or the most robust one's when none closes.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkBudget {
+    pub range: f64,                    // meters
+    pub free_space_loss: f64,          // dB
+    pub eirp: f64,                     // dBW
+    pub receive_gain: f64,             // dBi
+    pub noise_temperature: f64,        // K
+    pub carrier_to_noise_density: f64, // dB-Hz
+    pub data_rate: f64,                // bits per second
+    pub ebn0: f64,                     // dB
+    pub margin: f64,                   // dB over the required Eb/N0
+    pub modcod: Option<Modcod>,        // what ACM picked, if it is in use and the link closes
+}
+
+impl LinkBudget {
+    /**
+     * Budget for `transmitter` sending to `receiver` over `range` meters on the transmitter's
+     * frequency, running as fast as the link allows up to `max_data_rate` bits per second.
+     */
+    pub fn compute(
+        transmitter: &Radio,
+        receiver: &Radio,
+        range: f64,
+        max_data_rate: f64,
+        config: &LinkBudgetConfig,
+    ) -> Self {
+        let free_space_loss = free_space_path_loss(range, transmitter.frequency);
+        let carrier_to_noise_density = transmitter.eirp() - free_space_loss - config.other_losses
+            + receiver.figure_of_merit()
+            - BOLTZMANN_DB;
+        let (data_rate, modcod) = match &config.acm {
+            Some(table) => {
+                let esn0 = carrier_to_noise_density - to_db(transmitter.symbol_rate);
+                let modcod = table.select(esn0, config.margin);
+                let rate = modcod.map_or(0.0, |modcod| {
+                    (transmitter.symbol_rate * modcod.spectral_efficiency).min(max_data_rate)
+                });
+                (rate, modcod)
+            }
+            None => {
+                let achievable =
+                    from_db(carrier_to_noise_density - config.required_ebn0 - config.margin);
+                let rate = if achievable >= 1.0 {
+                    achievable.min(max_data_rate)
+                } else {
+                    0.0
+                };
+                (rate, None)
+            }
+        };
+        // At zero rate report Eb/N0 at 1 bit/s, i.e. how far the link is from closing at all
+        let ebn0 = carrier_to_noise_density - to_db(data_rate.max(1.0));
+        // Under ACM a link that doesn't close is measured against the most robust MODCOD
+        let threshold = modcod.or_else(|| config.acm.as_ref()?.most_robust());
+        let margin = match threshold {
+            Some(modcod) => {
+                carrier_to_noise_density - to_db(transmitter.symbol_rate) - modcod.required_esn0
+            }
+            None => ebn0 - config.required_ebn0,
+        };
+
+        Self {
+            range,
+            free_space_loss,
+            eirp: transmitter.eirp(),
+            receive_gain: receiver.antenna_gain,
+            noise_temperature: receiver.noise_temperature,
+            carrier_to_noise_density,
+            data_rate,
+            ebn0,
+            margin,
+            modcod,
+        }
+    }
+
+    pub fn closes(&self) -> bool {
+        self.data_rate > 0.0
+    }
+}
+
+// Free-space path loss in dB over `range` meters at `frequency` Hz
+pub fn free_space_path_loss(range: f64, frequency: f64) -> f64 {
+    let wavelength = SPEED_OF_LIGHT * 1000.0 / frequency;
+    20.0 * (4.0 * PI * range / wavelength).log10()
+}
+
+pub fn to_db(ratio: f64) -> f64 {
+    10.0 * ratio.log10()
+}
+
+pub fn from_db(db: f64) -> f64 {
+    10.0_f64.powf(db / 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed_rate() -> LinkBudgetConfig {
+        LinkBudgetConfig {
+            acm: None,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn free_space_loss_matches_the_textbook_formula() {
+        // 20 log10(d km) + 20 log10(f MHz) + 32.45
+        let loss = free_space_path_loss(1_000_000.0, 2.2e9);
+        assert!((loss - (60.0 + 20.0 * 2200.0_f64.log10() + 32.45)).abs() < 0.01);
+        // Twice as far is a quarter of the power
+        let farther = free_space_path_loss(2_000_000.0, 2.2e9);
+        assert!((farther - loss - to_db(4.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rate_leaves_exactly_the_configured_margin() {
+        let satellite = Radio::default();
+        let station = Radio::ground_station(35.0);
+        let config = fixed_rate();
+        let budget = LinkBudget::compute(&satellite, &station, 1_000_000.0, f64::MAX, &config);
+
+        let expected = satellite.eirp() - budget.free_space_loss - config.other_losses
+            + station.figure_of_merit()
+            - BOLTZMANN_DB;
+        assert!((budget.carrier_to_noise_density - expected).abs() < 1e-9);
+        assert!(budget.closes());
+        assert!((budget.margin - config.margin).abs() < 1e-9);
+
+        // Capping the rate buys margin back, one dB per dB of rate given up
+        let capped = LinkBudget::compute(
+            &satellite,
+            &station,
+            1_000_000.0,
+            budget.data_rate / 10.0,
+            &config,
+        );
+        assert!((capped.margin - config.margin - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn link_out_of_reach_does_not_close() {
+        let budget = LinkBudget::compute(
+            &Radio::default(),
+            &Radio::default(),
+            1e10, // ten million km on patch antennas
+            1e6,
+            &fixed_rate(),
+        );
+        assert!(!budget.closes());
+        assert_eq!(budget.data_rate, 0.0);
+        assert!(budget.margin < DEFAULT_LINK_MARGIN);
+    }
+}
@@ -1 +1,3 @@
-
+pub mod acm;
+pub mod ground_comms;
+pub mod link_budget;
@@ -1,17 +1,30 @@
-use std::{collections::HashMap, time::Duration};
-
-use clap::{Arg, ArgMatches, Command};
-use rand::Rng;
-use simulation::{network::SatelliteNetwork, satellite::Satellite};
-use tokio_serial::{SerialPort, SerialPortBuilder, SerialPortBuilderExt};
+use clap::{builder::PossibleValuesParser, Arg, ArgAction, Command};
+use communication::{
+    acm::AcmTable,
+    ground_comms::{SchedulerConfig, STORAGE_UNIT_BITS},
+    link_budget::{LinkBudgetConfig, Radio},
+};
+use routing::dtn::{DtnMode, DEFAULT_SPRAY_COPIES};
+use routing::heuristics::{
+    ClosestToGround, EnergyAware, RelayStrategy, StorageAware, WeightedScore,
+};
+use routing::pathfinding::{SearchAlgorithm, DEFAULT_BACKUP_PATHS};
+use security::{key_exchange, secure_comm, signature};
+use simulation::{
+    cgr::DEFAULT_BUNDLE_SIZE,
+    drag::StationKeeping,
+    ground_station::{GroundStation, DEFAULT_PASS_SEARCH_HORIZON, GROUND_STATION_ID},
+    network::{PauseAt, SatelliteNetwork},
+    numerical::Integrator,
+    time::{check_lookahead, check_speed, check_step},
+    tracking::{ContactPlanConfig, DEFAULT_DOPPLER_STEP},
+};
 mod common;
 mod communication;
 mod routing;
 mod security;
 mod simulation;
 mod storage;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::mpsc;
 
 #[tokio::main]
 async fn main() {
@@ -26,15 +39,697 @@ async fn main() {
                 .default_value("10")
                 .value_parser(clap::value_parser!(usize)),
         )
+        .arg(
+            Arg::new("tle")
+                .long("tle")
+                .help("Load satellites from a CelesTrak-style TLE file instead of generating them")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("ground-station")
+                .long("ground-station")
+                .help("Ground station as name,lat,lon[,alt_m[,mask_deg]][,gain=dBi][,window=start_s-end_s...]; repeat for more, replaces the default")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(GroundStation)),
+        )
+        .arg(
+            Arg::new("run")
+                .long("run")
+                .help("Run the time-stepped simulation for this many seconds of simulation time")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("step")
+                .long("step")
+                .help("Simulation time step in seconds")
+                .default_value("10")
+                .value_parser(|value: &str| {
+                    let step: f64 = value
+                        .parse()
+                        .map_err(|_| format!("{:?} is not a number", value))?;
+                    check_step(step).map_err(|error| error.to_string())
+                }),
+        )
+        .arg(
+            Arg::new("propagator")
+                .long("propagator")
+                .help("Integrate every orbit numerically under J2 and drag instead of propagating it analytically")
+                .default_value("analytical")
+                .value_parser(PossibleValuesParser::new(["analytical"].into_iter().chain(Integrator::NAMES))),
+        )
+        .arg(
+            Arg::new("station-keeping")
+                .long("station-keeping")
+                .help("With --run, raise any satellite that decays this many km below its starting altitude; generated satellites with the analytical propagator only")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("real-time")
+                .long("real-time")
+                .help("Pace the run against the wall clock at this speed-up (1 = real time)")
+                .value_parser(|value: &str| {
+                    let speed: f64 = value
+                        .parse()
+                        .map_err(|_| format!("{:?} is not a number", value))?;
+                    check_speed(speed).map_err(|error| error.to_string())
+                }),
+        )
+        .arg(
+            Arg::new("pause-at")
+                .long("pause-at")
+                .help("With --run, pause the clock once this many seconds of simulation time have passed")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("lookahead")
+                .long("lookahead")
+                .help("How many seconds ahead the live contact map looks for links coming up")
+                .value_parser(|value: &str| {
+                    let lookahead: f64 = value
+                        .parse()
+                        .map_err(|_| format!("{:?} is not a number", value))?;
+                    check_lookahead(lookahead).map_err(|error| error.to_string())
+                }),
+        )
+        .arg(
+            Arg::new("contact-plan")
+                .long("contact-plan")
+                .help("Print the predicted contact plan over this many seconds and exit")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("send")
+                .long("send")
+                .help("With --run, send a bundle from each of these satellites to the ground and print its event log")
+                .num_args(1..)
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("bundle-size")
+                .long("bundle-size")
+                .help("Size of the bundle sent with --send, in megabytes")
+                .value_parser(|value: &str| {
+                    let megabytes: f64 = value
+                        .parse()
+                        .map_err(|_| format!("{:?} is not a number", value))?;
+                    if megabytes > 0.0 && megabytes.is_finite() {
+                        Ok(megabytes)
+                    } else {
+                        Err(format!("bundle size must be a positive number of megabytes, got {}", megabytes))
+                    }
+                }),
+        )
+        .arg(
+            Arg::new("compare-relays")
+                .long("compare-relays")
+                .help("Print the relay every strategy picks for each satellite and exit")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("route")
+                .long("route")
+                .help("Print the best CGR routes from this satellite to the ground and exit")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("dtn")
+                .long("dtn")
+                .help("Also route the bundles sent with --send opportunistically and compare with CGR")
+                .value_parser(DtnMode::NAMES),
+        )
+        .arg(
+            Arg::new("copies")
+                .long("copies")
+                .help("Copies each bundle starts with under --dtn spray-and-wait")
+                .default_value("8")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("schedule")
+                .long("schedule")
+                .help("Schedule downlinks of every satellite's stored data and print each station's timeline; with --run the schedule is executed")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("priority")
+                .long("priority")
+                .help("Downlink priority of a satellite for --schedule, as id=weight (default 1); repeatable")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("radio")
+                .long("radio")
+                .help("Transmit power and antenna gain of a satellite's radio, as id=watts,dBi; repeatable")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("passes")
+                .long("passes")
+                .help("Print this satellite's passes over every ground station in the next day and exit")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("acm")
+                .long("acm")
+                .help("MODCOD table links adapt their rate with, or off for a rate held at its mid-contact value")
+                .default_value("dvb-s2")
+                .value_parser(PossibleValuesParser::new(AcmTable::NAMES.into_iter().chain(["off"]))),
+        )
+        .arg(
+            Arg::new("doppler")
+                .long("doppler")
+                .help("Print a Doppler frequency-correction table as CSV for every contact this satellite transmits on, then exit")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("eclipses")
+                .long("eclipses")
+                .help("Print this satellite's eclipse entries and exits in the next day and exit")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("paths")
+                .long("paths")
+                .help("Print the downlink paths every search finds from this satellite and exit")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("seal")
+                .long("seal")
+                .help("Sign and encrypt this message for the ground, check that only the ground can open it, and exit")
+                .value_parser(clap::value_parser!(String)),
+        )
         .get_matches();
 
+    if let Some(message) = matches.get_one::<String>("seal") {
+        // The source satellite signs, only the ground's key opens it and relays merely carry it
+        let (mut satellite_key, satellite_identity) = signature::generate_identity_keypair();
+        let (ground_secret, ground_public) = key_exchange::generate_keypair();
+        let (relay_secret, _) = key_exchange::generate_keypair();
+        let sealed =
+            match secure_comm::encrypt_and_sign(message, &mut satellite_key, &ground_public) {
+                Ok(sealed) => sealed,
+                Err(error) => {
+                    eprintln!("Failed to seal the message: {}", error);
+                    return;
+                }
+            };
+        println!(
+            "🔐 Sealed {} bytes into {} bytes of signed ciphertext",
+            message.len(),
+            sealed.ciphertext.len()
+        );
+        match secure_comm::verify_and_decrypt(&sealed, &satellite_identity, &relay_secret) {
+            Ok(_) => println!("❌ A relay could open the message"),
+            Err(error) => println!("🔒 A relay can't open it: {}", error),
+        }
+        match secure_comm::verify_and_decrypt(&sealed, &satellite_identity, &ground_secret) {
+            Ok(opened) => println!("📡 The ground opened it: {:?}", opened),
+            Err(error) => println!("❌ The ground couldn't open it: {}", error),
+        }
+        return;
+    }
+
     let num_satellites: usize = *matches.get_one::<usize>("num-satellites").unwrap_or(&5);
 
+    // Only Keplerian orbits can be maneuvered, see Satellite::can_maneuver
+    let numerical = matches
+        .get_one::<String>("propagator")
+        .is_some_and(|name| Integrator::from_name(name).is_some());
+    if matches.contains_id("station-keeping") && (numerical || matches.contains_id("tle")) {
+        eprintln!("--station-keeping can't be combined with --tle or a numerical --propagator");
+        return;
+    }
+
     // Create a network of satellites by first generating them then creating a graph and
     // updating their respective positions in the graph
     let mut network = SatelliteNetwork::new();
-    network.generate_satellite_network(num_satellites);
-    network.update_satellite_network();
+    if let Some(lookahead) = matches.get_one::<f64>("lookahead") {
+        if let Err(error) = network.clock_mut().set_lookahead(*lookahead) {
+            eprintln!("Invalid --lookahead: {}", error);
+            return;
+        }
+    }
+    match matches.get_one::<String>("tle") {
+        Some(tle_path) => match network.load_tle_constellation(tle_path) {
+            Ok(count) => println!("🛰️ Loaded {} satellites from {}", count, tle_path),
+            Err(e) => {
+                eprintln!("Failed to load TLE file {}: {}", tle_path, e);
+                return;
+            }
+        },
+        None => network.generate_satellite_network(num_satellites),
+    }
+    if let Some(stations) = matches.get_many::<GroundStation>("ground-station") {
+        if let Err(error) = network.set_ground_stations(stations.cloned().collect()) {
+            eprintln!("Invalid --ground-station: {}", error);
+            return;
+        }
+        for station in network.ground_stations() {
+            println!(
+                "📡 Ground station {} ({}) at {:.4}, {:.4} with a {}° mask and a {:.1} dBi antenna",
+                station.id,
+                station.name,
+                station.location.latitude,
+                station.location.longitude,
+                station.elevation_mask,
+                station.antenna.gain
+            );
+            for (start, end) in &station.availability {
+                println!("  available {:.0}s to {:.0}s", start, end);
+            }
+        }
+    }
+
+    for entry in matches.get_many::<String>("radio").into_iter().flatten() {
+        let parsed = entry.split_once('=').and_then(|(id, radio)| {
+            let (power, gain) = radio.split_once(',')?;
+            Some((id.parse().ok()?, power.parse().ok()?, gain.parse().ok()?))
+        });
+        let Some((id, transmit_power, antenna_gain)) = parsed else {
+            eprintln!("Invalid radio {:?}, expected id=watts,dBi", entry);
+            return;
+        };
+        let radio = Radio {
+            transmit_power,
+            antenna_gain,
+            ..Radio::default()
+        };
+        if !network.set_radio(id, radio) {
+            eprintln!("No satellite {} to fit a radio to", id);
+            return;
+        }
+        println!(
+            "📻 Satellite {} transmits {} W through a {} dBi antenna",
+            id, transmit_power, antenna_gain
+        );
+    }
+
+    if let Some(integrator) = matches
+        .get_one::<String>("propagator")
+        .and_then(|name| Integrator::from_name(name))
+    {
+        println!("🧮 Propagating numerically with {}", integrator.name());
+        network.set_integrator(integrator);
+    }
+
+    let acm = matches
+        .get_one::<String>("acm")
+        .map_or("dvb-s2", String::as_str);
+    let plan_config = ContactPlanConfig {
+        horizon: *matches
+            .get_one::<f64>("contact-plan")
+            .unwrap_or(&ContactPlanConfig::default().horizon),
+        link_budget: LinkBudgetConfig {
+            acm: AcmTable::from_name(acm),
+            ..LinkBudgetConfig::default()
+        },
+        ..ContactPlanConfig::default()
+    };
+    if let Err(error) = network.set_contact_plan_config(plan_config) {
+        eprintln!("Invalid contact plan: {}", error);
+        return;
+    }
+
+    if matches.get_flag("compare-relays") {
+        let strategies: [Box<dyn RelayStrategy>; 4] = [
+            Box::new(ClosestToGround),
+            Box::new(WeightedScore::default()),
+            Box::new(EnergyAware::default()),
+            Box::new(StorageAware::default()),
+        ];
+        let mut ids: Vec<u32> = network.satellites().keys().copied().collect();
+        ids.sort();
+        let mut picks: Vec<Vec<String>> = vec![Vec::new(); ids.len()];
+        for strategy in strategies {
+            network.set_relay_strategy(strategy);
+            let name = network.relay_strategy().name();
+            for (id, row) in ids.iter().zip(picks.iter_mut()) {
+                row.push(match network.find_best_relay(*id) {
+                    Some(relay) => format!("{} -> {}", name, relay),
+                    None => format!("{} -> none", name),
+                });
+            }
+        }
+        for (id, row) in ids.iter().zip(&picks) {
+            println!("📡 {}: {}", id, row.join(", "));
+        }
+        return;
+    }
+
+    if let Some(satellite) = matches.get_one::<u32>("passes") {
+        let passes = network.predict_passes(*satellite, DEFAULT_PASS_SEARCH_HORIZON);
+        if passes.is_empty() {
+            println!("❌ Satellite {} has no passes in the next day", satellite);
+        }
+        let now = network.clock().now();
+        for pass in passes {
+            println!(
+                "🛰️ {} over ground station {}: AOS {} from {:.0}°, TCA {} at {:.1}°, LOS {} to {:.0}° ({:.0}s){}",
+                pass.satellite,
+                pass.station,
+                network.clock().epoch_at(pass.aos),
+                pass.aos_azimuth,
+                network.clock().epoch_at(pass.tca),
+                pass.max_elevation,
+                network.clock().epoch_at(pass.los),
+                pass.los_azimuth,
+                pass.duration(),
+                if pass.is_in_progress(now) { ", in progress" } else { "" }
+            );
+        }
+        return;
+    }
+
+    if let Some(satellite) = matches.get_one::<u32>("eclipses") {
+        if let Some(sat) = network.satellites().get(satellite) {
+            println!(
+                "🌗 Satellite {} is in {} now",
+                satellite,
+                sat.shadow().name()
+            );
+        }
+        let events = network.predict_eclipses(*satellite, DEFAULT_PASS_SEARCH_HORIZON);
+        if events.is_empty() {
+            println!("❌ Satellite {} sees no eclipse in the next day", satellite);
+        }
+        for event in events {
+            println!(
+                "{} {} {} at {}",
+                if event.is_entry() { "🌑" } else { "🌕" },
+                event.satellite,
+                event.kind.name(),
+                network.clock().epoch_at(event.time)
+            );
+        }
+        return;
+    }
+
+    if let Some(satellite) = matches.get_one::<u32>("doppler") {
+        let tables = match network.doppler_tables(*satellite, DEFAULT_DOPPLER_STEP) {
+            Ok(tables) => tables,
+            Err(error) => {
+                eprintln!("Invalid contact plan: {}", error);
+                return;
+            }
+        };
+        if tables.is_empty() {
+            println!("❌ Satellite {} has no contacts to correct", satellite);
+        }
+        for table in tables {
+            println!(
+                "📻 {} -> {} at {:.4} GHz: up to {:.1} kHz, {:.1} Hz/s",
+                table.source,
+                table.destination,
+                table.frequency / 1e9,
+                table.max_doppler() / 1e3,
+                table.max_doppler_rate()
+            );
+            print!("{}", table.to_csv(network.clock()));
+        }
+        return;
+    }
+
+    if let Some(source) = matches.get_one::<u32>("paths") {
+        network.update_satellite_network();
+        for algorithm in [
+            SearchAlgorithm::MinHop,
+            SearchAlgorithm::MinLatency,
+            SearchAlgorithm::AStar,
+        ] {
+            match network.find_downlink_paths(*source, algorithm, DEFAULT_BACKUP_PATHS) {
+                Some(paths) => {
+                    for (i, path) in std::iter::once(&paths.primary)
+                        .chain(&paths.backups)
+                        .enumerate()
+                    {
+                        println!(
+                            "🗺️ {:?} #{}: {:?} ({} hops, {:.3} ms, down through {})",
+                            algorithm,
+                            i,
+                            path.nodes,
+                            path.hop_count(),
+                            path.latency * 1000.0,
+                            path.gateway().unwrap_or(*source)
+                        );
+                    }
+                }
+                None => println!("❌ {:?}: no downlink path from {}", algorithm, source),
+            }
+        }
+        return;
+    }
+
+    if let Some(source) = matches.get_one::<u32>("route") {
+        let config = network.contact_plan_config().clone();
+        if let Err(error) = network.refresh_contact_plan(&config) {
+            eprintln!("Invalid contact plan: {}", error);
+            return;
+        }
+        println!(
+            "📅 Routing over {} planned contacts (plan version {})",
+            network.contact_graph().len(),
+            network.contact_graph().version()
+        );
+        let now = network.clock().now();
+        let routes = network.usable_routes(*source, GROUND_STATION_ID, DEFAULT_BUNDLE_SIZE, now);
+        if routes.is_empty() {
+            println!("❌ No route from {} to the ground", source);
+        }
+        for route in &routes {
+            println!(
+                "🧭 Route {:?} delivers by {} (expires {}, room for {:.1} MB)",
+                route.nodes(),
+                network.clock().epoch_at(route.best_case_delivery_time),
+                network.clock().epoch_at(route.expiry),
+                route.volume / 8e6
+            );
+            for hop in &route.hops {
+                println!(
+                    "  {} -> {}: {} .. {}",
+                    hop.from,
+                    hop.to,
+                    network.clock().epoch_at(hop.start_time),
+                    network.clock().epoch_at(hop.end_time)
+                );
+            }
+        }
+        return;
+    }
+
+    if matches.get_flag("schedule") {
+        let mut config = SchedulerConfig::default();
+        for entry in matches.get_many::<String>("priority").into_iter().flatten() {
+            match entry
+                .split_once('=')
+                .and_then(|(id, weight)| Some((id.parse().ok()?, weight.parse().ok()?)))
+            {
+                Some((id, weight)) => {
+                    config.priorities.insert(id, weight);
+                }
+                None => {
+                    eprintln!("Invalid priority {:?}, expected id=weight", entry);
+                    return;
+                }
+            }
+        }
+        let clock = network.clock().clone();
+        let schedule = match network.schedule_downlinks(&config) {
+            Ok(schedule) => schedule,
+            Err(error) => {
+                eprintln!("Invalid contact plan: {}", error);
+                return;
+            }
+        };
+        for station in schedule.stations() {
+            println!("📅 Ground station {}:", station);
+            for window in schedule.timeline(station) {
+                println!(
+                    "  {} .. {}: satellite {} ({:.1} kB, priority {})",
+                    clock.epoch_at(window.start),
+                    clock.epoch_at(window.end),
+                    window.satellite,
+                    window.volume / STORAGE_UNIT_BITS,
+                    window.priority
+                );
+            }
+        }
+        let mut satellites: Vec<u32> = schedule.windows().map(|window| window.satellite).collect();
+        satellites.sort();
+        satellites.dedup();
+        for satellite in satellites {
+            println!(
+                "📦 Satellite {}: {:.1} kB scheduled",
+                satellite,
+                schedule.volume_for(satellite) / STORAGE_UNIT_BITS
+            );
+        }
+        println!(
+            "📦 {:.1} kB scheduled for downlink, {:.1} kB weighted by priority",
+            schedule.total_volume() / STORAGE_UNIT_BITS,
+            schedule.weighted_volume() / STORAGE_UNIT_BITS
+        );
+        if !matches.contains_id("run") {
+            return;
+        }
+    }
+
+    // With --schedule the horizon only sets how far ahead downlinks are planned
+    let print_plan = !matches.get_flag("schedule");
+    if let Some(horizon) = matches
+        .get_one::<f64>("contact-plan")
+        .filter(|_| print_plan)
+    {
+        let plan = match network.generate_contact_plan(network.contact_plan_config()) {
+            Ok(plan) => plan,
+            Err(error) => {
+                eprintln!("Invalid contact plan: {}", error);
+                return;
+            }
+        };
+        println!("📅 {} contacts over the next {}s", plan.len(), horizon);
+        for contact in &plan {
+            let grazing = contact
+                .grazing_altitude
+                .map(|altitude| format!(", grazing {:.0} km", altitude / 1000.0))
+                .unwrap_or_default();
+            println!(
+                "  {} -> {}: {} .. {} ({:.0}s, owlt {:.2}ms, {:.2} Mbps{}, margin {:.1} dB{})",
+                contact.source,
+                contact.destination,
+                network.clock().epoch_at(contact.start_time),
+                network.clock().epoch_at(contact.end_time),
+                contact.end_time - contact.start_time,
+                contact.latency * 1000.0,
+                contact.data_rate / 1e6,
+                contact
+                    .link_budget
+                    .modcod
+                    .map(|modcod| format!(" mid-contact {}", modcod.name))
+                    .unwrap_or_default(),
+                contact.link_budget.margin,
+                grazing
+            );
+        }
+        return;
+    }
+
+    match matches.get_one::<f64>("run") {
+        Some(duration) => {
+            let step = *matches.get_one::<f64>("step").unwrap_or(&10.0);
+            if let Some(speed) = matches.get_one::<f64>("real-time") {
+                if let Err(error) = network.clock_mut().real_time(*speed) {
+                    eprintln!("Invalid --real-time: {}", error);
+                    return;
+                }
+            }
+            if let Some(time) = matches.get_one::<f64>("pause-at") {
+                network.add_tick_hook(PauseAt(*time));
+            }
+            if let Some(deadband) = matches.get_one::<f64>("station-keeping") {
+                network.set_station_keeping(Some(StationKeeping {
+                    deadband: *deadband,
+                }));
+            }
+            if let Some(name) = matches.get_one::<String>("dtn") {
+                let copies = *matches
+                    .get_one::<u32>("copies")
+                    .unwrap_or(&DEFAULT_SPRAY_COPIES);
+                network.update_satellite_network();
+                network.set_dtn_mode(DtnMode::from_name(name, copies));
+            }
+            let size = matches
+                .get_one::<f64>("bundle-size")
+                .map(|megabytes| megabytes * 8e6)
+                .unwrap_or(DEFAULT_BUNDLE_SIZE);
+            for source in matches.get_many::<u32>("send").into_iter().flatten() {
+                if let Err(error) = network.send_bundle(*source, GROUND_STATION_ID, size) {
+                    eprintln!("Invalid --send: {}", error);
+                    return;
+                }
+            }
+            if let Err(error) = network.run(*duration, step) {
+                eprintln!("Invalid --run or --step: {}", error);
+                return;
+            }
+
+            let satellites = network.satellites();
+            let count = satellites.len().max(1) as f64;
+            println!(
+                "🔋 Mean charge {:.1}%, {} in sunlight, {} too low to relay",
+                satellites
+                    .values()
+                    .map(|sat| sat.energy_level())
+                    .sum::<f64>()
+                    / count,
+                satellites
+                    .values()
+                    .filter(|sat| sat.power.is_in_sunlight())
+                    .count(),
+                satellites.values().filter(|sat| !sat.can_relay()).count()
+            );
+            if !network.maneuvers().is_empty()
+                || !network.planned_maneuvers().is_empty()
+                || !network.reentries().is_empty()
+            {
+                println!(
+                    "🚀 {} station-keeping burn(s) for {:.2} m/s in total, {} still planned, {} satellite(s) reentered",
+                    network.maneuvers().len(),
+                    network
+                        .maneuvers()
+                        .iter()
+                        .map(|maneuver| maneuver.delta_v)
+                        .sum::<f64>(),
+                    network.planned_maneuvers().len(),
+                    network.reentries().len()
+                );
+            }
+
+            for bundle in network.bundles() {
+                println!(
+                    "📦 Bundle {} from {} at {} ({:.1} MB at offset {:.1} MB of payload {}): {:?}",
+                    bundle.id,
+                    bundle.source,
+                    network.clock().epoch_at(bundle.created_at),
+                    bundle.size / 8e6,
+                    bundle.offset / 8e6,
+                    bundle.payload_id,
+                    bundle.state()
+                );
+                for entry in bundle.log() {
+                    println!(
+                        "  {} @ {}: {:?} ({:?} -> {:?})",
+                        network.clock().epoch_at(entry.time),
+                        entry.node,
+                        entry.event,
+                        entry.from_state,
+                        entry.to_state
+                    );
+                }
+            }
+
+            let mut results = vec![("cgr", network.cgr_stats())];
+            if let (Some(router), Some(stats)) = (network.dtn_router(), network.dtn_stats()) {
+                results.push((router.mode().name(), stats));
+            }
+            for (name, stats) in results {
+                println!(
+                    "📊 {}: delivered {}/{} ({:.0}%), {} transmissions, overhead {}",
+                    name,
+                    stats.delivered,
+                    stats.created,
+                    stats.delivery_ratio() * 100.0,
+                    stats.transmissions,
+                    stats
+                        .overhead_ratio()
+                        .map_or("n/a".to_string(), |ratio| format!("{:.2}", ratio))
+                );
+            }
+        }
+        None => network.update_satellite_network(),
+    }
 
     // let connections: HashMap<u32, Vec<u32>> = find_nearby_satellites(&network.satellites);
 
@@ -43,6 +738,7 @@ async fn main() {
     // }
 
     // UART LISTEN
+    // const BUFFER_SIZE: usize = 1024;
     // let port_name = "/dev/tty.usbmodem2103";
     // let baud_rate = 115_200;
     // let serial_port_builder: SerialPortBuilder = tokio_serial::new(port_name, baud_rate);
@@ -95,5 +791,3 @@ async fn main() {
     //     }
     // });
 }
-
-const BUFFER_SIZE: usize = 1024;
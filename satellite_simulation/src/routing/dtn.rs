@@ -0,0 +1,459 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::simulation::{
+    ground_station::{is_ground_station, reaches, GROUND_STATION_ID},
+    tracking::ConnectionEvent,
+};
+
+/*
+ * Opportunistic DTN routing, for when no contact plan is known in advance and CGR has nothing
+ * to work with. Nodes only learn about each other by meeting: every link that comes up is a
+ * chance to hand over copies of the messages a node carries, following one of
+ *      Epidemic      - copy everything to everyone met (Vahdat & Becker)
+ *      SprayAndWait  - binary spray of a fixed number of copies, then wait to meet the
+ *                      destination directly (Spyropoulos et al.)
+ *      Prophet       - copy to nodes more likely to meet the destination, learnt from the
+ *                      history of encounters (RFC 6693)
+ */
+
+pub const DEFAULT_SPRAY_COPIES: u32 = 8;
+
+// RFC 6693 defaults
+pub const DEFAULT_PROPHET_P_ENCOUNTER: f64 = 0.75;
+pub const DEFAULT_PROPHET_BETA: f64 = 0.25;
+pub const DEFAULT_PROPHET_GAMMA: f64 = 0.98;
+pub const DEFAULT_PROPHET_AGING_UNIT: f64 = 30.0; // seconds of simulation time per aging step
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProphetParams {
+    pub p_encounter: f64, // predictability gained on meeting a node
+    pub beta: f64,        // how much of a predictability carries over transitively
+    pub gamma: f64,       // decay per aging unit of not meeting a node
+    pub aging_unit: f64,
+}
+
+impl Default for ProphetParams {
+    fn default() -> Self {
+        Self {
+            p_encounter: DEFAULT_PROPHET_P_ENCOUNTER,
+            beta: DEFAULT_PROPHET_BETA,
+            gamma: DEFAULT_PROPHET_GAMMA,
+            aging_unit: DEFAULT_PROPHET_AGING_UNIT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DtnMode {
+    Epidemic,
+    SprayAndWait { copies: u32 },
+    Prophet(ProphetParams),
+}
+
+impl DtnMode {
+    pub const NAMES: [&'static str; 3] = ["epidemic", "spray-and-wait", "prophet"];
+
+    // Mode by its CLI name, `copies` is only used by Spray-and-Wait
+    pub fn from_name(name: &str, copies: u32) -> Option<Self> {
+        match name {
+            "epidemic" => Some(DtnMode::Epidemic),
+            "spray-and-wait" => Some(DtnMode::SprayAndWait { copies }),
+            "prophet" => Some(DtnMode::Prophet(ProphetParams::default())),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DtnMode::Epidemic => Self::NAMES[0],
+            DtnMode::SprayAndWait { .. } => Self::NAMES[1],
+            DtnMode::Prophet(_) => Self::NAMES[2],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DtnMessage {
+    pub id: u32,
+    pub source: u32,
+    pub destination: u32,
+    pub size: f64, // bits
+    pub expires_at: f64,
+    pub delivered_at: Option<f64>,
+}
+
+/**
+ * How a routing scheme did over a run. Overhead follows the usual DTN definition of relayed
+ * transmissions that didn't deliver anything per delivered message, so a single-copy scheme
+ * over a three-hop path scores 2.0.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeliveryStats {
+    pub created: usize,
+    pub delivered: usize,
+    pub transmissions: usize,
+}
+
+impl DeliveryStats {
+    pub fn delivery_ratio(&self) -> f64 {
+        if self.created == 0 {
+            return 0.0;
+        }
+        self.delivered as f64 / self.created as f64
+    }
+
+    pub fn overhead_ratio(&self) -> Option<f64> {
+        if self.delivered == 0 {
+            return None;
+        }
+        Some((self.transmissions - self.delivered) as f64 / self.delivered as f64)
+    }
+}
+
+/**
+ * Carries messages between nodes as links come and go. The network feeds it the connection
+ * events it detects on every update and then calls `exchange`, which hands copies over every
+ * link that is up. A message only moves one hop per exchange. Once a message is delivered
+ * every carrier drops its copy, as if the delivery had been acknowledged instantly.
+ */
+#[derive(Debug, Clone)]
+pub struct DtnRouter {
+    mode: DtnMode,
+    messages: HashMap<u32, DtnMessage>,
+    buffers: HashMap<u32, HashMap<u32, u32>>, // node -> message id -> copies it may still hand out
+    links: HashSet<(u32, u32)>,               // links currently up, lower id first
+    predictability: HashMap<u32, HashMap<u32, f64>>, // PRoPHET P(a, b)
+    last_aged: HashMap<u32, f64>,
+    transmissions: usize,
+    last_transfers: Vec<(u32, u32, u32)>, // (from, to, message id) of the latest exchange
+}
+
+impl DtnRouter {
+    pub fn new(mode: DtnMode) -> Self {
+        Self {
+            mode,
+            messages: HashMap::new(),
+            buffers: HashMap::new(),
+            links: HashSet::new(),
+            predictability: HashMap::new(),
+            last_aged: HashMap::new(),
+            transmissions: 0,
+            last_transfers: Vec::new(),
+        }
+    }
+
+    pub fn mode(&self) -> DtnMode {
+        self.mode
+    }
+
+    pub fn message(&self, id: u32) -> Option<&DtnMessage> {
+        self.messages.get(&id)
+    }
+
+    pub fn stats(&self) -> DeliveryStats {
+        DeliveryStats {
+            created: self.messages.len(),
+            delivered: self
+                .messages
+                .values()
+                .filter(|message| message.delivered_at.is_some())
+                .count(),
+            transmissions: self.transmissions,
+        }
+    }
+
+    pub fn send(&mut self, message: DtnMessage) {
+        let copies = match self.mode {
+            DtnMode::SprayAndWait { copies } => copies.max(1),
+            _ => 1,
+        };
+        self.buffers
+            .entry(message.source)
+            .or_default()
+            .insert(message.id, copies);
+        self.messages.insert(message.id, message);
+    }
+
+    pub fn on_connection_event(&mut self, event: &ConnectionEvent) {
+        match *event {
+            ConnectionEvent::Established {
+                time,
+                source,
+                destination,
+            } => {
+                self.links.insert((source, destination));
+                if let DtnMode::Prophet(params) = self.mode {
+                    // Stations all deliver to the same ground, so they share one predictability
+                    let destination = if is_ground_station(destination) {
+                        GROUND_STATION_ID
+                    } else {
+                        destination
+                    };
+                    self.encounter(&params, time, source, destination);
+                }
+            }
+            ConnectionEvent::Lost {
+                source,
+                destination,
+                ..
+            } => {
+                self.links.remove(&(source, destination));
+            }
+        }
+    }
+
+    // Every hand-over the latest exchange made, as (from, to, message id)
+    pub fn last_transfers(&self) -> &[(u32, u32, u32)] {
+        &self.last_transfers
+    }
+
+    /**
+     * Hands copies over every link that is up, deciding against the buffers as they were
+     * when the exchange started. Nodes `accepts_relay` turns down only take messages they
+     * deliver. Returns how many messages were delivered.
+     */
+    pub fn exchange<F: Fn(u32) -> bool>(&mut self, now: f64, accepts_relay: F) -> usize {
+        self.drop_expired(now);
+        self.last_transfers.clear();
+
+        let mut links: Vec<(u32, u32)> = self.links.iter().copied().collect();
+        links.sort();
+        let mut transfers = Vec::new();
+        for (a, b) in links {
+            transfers.extend(self.transfers(now, a, b));
+            transfers.extend(self.transfers(now, b, a));
+        }
+
+        let mut delivered = 0;
+        for (from, to, id) in transfers {
+            let Some(message) = self.messages.get_mut(&id) else {
+                continue;
+            };
+            if message.delivered_at.is_some() {
+                continue;
+            }
+            if reaches(to, message.destination) {
+                self.transmissions += 1;
+                self.last_transfers.push((from, to, id));
+                message.delivered_at = Some(now);
+                delivered += 1;
+                for buffer in self.buffers.values_mut() {
+                    buffer.remove(&id);
+                }
+                continue;
+            }
+            // `to` is turned down, or someone else already handed it a copy this exchange
+            if !accepts_relay(to) || self.buffers.get(&to).is_some_and(|b| b.contains_key(&id)) {
+                continue;
+            }
+            let Some(copies) = self.buffers.get_mut(&from).and_then(|b| b.get_mut(&id)) else {
+                continue;
+            };
+            let handed_over = match self.mode {
+                DtnMode::SprayAndWait { .. } if *copies <= 1 => continue,
+                DtnMode::SprayAndWait { .. } => *copies / 2,
+                _ => 1,
+            };
+            if matches!(self.mode, DtnMode::SprayAndWait { .. }) {
+                *copies -= handed_over;
+            }
+            self.transmissions += 1;
+            self.last_transfers.push((from, to, id));
+            self.buffers.entry(to).or_default().insert(id, handed_over);
+        }
+        delivered
+    }
+
+    // PRoPHET delivery predictability of `from` for `to`, aged up to `now`
+    pub fn predictability(&mut self, now: f64, from: u32, to: u32) -> f64 {
+        if let DtnMode::Prophet(params) = self.mode {
+            self.age(&params, now, from);
+        }
+        self.predictability
+            .get(&from)
+            .and_then(|table| table.get(&to))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    // Messages `from` would hand to `to` over the link between them
+    fn transfers(&mut self, now: f64, from: u32, to: u32) -> Vec<(u32, u32, u32)> {
+        let Some(buffer) = self.buffers.get(&from) else {
+            return Vec::new();
+        };
+        let carried = self.buffers.get(&to);
+        let mut candidates: Vec<(u32, u32)> = buffer
+            .iter()
+            .filter(|(id, _)| carried.is_none_or(|carried| !carried.contains_key(id)))
+            .map(|(id, copies)| (*id, *copies))
+            .collect();
+        candidates.sort();
+
+        let mut transfers = Vec::new();
+        for (id, copies) in candidates {
+            let destination = self.messages[&id].destination;
+            let forward = reaches(to, destination)
+                || match self.mode {
+                    DtnMode::Epidemic => true,
+                    DtnMode::SprayAndWait { .. } => copies > 1,
+                    DtnMode::Prophet(_) => {
+                        let theirs = self.predictability(now, to, destination);
+                        theirs > self.predictability(now, from, destination)
+                    }
+                };
+            if forward {
+                transfers.push((from, to, id));
+            }
+        }
+        transfers
+    }
+
+    fn drop_expired(&mut self, now: f64) {
+        let expired: HashSet<u32> = self
+            .messages
+            .values()
+            .filter(|message| message.delivered_at.is_none() && now >= message.expires_at)
+            .map(|message| message.id)
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+        for buffer in self.buffers.values_mut() {
+            buffer.retain(|id, _| !expired.contains(id));
+        }
+    }
+
+    // Direct update for meeting each other, then the transitive update through each other
+    fn encounter(&mut self, params: &ProphetParams, now: f64, a: u32, b: u32) {
+        self.age(params, now, a);
+        self.age(params, now, b);
+        for (from, to) in [(a, b), (b, a)] {
+            let table = self.predictability.entry(from).or_default();
+            let p = table.entry(to).or_insert(0.0);
+            *p += (1.0 - *p) * params.p_encounter;
+        }
+        for (from, via) in [(a, b), (b, a)] {
+            let p_via = self.predictability[&from][&via];
+            let reachable: Vec<(u32, f64)> = self.predictability[&via]
+                .iter()
+                .filter(|(node, _)| **node != from)
+                .map(|(node, p)| (*node, *p))
+                .collect();
+            let table = self.predictability.entry(from).or_default();
+            for (node, p_node) in reachable {
+                let p = table.entry(node).or_insert(0.0);
+                *p = p.max(p_via * p_node * params.beta);
+            }
+        }
+    }
+
+    fn age(&mut self, params: &ProphetParams, now: f64, node: u32) {
+        let last = *self.last_aged.entry(node).or_insert(now);
+        let steps = ((now - last) / params.aging_unit).floor();
+        if steps < 1.0 {
+            return;
+        }
+        let decay = params.gamma.powf(steps);
+        if let Some(table) = self.predictability.get_mut(&node) {
+            table.values_mut().for_each(|p| *p *= decay);
+        }
+        self.last_aged
+            .insert(node, last + steps * params.aging_unit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u32, source: u32, destination: u32) -> DtnMessage {
+        DtnMessage {
+            id,
+            source,
+            destination,
+            size: 1e6,
+            expires_at: f64::INFINITY,
+            delivered_at: None,
+        }
+    }
+
+    fn connect(router: &mut DtnRouter, time: f64, links: &[(u32, u32)]) {
+        for (a, b) in links {
+            router.on_connection_event(&ConnectionEvent::established(time, *a, *b));
+        }
+    }
+
+    fn disconnect(router: &mut DtnRouter, time: f64, links: &[(u32, u32)]) {
+        for (a, b) in links {
+            router.on_connection_event(&ConnectionEvent::lost(time, *a, *b));
+        }
+    }
+
+    #[test]
+    fn epidemic_moves_one_hop_per_exchange() {
+        let mut router = DtnRouter::new(DtnMode::Epidemic);
+        router.send(message(1, 1, 3));
+        connect(&mut router, 0.0, &[(1, 2), (2, 3)]);
+
+        assert_eq!(router.exchange(0.0, |_| true), 0);
+        assert_eq!(router.last_transfers(), &[(1, 2, 1)]);
+        assert_eq!(router.exchange(1.0, |_| true), 1);
+        assert_eq!(router.last_transfers(), &[(2, 3, 1)]);
+        assert_eq!(router.message(1).unwrap().delivered_at, Some(1.0));
+
+        let stats = router.stats();
+        assert_eq!(stats.delivery_ratio(), 1.0);
+        assert_eq!(stats.overhead_ratio(), Some(1.0));
+    }
+
+    #[test]
+    fn spray_and_wait_halves_its_copies_then_waits_for_the_destination() {
+        let mut router = DtnRouter::new(DtnMode::SprayAndWait { copies: 4 });
+        router.send(message(1, 1, 9));
+        connect(&mut router, 0.0, &[(1, 2), (1, 3), (1, 4), (1, 5)]);
+
+        // 4 copies: 2 go to node 2, 1 to node 3 and the source keeps the last one
+        router.exchange(0.0, |_| true);
+        assert_eq!(router.last_transfers(), &[(1, 2, 1), (1, 3, 1)]);
+
+        // Node 3 holds a single copy, so it only hands it to the destination
+        disconnect(&mut router, 1.0, &[(1, 2), (1, 3), (1, 4), (1, 5)]);
+        connect(&mut router, 1.0, &[(3, 6)]);
+        assert_eq!(router.exchange(1.0, |_| true), 0);
+        assert!(router.last_transfers().is_empty());
+        connect(&mut router, 2.0, &[(3, 9)]);
+        assert_eq!(router.exchange(2.0, |_| true), 1);
+        assert_eq!(router.last_transfers(), &[(3, 9, 1)]);
+    }
+
+    #[test]
+    fn prophet_forwards_towards_nodes_that_met_the_destination() {
+        let params = ProphetParams::default();
+        let mut router = DtnRouter::new(DtnMode::Prophet(params));
+        connect(&mut router, 0.0, &[(1, 2), (2, 3)]);
+        assert_eq!(router.predictability(0.0, 1, 2), params.p_encounter);
+        assert_eq!(router.predictability(0.0, 2, 3), params.p_encounter);
+        // 3 learns of 1 through 2, and forgets it a little every aging unit
+        let transitive = params.p_encounter * params.p_encounter * params.beta;
+        assert_eq!(router.predictability(0.0, 3, 1), transitive);
+        let aged = router.predictability(2.0 * params.aging_unit, 3, 1);
+        assert!((aged - transitive * params.gamma.powi(2)).abs() < 1e-12);
+
+        // Node 4 carries a message for 3: a stranger gets nothing, node 2 gets a copy
+        disconnect(&mut router, 0.0, &[(1, 2), (2, 3)]);
+        router.send(message(1, 4, 3));
+        connect(&mut router, 0.0, &[(4, 5), (2, 4)]);
+        router.exchange(0.0, |_| true);
+        assert_eq!(router.last_transfers(), &[(4, 2, 1)]);
+    }
+
+    #[test]
+    fn any_station_delivers_to_the_ground() {
+        let station = GROUND_STATION_ID - 1;
+        let mut router = DtnRouter::new(DtnMode::Epidemic);
+        router.send(message(1, 1, GROUND_STATION_ID));
+        connect(&mut router, 0.0, &[(1, station)]);
+        assert_eq!(router.exchange(0.0, |_| true), 1);
+        assert_eq!(router.stats().overhead_ratio(), Some(0.0));
+    }
+}
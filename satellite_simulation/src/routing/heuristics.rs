@@ -1,8 +1,11 @@
 use core::f64;
 
-use crate::{common::calculate_euclid_distance, simulation::satellite::Satellite};
+use crate::simulation::{
+    coordinates::Geodetic,
+    satellite::{Satellite, COMMUNICATION_RANGE},
+};
 
-/**
+/*
  * The thing with finding the next best satellite to communicate my information to the ground is based on multiple factors:
  *      1. The satellite's direction, speed, altitude to ground.
  *      2. The satellite's available storage on-board.
@@ -10,31 +13,307 @@ use crate::{common::calculate_euclid_distance, simulation::satellite::Satellite}
  *      4. What else?
  */
 
+pub const DEFAULT_MIN_RELAY_ENERGY: f64 = 60.0; // percent charge, see Satellite::energy_level
+pub const DEFAULT_MIN_RELAY_STORAGE: f64 = 1000.0; // kB free, see Satellite::free_storage
+
+/**
+ * A way of picking which satellite `source` hands its data to on the way to the ground.
+ * Strategies only score candidates, `find_best_relay` does the part they all share: the
+ * source never relays for itself and only satellites it can currently reach, with enough
+ * charge to take on relay duty, are considered.
+ */
+pub trait RelayStrategy {
+    fn name(&self) -> &str;
+
+    // Lower is better, None rules the candidate out altogether
+    fn score(
+        &self,
+        source: &Satellite,
+        candidate: &Satellite,
+        ground_site: &Geodetic,
+    ) -> Option<f64>;
+
+    /**
+     * Returns the id of the best relay satellite for downlink. None when no candidate qualifies,
+     * the source then keeps its data and downlinks it itself on its next pass.
+     */
+    fn find_best_relay(
+        &self,
+        source: &Satellite,
+        satellites: &[&Satellite],
+        ground_site: &Geodetic,
+    ) -> Option<u32> {
+        satellites
+            .iter()
+            .filter(|sat| sat.id != source.id && sat.can_relay() && is_within_range(source, sat))
+            .filter_map(|sat| Some((self.score(source, sat, ground_site)?, sat.id)))
+            .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+            .map(|(_, id)| id)
+    }
+}
+
+/**
+ * The best relay is the satellite that is closer to the ground station than the source, and
+ * the closest one of those wins.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClosestToGround;
+
+impl RelayStrategy for ClosestToGround {
+    fn name(&self) -> &str {
+        "closest-to-ground"
+    }
+
+    fn score(
+        &self,
+        source: &Satellite,
+        candidate: &Satellite,
+        ground_site: &Geodetic,
+    ) -> Option<f64> {
+        let dist_to_ground = slant_range(candidate, ground_site);
+        (dist_to_ground < slant_range(source, ground_site)).then_some(dist_to_ground)
+    }
+}
+
 /**
- * Returns an optional id of the best relay satellite for downlink. If no such relay available, the
- * calling function's satellite would be its own relay. (Define what that would mean in this case.)
+ * Weights for `WeightedScore`. Every term is lower-is-better, and the defaults are the weights
+ * the network used to hard-code.
  */
-pub fn find_best_relay(
-    source: &Satellite,
-    satellites: &Vec<Satellite>,
-    ground_position: (f64, f64),
-) -> Option<u32> {
-    let mut best_relay_satellite_id = None;
-    let mut best_distance = f64::MAX;
-
-    for sat in satellites {
-        if sat.id == source.id {
-            continue;
+#[derive(Debug, Clone, Copy)]
+pub struct RelayWeights {
+    pub distance_to_ground: f64, // per km of slant range to the ground station
+    pub storage: f64,
+    pub energy: f64,
+    pub time_to_downlink: f64,
+    pub communication_window: f64,
+}
+
+impl Default for RelayWeights {
+    fn default() -> Self {
+        Self {
+            distance_to_ground: 1.5,
+            storage: 0.5,
+            energy: 0.3,
+            time_to_downlink: 1.0,
+            communication_window: 0.2,
         }
-        let dist_to_ground = calculate_euclid_distance(&sat.position, &ground_position);
-        let source_to_ground = calculate_euclid_distance(&source.position, &ground_position);
+    }
+}
+
+/**
+ * Blends distance to the ground station, free storage, energy, time to downlink and the
+ * communication window into a single score.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeightedScore {
+    pub weights: RelayWeights,
+}
 
-        // The best relay is the satellite that is closer to the ground station than the source
-        if dist_to_ground < source_to_ground && dist_to_ground < best_distance {
-            best_distance = dist_to_ground;
-            best_relay_satellite_id = Some(sat.id)
+impl RelayStrategy for WeightedScore {
+    fn name(&self) -> &str {
+        "weighted-score"
+    }
+
+    fn score(
+        &self,
+        _source: &Satellite,
+        candidate: &Satellite,
+        ground_site: &Geodetic,
+    ) -> Option<f64> {
+        let distance_to_ground = slant_range(candidate, ground_site) / 1000.0;
+        let storage_score = 1.0 / (candidate.free_storage() + 1.0); // avoids division by zero
+        let energy_avail_score = 1.0 / (candidate.energy_level() + 1.0);
+        let time_to_downlink_score = candidate.time_to_downlink;
+        let communication_window_score = 1.0 / (candidate.communication_window + 1.0);
+
+        Some(
+            (distance_to_ground * self.weights.distance_to_ground)
+                + (storage_score * self.weights.storage)
+                + (energy_avail_score * self.weights.energy)
+                + (time_to_downlink_score * self.weights.time_to_downlink)
+                + (communication_window_score * self.weights.communication_window),
+        )
+    }
+}
+
+/**
+ * Among the satellites that get the data closer to the ground, picks the one with the most
+ * energy to spare. Satellites below `min_energy` are never asked to relay.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct EnergyAware {
+    pub min_energy: f64,
+}
+
+impl Default for EnergyAware {
+    fn default() -> Self {
+        Self {
+            min_energy: DEFAULT_MIN_RELAY_ENERGY,
         }
     }
+}
+
+impl RelayStrategy for EnergyAware {
+    fn name(&self) -> &str {
+        "energy-aware"
+    }
 
-    best_relay_satellite_id
+    fn score(
+        &self,
+        source: &Satellite,
+        candidate: &Satellite,
+        ground_site: &Geodetic,
+    ) -> Option<f64> {
+        let energy = candidate.energy_level();
+        (energy >= self.min_energy && makes_progress(source, candidate, ground_site))
+            .then_some(-energy)
+    }
+}
+
+/**
+ * Among the satellites that get the data closer to the ground, picks the one with the most
+ * free storage, what is left of MAX_ONBOARD_STORAGE once its own queued data is counted.
+ * Satellites with less than `min_free_storage` kB left are never asked to relay.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct StorageAware {
+    pub min_free_storage: f64,
+}
+
+impl Default for StorageAware {
+    fn default() -> Self {
+        Self {
+            min_free_storage: DEFAULT_MIN_RELAY_STORAGE,
+        }
+    }
+}
+
+impl RelayStrategy for StorageAware {
+    fn name(&self) -> &str {
+        "storage-aware"
+    }
+
+    fn score(
+        &self,
+        source: &Satellite,
+        candidate: &Satellite,
+        ground_site: &Geodetic,
+    ) -> Option<f64> {
+        let free_storage = candidate.free_storage();
+        (free_storage >= self.min_free_storage && makes_progress(source, candidate, ground_site))
+            .then_some(-free_storage)
+    }
+}
+
+// Slant range in meters from the ground station, at its own altitude, to the satellite
+fn slant_range(satellite: &Satellite, ground_site: &Geodetic) -> f64 {
+    satellite.ecef().to_aer(ground_site).range
+}
+
+fn makes_progress(source: &Satellite, candidate: &Satellite, ground_site: &Geodetic) -> bool {
+    slant_range(candidate, ground_site) < slant_range(source, ground_site)
+}
+
+fn is_within_range(source: &Satellite, candidate: &Satellite) -> bool {
+    source.eci().distance_to(&candidate.eci()) / 1000.0 <= COMMUNICATION_RANGE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{
+        orbit::OrbitalElements,
+        power::{PowerConfig, PowerSystem},
+        time::Epoch,
+    };
+
+    /*
+     * Satellites strung along one equatorial orbit towards a ground site below the 8° point:
+     * 1 is the source at 0°, 2, 3 and 4 sit at 2°, 4° and 6°, closer to the site each time,
+     * and 5 trails at -2°, within reach of 1 but further from the site.
+     */
+    fn constellation() -> (Vec<Satellite>, Geodetic) {
+        let epoch = Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0);
+        let at = |id: u32, degrees: f64| {
+            let elements = OrbitalElements::circular(550.0, 0.0, 0.0, degrees.to_radians());
+            let mut satellite = Satellite::from_elements(id, elements, &epoch);
+            satellite.storage_on_board = 0.0;
+            satellite
+        };
+        let satellites = vec![at(1, 0.0), at(2, 2.0), at(3, 4.0), at(4, 6.0), at(5, -2.0)];
+        let ground_site = Geodetic {
+            altitude: 0.0,
+            ..at(0, 8.0).geodetic()
+        };
+        (satellites, ground_site)
+    }
+
+    fn best(
+        strategy: &dyn RelayStrategy,
+        satellites: &[Satellite],
+        ground_site: &Geodetic,
+    ) -> Option<u32> {
+        let candidates: Vec<&Satellite> = satellites.iter().collect();
+        strategy.find_best_relay(&satellites[0], &candidates, ground_site)
+    }
+
+    fn charge(satellite: &mut Satellite, state_of_charge: f64) {
+        satellite.power = PowerSystem::new(PowerConfig::default(), state_of_charge);
+    }
+
+    #[test]
+    fn closest_to_ground_picks_the_satellite_nearest_the_site() {
+        let (satellites, ground_site) = constellation();
+        assert_eq!(best(&ClosestToGround, &satellites, &ground_site), Some(4));
+    }
+
+    #[test]
+    fn weighted_score_trades_distance_for_an_earlier_downlink() {
+        let (mut satellites, ground_site) = constellation();
+        assert_eq!(
+            best(&WeightedScore::default(), &satellites, &ground_site),
+            Some(4)
+        );
+
+        // 4 is only ~120 km closer than 3, ten minutes of waiting outweigh that
+        satellites[3].time_to_downlink = 600.0;
+        assert_eq!(
+            best(&WeightedScore::default(), &satellites, &ground_site),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn energy_aware_picks_the_most_charged_satellite_that_makes_progress() {
+        let (mut satellites, ground_site) = constellation();
+        charge(&mut satellites[1], 0.7);
+        charge(&mut satellites[2], 0.95);
+        charge(&mut satellites[3], 0.65);
+        // 5 is fully charged but would carry the data away from the site
+        assert_eq!(
+            best(&EnergyAware::default(), &satellites, &ground_site),
+            Some(3)
+        );
+
+        let strict = EnergyAware { min_energy: 96.0 };
+        assert_eq!(best(&strict, &satellites, &ground_site), None);
+    }
+
+    #[test]
+    fn storage_aware_picks_the_emptiest_satellite_that_makes_progress() {
+        let (mut satellites, ground_site) = constellation();
+        satellites[1].storage_on_board = 1000.0;
+        satellites[2].storage_on_board = 5000.0;
+        satellites[3].storage_on_board = 8000.0;
+        // 5 is empty but would carry the data away from the site
+        assert_eq!(
+            best(&StorageAware::default(), &satellites, &ground_site),
+            Some(2)
+        );
+
+        let strict = StorageAware {
+            min_free_storage: 9500.0,
+        };
+        assert_eq!(best(&strict, &satellites, &ground_site), None);
+    }
 }
@@ -1 +1,3 @@
+pub mod dtn;
 pub mod heuristics;
+pub mod pathfinding;
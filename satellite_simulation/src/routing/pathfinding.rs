@@ -1,8 +1,371 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+};
 
-/**
+use ordered_float::OrderedFloat;
+
+use crate::{
+    common::SPEED_OF_LIGHT,
+    simulation::{
+        coordinates::{WGS84_FLATTENING, WGS84_SEMI_MAJOR_AXIS},
+        ground_station::GroundStation,
+        satellite::Satellite,
+        tracking::Contact,
+    },
+};
+
+/*
  * So, hello Routing. The routing's job is to ensure the best set of satellites is found for the
  * downlink to work properly. In case of failure, the set is produced with multiple options available.
- * 
+ *
+ * Everything here works on the current snapshot graph (`SatelliteNetwork::satellites_network`),
+ * i.e. who can talk to whom right now, as opposed to CGR which plans over future contacts.
+ * A path ends at a gateway: a satellite that some ground station can see at this moment.
+ */
+
+// Node-disjoint alternatives returned next to the primary path unless asked otherwise
+pub const DEFAULT_BACKUP_PATHS: usize = 2;
+// Smallest radius of the WGS-84 ellipsoid, keeps the A* heuristic a true lower bound
+const EARTH_POLAR_RADIUS: f64 = WGS84_SEMI_MAJOR_AXIS * (1.0 - WGS84_FLATTENING);
+// Covers the local vertical elevation is measured from not pointing at the Earth's center (~0.19°)
+const GEODETIC_SLACK: f64 = 0.2 * std::f64::consts::PI / 180.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchAlgorithm {
+    MinHop,     // breadth-first search, fewest inter-satellite hops
+    MinLatency, // Dijkstra on one-way light time
+    AStar,      // Dijkstra guided by the great-circle distance left to the ground station
+}
+
+/**
+ * Satellites from the source to a gateway, source first. `latency` is the summed one-way
+ * light time of the inter-satellite hops in seconds; the downlink itself isn't included.
+ */
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub nodes: Vec<u32>,
+    pub latency: f64,
+}
+
+impl Path {
+    pub fn hop_count(&self) -> usize {
+        self.nodes.len().saturating_sub(1)
+    }
+
+    pub fn gateway(&self) -> Option<u32> {
+        self.nodes.last().copied()
+    }
+}
+
+/**
+ * The path to use plus backups that share no satellite with it or with each other (apart from
+ * the source), so losing any one relay or gateway leaves the backups intact.
+ */
+#[derive(Debug, Clone)]
+pub struct DownlinkPaths {
+    pub primary: Path,
+    pub backups: Vec<Path>,
+}
+
+// Satellites some ground station sees above its elevation mask right now
+pub fn gateways(
+    satellites: &HashMap<u32, Satellite>,
+    ground_stations: &[GroundStation],
+) -> HashSet<u32> {
+    satellites
+        .values()
+        .filter(|sat| ground_stations.iter().any(|station| station.can_see(sat)))
+        .map(|sat| sat.id)
+        .collect()
+}
+
+/**
+ * Primary downlink path from `source` with up to `max_backups` node-disjoint backups. Each
+ * backup is found by searching again with every relay and gateway used so far taken out.
  */
+pub fn find_downlink_paths(
+    graph: &HashMap<u32, Vec<Contact>>,
+    satellites: &HashMap<u32, Satellite>,
+    source: u32,
+    ground_stations: &[GroundStation],
+    algorithm: SearchAlgorithm,
+    max_backups: usize,
+) -> Option<DownlinkPaths> {
+    let goals = gateways(satellites, ground_stations);
+    let mut excluded = HashSet::new();
+    let search = |excluded: &HashSet<u32>| match algorithm {
+        SearchAlgorithm::MinHop => bfs_min_hop(graph, source, &goals, excluded),
+        SearchAlgorithm::MinLatency => dijkstra_min_latency(graph, source, &goals, excluded),
+        SearchAlgorithm::AStar => {
+            a_star(graph, satellites, source, &goals, ground_stations, excluded)
+        }
+    };
+
+    let primary = search(&excluded)?;
+    let mut backups = Vec::new();
+    let mut last = primary.clone();
+    while backups.len() < max_backups && last.hop_count() > 0 {
+        excluded.extend(last.nodes.iter().skip(1));
+        match search(&excluded) {
+            Some(path) => {
+                last = path.clone();
+                backups.push(path);
+            }
+            None => break,
+        }
+    }
+
+    Some(DownlinkPaths { primary, backups })
+}
+
+// Fewest hops from `source` to any gateway, never passing through `excluded`
+pub fn bfs_min_hop(
+    graph: &HashMap<u32, Vec<Contact>>,
+    source: u32,
+    gateways: &HashSet<u32>,
+    excluded: &HashSet<u32>,
+) -> Option<Path> {
+    let mut previous: HashMap<u32, Option<u32>> = HashMap::from([(source, None)]);
+    let mut queue = VecDeque::from([source]);
+
+    while let Some(node) = queue.pop_front() {
+        if gateways.contains(&node) {
+            return Some(build_path(graph, &previous, node));
+        }
+        for contact in graph.get(&node).into_iter().flatten() {
+            let next = contact.destination;
+            if excluded.contains(&next) || previous.contains_key(&next) {
+                continue;
+            }
+            previous.insert(next, Some(node));
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+// Lowest summed light time from `source` to any gateway, never passing through `excluded`
+pub fn dijkstra_min_latency(
+    graph: &HashMap<u32, Vec<Contact>>,
+    source: u32,
+    gateways: &HashSet<u32>,
+    excluded: &HashSet<u32>,
+) -> Option<Path> {
+    best_first(graph, source, gateways, excluded, |_| 0.0)
+}
+
+/**
+ * Dijkstra guided toward the ground, expanding far fewer satellites. The heuristic is the
+ * light time over the shortest straight line that could still separate a satellite from
+ * any gateway: a station only sees satellites within a cone set by its elevation mask and
+ * the highest orbit in the constellation, and outside the Earth a chord over the remaining
+ * central angle is as short as it gets. That bound holds for the current geometry, while
+ * snapshot latencies are the shorter of now and the lookahead, so a path can come out a
+ * fraction of a millisecond slower than Dijkstra's.
+ */
+pub fn a_star(
+    graph: &HashMap<u32, Vec<Contact>>,
+    satellites: &HashMap<u32, Satellite>,
+    source: u32,
+    gateways: &HashSet<u32>,
+    ground_stations: &[GroundStation],
+    excluded: &HashSet<u32>,
+) -> Option<Path> {
+    let highest_orbit = satellites
+        .values()
+        .map(|sat| sat.state.position.norm())
+        .fold(EARTH_POLAR_RADIUS, f64::max);
+    // Central angle out to where a satellite on the highest orbit sits right on the mask
+    let coverage: Vec<(&GroundStation, f64)> = ground_stations
+        .iter()
+        .map(|station| {
+            let mask = station.elevation_mask.max(0.0).to_radians();
+            let angle = (EARTH_POLAR_RADIUS * mask.cos() / highest_orbit).acos() - mask;
+            (station, angle + GEODETIC_SLACK)
+        })
+        .collect();
+
+    best_first(graph, source, gateways, excluded, |node| {
+        let Some(sat) = satellites.get(&node) else {
+            return 0.0;
+        };
+        let position = sat.ecef().0;
+        let remaining = coverage
+            .iter()
+            .map(|(station, angle)| {
+                let site = station.location.to_ecef().0;
+                let cos = position.dot(&site) / (position.norm() * site.norm());
+                cos.clamp(-1.0, 1.0).acos() - angle
+            })
+            .fold(f64::INFINITY, f64::min)
+            .max(0.0);
+        2.0 * EARTH_POLAR_RADIUS * (remaining / 2.0).sin() / 1000.0 / SPEED_OF_LIGHT
+    })
+}
+
+// Dijkstra on contact latency, ordered by cost so far plus `heuristic`
+fn best_first<H: Fn(u32) -> f64>(
+    graph: &HashMap<u32, Vec<Contact>>,
+    source: u32,
+    gateways: &HashSet<u32>,
+    excluded: &HashSet<u32>,
+    heuristic: H,
+) -> Option<Path> {
+    let mut latency: HashMap<u32, f64> = HashMap::from([(source, 0.0)]);
+    let mut previous: HashMap<u32, Option<u32>> = HashMap::from([(source, None)]);
+    let mut settled = HashSet::new();
+    let mut queue = BinaryHeap::from([(Reverse(OrderedFloat(heuristic(source))), source)]);
+
+    while let Some((_, node)) = queue.pop() {
+        if !settled.insert(node) {
+            continue;
+        }
+        if gateways.contains(&node) {
+            return Some(build_path(graph, &previous, node));
+        }
+        for contact in graph.get(&node).into_iter().flatten() {
+            let next = contact.destination;
+            if excluded.contains(&next) || settled.contains(&next) {
+                continue;
+            }
+            let candidate = latency[&node] + contact.latency;
+            if latency.get(&next).is_some_and(|&best| best <= candidate) {
+                continue;
+            }
+            latency.insert(next, candidate);
+            previous.insert(next, Some(node));
+            queue.push((Reverse(OrderedFloat(candidate + heuristic(next))), next));
+        }
+    }
+    None
+}
+
+fn build_path(
+    graph: &HashMap<u32, Vec<Contact>>,
+    previous: &HashMap<u32, Option<u32>>,
+    last: u32,
+) -> Path {
+    let mut nodes = vec![last];
+    while let Some(Some(node)) = previous.get(nodes.last().unwrap_or(&last)) {
+        nodes.push(*node);
+    }
+    nodes.reverse();
+
+    let latency = nodes
+        .windows(2)
+        .filter_map(|pair| {
+            graph
+                .get(&pair[0])?
+                .iter()
+                .find(|contact| contact.destination == pair[1])
+                .map(|contact| contact.latency)
+        })
+        .sum();
+    Path { nodes, latency }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{
+        orbit::OrbitalElements,
+        time::{Epoch, SimClock},
+        tracking::{create_satellites_map, ContactPlanConfig},
+    };
+
+    fn contact(source: u32, destination: u32, latency: f64) -> Contact {
+        Contact {
+            latency,
+            ..Contact::constant(source, destination, 0.0, 60.0, 1e6)
+        }
+    }
+
+    /**
+     * Three ways from 1 to a gateway: straight to 6 over a slow link, through 2 in two hops,
+     * or through 3 and 4 in three short ones.
+     */
+    fn graph() -> HashMap<u32, Vec<Contact>> {
+        let mut graph: HashMap<u32, Vec<Contact>> = HashMap::new();
+        for (source, destination, latency) in [
+            (1, 6, 0.005),
+            (1, 2, 0.001),
+            (2, 5, 0.001),
+            (1, 3, 0.0002),
+            (3, 4, 0.0002),
+            (4, 5, 0.0002),
+        ] {
+            graph
+                .entry(source)
+                .or_default()
+                .push(contact(source, destination, latency));
+        }
+        graph
+    }
+
+    #[test]
+    fn bfs_takes_the_fewest_hops() {
+        let gateways = HashSet::from([5, 6]);
+        let path = bfs_min_hop(&graph(), 1, &gateways, &HashSet::new()).unwrap();
+        assert_eq!(path.nodes, [1, 6]);
+        assert!((path.latency - 0.005).abs() < 1e-12);
+
+        let path = bfs_min_hop(&graph(), 1, &gateways, &HashSet::from([6])).unwrap();
+        assert_eq!(path.nodes, [1, 2, 5]);
+        assert!(bfs_min_hop(&graph(), 1, &gateways, &HashSet::from([5, 6])).is_none());
+    }
+
+    #[test]
+    fn dijkstra_takes_the_lowest_latency() {
+        let gateways = HashSet::from([5, 6]);
+        let path = dijkstra_min_latency(&graph(), 1, &gateways, &HashSet::new()).unwrap();
+        assert_eq!(path.nodes, [1, 3, 4, 5]);
+        assert_eq!(path.hop_count(), 3);
+        assert_eq!(path.gateway(), Some(5));
+        assert!((path.latency - 0.0006).abs() < 1e-12);
+
+        let path = dijkstra_min_latency(&graph(), 1, &gateways, &HashSet::from([4])).unwrap();
+        assert_eq!(path.nodes, [1, 2, 5]);
+    }
+
+    #[test]
+    fn a_star_agrees_with_dijkstra_over_a_real_constellation() {
+        let epoch = Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0);
+        let clock = SimClock::new(epoch, 10.0);
+        // A string of satellites 5° apart along one orbit, the last one over the station
+        let satellites: HashMap<u32, Satellite> = (1..=6)
+            .map(|id| {
+                let elements = OrbitalElements::circular(
+                    550.0,
+                    53.0_f64.to_radians(),
+                    0.0,
+                    (5.0 * id as f64).to_radians(),
+                );
+                (id, Satellite::from_elements(id, elements, &epoch))
+            })
+            .collect();
+        let (latitude, longitude) = satellites[&6].position;
+        let stations = [GroundStation::new("below 6", latitude, longitude, 0.0)];
+        let graph =
+            create_satellites_map(&satellites, &clock, &ContactPlanConfig::default()).unwrap();
+        let goals = gateways(&satellites, &stations);
+        assert!(goals.contains(&6) && !goals.contains(&1));
+
+        let dijkstra = dijkstra_min_latency(&graph, 1, &goals, &HashSet::new()).unwrap();
+        assert!(dijkstra.hop_count() >= 2);
+        let a_star = a_star(&graph, &satellites, 1, &goals, &stations, &HashSet::new()).unwrap();
+        assert_eq!(a_star.nodes, dijkstra.nodes);
+        assert!((a_star.latency - dijkstra.latency).abs() < 1e-9);
 
- 
\ No newline at end of file
+        // Every relay and gateway the primary uses is off limits to the backups
+        let paths =
+            find_downlink_paths(&graph, &satellites, 1, &stations, SearchAlgorithm::AStar, 2)
+                .unwrap();
+        assert_eq!(paths.primary.nodes, a_star.nodes);
+        for backup in &paths.backups {
+            assert!(backup.nodes[1..]
+                .iter()
+                .all(|node| !paths.primary.nodes.contains(node)));
+        }
+    }
+}
@@ -9,7 +9,6 @@ use chacha20poly1305::{
     aead::{Aead, KeyInit, Payload},
     AeadCore, Key, XChaCha20Poly1305, XNonce,
 };
-use rand::Rng;
 
 /**
  * Ensures high security (resistant to brute-force).
@@ -21,13 +20,6 @@ pub const XCHACHA20_POLY1305_KEY_SIZE: usize = 32;
  */
 pub const XCHACHA20_POLY1305_NONCE_SIZE: usize = 24;
 
-// For testing only rn
-pub fn generate_encryption_key() -> [u8; XCHACHA20_POLY1305_KEY_SIZE] {
-    let mut key = [0u8; XCHACHA20_POLY1305_KEY_SIZE];
-    rand::thread_rng().fill(&mut key);
-    key
-}
-
 /**
  * Encrypt the message with optional Metadata AAD (additional associated data).
  */
@@ -36,6 +28,10 @@ pub fn encrypt_message(
     key: &[u8],
     associated_data: Option<&[u8]>,
 ) -> Result<(Vec<u8>, [u8; XCHACHA20_POLY1305_NONCE_SIZE]), &'static str> {
+    // Key::from_slice panics on anything but a full-size key
+    if key.len() != XCHACHA20_POLY1305_KEY_SIZE {
+        return Err("Invalid encryption key size.");
+    }
     let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
     let nonce = XChaCha20Poly1305::generate_nonce(&mut rand::thread_rng()); // 24-byte nonce
 
@@ -55,6 +51,9 @@ pub fn decrypt_message(
     key: &[u8],
     associated_data: Option<&[u8]>,
 ) -> Result<String, &'static str> {
+    if key.len() != XCHACHA20_POLY1305_KEY_SIZE {
+        return Err("Invalid encryption key size.");
+    }
     let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
     let payload = Payload {
         msg: ciphertext,
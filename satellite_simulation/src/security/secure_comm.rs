@@ -57,11 +57,31 @@ pub fn verify_and_decrypt(
     let ciphertext: &[u8] = signed_encrypted_msg.ciphertext.as_slice();
     let nonce = signed_encrypted_msg.nonce;
     match signature::verify_signature(sender_verifying_key, ciphertext, &sender_signature) {
-        Ok(_) => Ok(
-            encryption::decrypt_message(ciphertext, &nonce, &encryption_key, None)
-                .map_err(|_| "Error decrypting message.")
-                .unwrap(),
-        ),
+        Ok(_) => encryption::decrypt_message(ciphertext, &nonce, &encryption_key, None)
+            .map_err(|_| "Error decrypting message."),
         Err(_) => Err("Error verifying signature."),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_receiver_opens_a_sealed_message() {
+        let (mut sender_key, sender_identity) = signature::generate_identity_keypair();
+        let (receiver_secret, receiver_public) = key_exchange::generate_keypair();
+        let (relay_secret, _) = key_exchange::generate_keypair();
+        let sealed = encrypt_and_sign("telemetry", &mut sender_key, &receiver_public).unwrap();
+
+        assert!(verify_and_decrypt(&sealed, &sender_identity, &relay_secret).is_err());
+        assert_eq!(
+            verify_and_decrypt(&sealed, &sender_identity, &receiver_secret).unwrap(),
+            "telemetry"
+        );
+
+        // Checked against anyone but the sender, the signature fails
+        let (_, impostor) = signature::generate_identity_keypair();
+        assert!(verify_and_decrypt(&sealed, &impostor, &receiver_secret).is_err());
+    }
+}
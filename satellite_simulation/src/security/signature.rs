@@ -6,17 +6,17 @@ use rand::rngs::OsRng;
 
 /**
 *  pub struct SigningKey {
-       // The secret half of this signing key.
-       pub(crate) secret_key: SecretKey,
-       // The public half of this signing key.
-       pub(crate) verifying_key: VerifyingKey,
-   }
+*      // The secret half of this signing key.
+*      pub(crate) secret_key: SecretKey,
+*      // The public half of this signing key.
+*      pub(crate) verifying_key: VerifyingKey,
+*  }
 */
 pub fn generate_identity_keypair() -> (SigningKey, VerifyingKey) {
     let mut csprng = OsRng;
     let signing_key: SigningKey = SigningKey::generate(&mut csprng);
     let verifying_key: VerifyingKey = signing_key.verifying_key(); // getting public part of the key
-    return (signing_key, verifying_key);
+    (signing_key, verifying_key)
 }
 
 /// Sign the message using ED25519
@@ -1,17 +1,164 @@
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
 
-use blake2::digest::generic_array::arr;
 use ordered_float::OrderedFloat;
 
+use super::{
+    ground_station::reaches,
+    time::SimClock,
+    tracking::{Contact, ContactGraph},
+};
+
+pub const DEFAULT_BUNDLE_SIZE: f64 = 8_000_000.0; // bits (1 MB)
+pub const DEFAULT_ROUTE_COUNT: usize = 3; // routes kept per destination in a route table
+pub const DEFAULT_BUNDLE_TTL: f64 = 24.0 * 3600.0; // seconds a bundle may wait before it fails
+pub const MIN_FRAGMENT_SIZE: f64 = 1_000_000.0; // bits, smaller leftovers wait for more capacity
+
+#[derive(Debug, Clone)]
+pub struct CommunicationLink {
+    pub from: usize,          // The satellite (node) initiating the contact (sender)
+    pub to: usize,            // The satellite (node) receiving the contact (receiver)
+    pub start_time: f64, // The earliest time this communication link is available (sim seconds)
+    pub end_time: f64,   // The latest time this contact is available (sim seconds)
+    pub latency: f64,    // The time delay for data transmission over this link
+    pub bandwidth: f64,  // The transmission rate of the link in bits per second
+    pub residual_volume: f64, // Bits that can still be booked on this contact
+    pub backlog: f64,    // Bits already queued for this contact ahead of any new bundle
+}
+
+impl CommunicationLink {
+    pub fn new(
+        from: usize,
+        to: usize,
+        start_time: f64,
+        end_time: f64,
+        latency: f64,
+        bandwidth: f64,
+    ) -> Self {
+        Self {
+            from,
+            to,
+            start_time,
+            end_time,
+            latency,
+            bandwidth,
+            residual_volume: bandwidth * (end_time - start_time).max(0.0),
+            backlog: 0.0,
+        }
+    }
+
+    // Accounts for `backlog` bits that are already queued for this contact
+    pub fn with_backlog(mut self, backlog: f64) -> Self {
+        self.backlog = backlog;
+        self.residual_volume = (self.residual_volume - backlog).max(0.0);
+        self
+    }
+
+    fn transmission_time(&self, volume: f64) -> f64 {
+        if self.bandwidth > 0.0 {
+            volume / self.bandwidth
+        } else {
+            0.0
+        }
+    }
+
+    /**
+     * Earliest time a bundle ready at `ready_time` can start going out, i.e. once the contact
+     * is open and everything queued ahead of it has been sent.
+     */
+    pub fn earliest_transmission(&self, ready_time: f64) -> f64 {
+        ready_time.max(self.start_time + self.transmission_time(self.backlog))
+    }
+
+    /**
+     * Best-case arrival time at `to` for a bundle of `bundle_size` bits that is ready at `from`
+     * at `ready_time`: it goes out at its earliest transmission opportunity and lands one
+     * transmission time plus one OWLT later. Returns None when the contact is already over,
+     * has no residual volume left for the bundle, or closes before the last bit is sent.
+     */
+    pub fn arrival_time(&self, ready_time: f64, bundle_size: f64) -> Option<f64> {
+        if self.end_time <= ready_time || self.residual_volume < bundle_size {
+            return None;
+        }
+        let departure = self.earliest_transmission(ready_time);
+        let transmission = self.transmission_time(bundle_size);
+        if departure + transmission > self.end_time {
+            return None;
+        }
+        Some(departure + transmission + self.latency)
+    }
+}
+
+impl From<&Contact> for CommunicationLink {
+    fn from(contact: &Contact) -> Self {
+        Self::new(
+            contact.source as usize,
+            contact.destination as usize,
+            contact.start_time,
+            contact.end_time,
+            contact.latency,
+            contact.data_rate,
+        )
+    }
+}
+
+/**
+ * The outcome of a CGR search. `hops` are the contacts in the order the bundle uses them,
+ * `best_case_delivery_time` assumes no queueing anywhere along the way, and `expiry` is the
+ * end of the earliest-closing contact, after which the route can no longer be followed.
+ * `volume` is the smallest residual volume along the way, i.e. the most the route can carry.
+ */
 #[derive(Debug, Clone)]
-struct CommunicationLink {
-    from: usize,     // The satellite (node) initiating the contact (sender)
-    to: usize,       // The satellite (node) receiving the contact (receiver)
-    start_time: f64, // The earliest time this communication link is available
-    end_time: f64,   // The latest time this contact is available
-    latency: f64,    // The time delay for data transmission over this link
-    bandwidth: f64,  // The capacity of the link (not used in shortest path search)
+pub struct Route {
+    pub hops: Vec<CommunicationLink>,
+    pub best_case_delivery_time: f64,
+    pub expiry: f64,
+    pub volume: f64,
+}
+
+impl Route {
+    // Every node the bundle passes through, source and destination included
+    pub fn nodes(&self) -> Vec<usize> {
+        let mut nodes: Vec<usize> = self.hops.first().map(|hop| hop.from).into_iter().collect();
+        nodes.extend(self.hops.iter().map(|hop| hop.to));
+        nodes
+    }
+}
+
+/**
+ * A node's cache of the best routes to each destination it has sent to. The table is only
+ * valid for the contact plan version it was built against; once the plan changes every
+ * entry has to be recomputed.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct RouteTable {
+    plan_version: u64,
+    routes: HashMap<usize, Vec<Route>>,
+}
+
+impl RouteTable {
+    pub fn new(plan_version: u64) -> Self {
+        Self {
+            plan_version,
+            routes: HashMap::new(),
+        }
+    }
+
+    pub fn is_valid_for(&self, plan_version: u64) -> bool {
+        self.plan_version == plan_version
+    }
+
+    // Cached routes to `destination`, best first
+    pub fn routes_to(&self, destination: usize) -> Option<&[Route]> {
+        self.routes
+            .get(&destination)
+            .map(|routes| routes.as_slice())
+    }
+
+    pub fn insert(&mut self, destination: usize, routes: Vec<Route>) {
+        self.routes.insert(destination, routes);
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -19,6 +166,7 @@ struct RouteNode {
     id: usize,                       // Current satellite (node) in the route
     arrival_time: OrderedFloat<f64>, // The earliest time data can arrive at this node
     path: Vec<usize>,                // The sequence of satellites visited so far in this route
+    hops: Vec<usize>,                // Indices of the communication links taken to get here
 }
 
 /**
@@ -36,8 +184,9 @@ impl PartialOrd for RouteNode {
     }
 }
 
-#[derive(Clone, Debug)]
-enum CGRState {
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum CGRState {
     IDLE,
     DiscoverCommunicationLinks,
     SelectRoute,
@@ -48,15 +197,15 @@ enum CGRState {
     Failed,
 }
 
-struct CGR {
+#[allow(clippy::upper_case_acronyms)]
+pub struct CGR {
     communication_links: Vec<CommunicationLink>,
-    // Map a Sat Id to a list of communication links it points to
-    adjacency_list: HashMap<usize, Vec<CommunicationLink>>,
-    // The state within the Constrained Geographic Routing Algorithm State Machine
-    state: CGRState,
+    // Map a Sat Id to the indices of the communication links it points to
+    adjacency_list: HashMap<usize, Vec<usize>>,
 }
 
-enum CGREvent {
+#[derive(Clone, Debug, PartialEq)]
+pub enum CGREvent {
     NewPacketArrived,
     CommunicationLinksAvailable,
     NoCommunicationLinksAvailable,
@@ -64,27 +213,182 @@ enum CGREvent {
     CommunicationLinkLost,
     CommunicationLinkRestored,
     Timeout,
+    HopCompleted, // reached an intermediate node, carries on along the same route
+    Fragmented,   // too big for any one route, part of the payload was split off
     DataSent,
 }
 
-impl CGR {
-    fn new(communication_links: Vec<CommunicationLink>) -> Self {
-        let mut adjacency_list = HashMap::new();
-        for communication_link in &communication_links {
-            adjacency_list
-                .entry(communication_link.from)
-                .or_insert_with(Vec::new)
-                .push(communication_link.clone());
+// One line of a bundle's history: what happened, where, and the transition it caused
+#[derive(Clone, Debug)]
+pub struct BundleLogEntry {
+    pub time: f64,
+    pub node: usize,
+    pub event: CGREvent,
+    pub from_state: CGRState,
+    pub to_state: CGRState,
+}
+
+/**
+ * Why a bundle couldn't be sent: its size has to be a positive, finite number of bits, it has
+ * to start at a satellite the network knows and go to a node it knows (or any station).
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BundleError {
+    Size(f64),
+    UnknownSource(u32),
+    UnknownDestination(u32),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::Size(size) => write!(
+                f,
+                "bundle size must be a positive, finite number of bits, got {}",
+                size
+            ),
+            BundleError::UnknownSource(id) => write!(f, "no satellite {} to send from", id),
+            BundleError::UnknownDestination(id) => write!(f, "no node {} to send to", id),
         }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+/**
+ * A bundle in flight, carrying its own instance of the CGR state machine and a log of every
+ * event it went through. The network drives it (see `SatelliteNetwork::forward_bundles`);
+ * the bundle itself only knows where it is, which route it follows and when its current hop
+ * finishes. Sizes are in bits, times in simulation seconds.
+ */
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    pub id: u32,
+    pub payload_id: u32, // id of the bundle this one was fragmented from, or its own id
+    pub offset: f64,     // where this fragment starts within the original payload, in bits
+    pub source: usize,
+    pub destination: usize,
+    pub size: f64,
+    pub created_at: f64,
+    pub expires_at: f64,
+    pub current_node: usize,
+    pub ready_at: f64, // when the bundle became available for sending at `current_node`
+    pub route: Option<Route>,
+    pub hop_index: usize,
+    // (departure, end of transmission) of the hop currently being sent
+    pub hop_window: Option<(f64, f64)>,
+    state: CGRState,
+    log: Vec<BundleLogEntry>,
+}
+
+impl Bundle {
+    pub fn new(
+        id: u32,
+        source: usize,
+        destination: usize,
+        size: f64,
+        created_at: f64,
+        ttl: f64,
+    ) -> Self {
         Self {
-            communication_links,
-            adjacency_list,
+            id,
+            payload_id: id,
+            offset: 0.0,
+            source,
+            destination,
+            size,
+            created_at,
+            expires_at: created_at + ttl,
+            current_node: source,
+            ready_at: created_at,
+            route: None,
+            hop_index: 0,
+            hop_window: None,
             state: CGRState::IDLE,
+            log: Vec::new(),
         }
     }
 
-    fn transition(&mut self, event: &CGREvent) {
-        self.state = match (self.state.clone(), event) {
+    pub fn state(&self) -> &CGRState {
+        &self.state
+    }
+
+    pub fn log(&self) -> &[BundleLogEntry] {
+        &self.log
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, CGRState::Delivered | CGRState::Failed)
+    }
+
+    /**
+     * Cuts this bundle down to `size` bits and returns the rest of the payload as a new
+     * fragment with id `id`, waiting at the same node. The fragment starts out idle with an
+     * empty log.
+     */
+    pub fn split(&mut self, id: u32, size: f64) -> Bundle {
+        let remainder = Bundle {
+            id,
+            size: self.size - size,
+            offset: self.offset + size,
+            route: None,
+            hop_index: 0,
+            hop_window: None,
+            state: CGRState::IDLE,
+            log: Vec::new(),
+            ..self.clone()
+        };
+        self.size = size;
+        remainder
+    }
+
+    pub fn current_hop(&self) -> Option<&CommunicationLink> {
+        self.route
+            .as_ref()
+            .and_then(|route| route.hops.get(self.hop_index))
+    }
+
+    // When the last bit of the current hop lands at the receiver
+    pub fn hop_arrival(&self) -> Option<f64> {
+        let (_, transmitted) = self.hop_window?;
+        Some(transmitted + self.current_hop()?.latency)
+    }
+
+    /**
+     * Puts the bundle on `route` and starts sending over its first contact as soon as both
+     * the bundle and the contact are ready.
+     */
+    pub fn follow(&mut self, route: Route) {
+        self.route = Some(route);
+        self.hop_index = 0;
+        self.begin_hop();
+    }
+
+    /**
+     * Hands the bundle over to the receiver of the current hop once it has fully arrived.
+     * Returns false if it is still on its way at `time`.
+     */
+    pub fn complete_hop(&mut self, time: f64) -> bool {
+        let (Some(arrival), Some(hop)) = (self.hop_arrival(), self.current_hop()) else {
+            return false;
+        };
+        if arrival > time {
+            return false;
+        }
+        self.current_node = hop.to;
+        self.ready_at = arrival;
+        self.hop_index += 1;
+        self.begin_hop();
+        true
+    }
+
+    /**
+     * Feeds `event` to this bundle's state machine and records the transition, at `time`,
+     * in the bundle's log.
+     */
+    pub fn transition(&mut self, time: f64, event: CGREvent) {
+        let from_state = self.state.clone();
+        self.state = match (self.state.clone(), &event) {
             (CGRState::IDLE, CGREvent::NewPacketArrived) => CGRState::DiscoverCommunicationLinks,
 
             (CGRState::DiscoverCommunicationLinks, CGREvent::CommunicationLinksAvailable) => {
@@ -96,31 +400,413 @@ impl CGR {
 
             (CGRState::SelectRoute, CGREvent::RouteComputed) => CGRState::TransmitData,
             // the route unavailable state **
+            (CGRState::TransmitData, CGREvent::HopCompleted) => CGRState::TransmitData,
             (CGRState::TransmitData, CGREvent::DataSent) => CGRState::Delivered,
             (CGRState::TransmitData, CGREvent::CommunicationLinkLost) => CGRState::HoldData,
 
             (CGRState::HoldData, CGREvent::Timeout) => CGRState::Failed,
             (CGRState::HoldData, CGREvent::CommunicationLinkRestored) => CGRState::Retransmit,
 
+            (CGRState::Retransmit, CGREvent::HopCompleted) => CGRState::TransmitData,
             (CGRState::Retransmit, CGREvent::DataSent) => CGRState::Delivered,
+            (CGRState::Retransmit, CGREvent::CommunicationLinkLost) => CGRState::HoldData,
 
             _ => self.state.clone(), // for now the default state is to remain in current state
+        };
+        self.log.push(BundleLogEntry {
+            time,
+            node: self.current_node,
+            event,
+            from_state,
+            to_state: self.state.clone(),
+        });
+    }
+
+    fn begin_hop(&mut self) {
+        self.hop_window = self.current_hop().map(|hop| {
+            let departure = hop.earliest_transmission(self.ready_at);
+            (departure, departure + hop.transmission_time(self.size))
+        });
+    }
+}
+
+impl CGR {
+    pub fn new(communication_links: Vec<CommunicationLink>) -> Self {
+        let mut adjacency_list = HashMap::new();
+        for (index, communication_link) in communication_links.iter().enumerate() {
+            adjacency_list
+                .entry(communication_link.from)
+                .or_insert_with(Vec::new)
+                .push(index);
+        }
+        Self {
+            communication_links,
+            adjacency_list,
         }
     }
 
-    fn find_best_route(
-        &mut self,
+    /**
+     * Builds the router's view of the predicted contact plan, with whatever is already booked
+     * on each contact taken off its volume. Links are ordered by start time so ties between
+     * equally good routes always resolve the same way.
+     */
+    pub fn from_contact_graph(contact_graph: &ContactGraph) -> Self {
+        let mut communication_links: Vec<CommunicationLink> = contact_graph
+            .contacts
+            .values()
+            .flat_map(|destinations| destinations.values().flatten())
+            .map(|contact| {
+                CommunicationLink::from(contact).with_backlog(contact_graph.booked_volume(contact))
+            })
+            .collect();
+        communication_links.sort_by(|a, b| {
+            a.start_time
+                .total_cmp(&b.start_time)
+                .then(a.from.cmp(&b.from))
+                .then(a.to.cmp(&b.to))
+        });
+        Self::new(communication_links)
+    }
+
+    /**
+     * Earliest-arrival route over the contact plan for a bundle of `bundle_size` bits leaving
+     * `source_satellite` now. Routes are searched from the current simulation time, so every
+     * arrival time along the route is an absolute timestamp on the shared clock.
+     */
+    pub fn find_best_route(
+        &self,
+        clock: &SimClock,
         source_satellite: usize,
         destination_satellite: usize,
-        arrival_time: f64,
-    ) -> Vec<usize> {
-        // define a Path type on the return
-        let mut queue = BinaryHeap::new();
-        let mut path_so_far = Vec::new().push(source_satellite);
-        queue.push((
+        bundle_size: f64,
+    ) -> Option<Route> {
+        self.search(
+            clock.now(),
             source_satellite,
-            OrderedFloat::from(arrival_time),
-            path_so_far,
-        ));
+            destination_satellite,
+            bundle_size,
+            &HashSet::new(),
+        )
+        .and_then(|node| self.build_route(&node))
+    }
+
+    /**
+     * Up to `k` contact-disjoint routes, best first, by iterative contact suppression: each
+     * round runs the earliest-arrival search again with every contact the routes found so far
+     * use taken out, and stops early once no route is left. The alternates are what a bundle
+     * falls back on when its route breaks mid-transmission.
+     */
+    pub fn find_k_best_routes(
+        &self,
+        clock: &SimClock,
+        source_satellite: usize,
+        destination_satellite: usize,
+        bundle_size: f64,
+        k: usize,
+    ) -> Vec<Route> {
+        let mut routes = Vec::new();
+        let mut suppressed = HashSet::new();
+        while routes.len() < k {
+            let Some(node) = self.search(
+                clock.now(),
+                source_satellite,
+                destination_satellite,
+                bundle_size,
+                &suppressed,
+            ) else {
+                break;
+            };
+            let Some(route) = self.build_route(&node) else {
+                break;
+            };
+            suppressed.extend(node.hops);
+            routes.push(route);
+        }
+        routes
+    }
+
+    /**
+     * Earliest-arrival Dijkstra over the contact plan from `source_satellite` at `start_time`,
+     * never using the `suppressed` links. Arrival times are absolute timestamps on the shared
+     * clock. A contact is only usable if it is still open when the bundle gets to its sender,
+     * has enough residual volume for the whole bundle and stays open long enough to send it;
+     * the one-way light time is added on every hop.
+     */
+    fn search(
+        &self,
+        start_time: f64,
+        source_satellite: usize,
+        destination_satellite: usize,
+        bundle_size: f64,
+        suppressed: &HashSet<usize>,
+    ) -> Option<RouteNode> {
+        let mut best_arrival: HashMap<usize, f64> = HashMap::from([(source_satellite, start_time)]);
+        let mut queue = BinaryHeap::new();
+        queue.push(RouteNode {
+            id: source_satellite,
+            arrival_time: OrderedFloat::from(start_time),
+            path: vec![source_satellite],
+            hops: Vec::new(),
+        });
+
+        while let Some(node) = queue.pop() {
+            if reaches(node.id as u32, destination_satellite as u32) {
+                return Some(node);
+            }
+            // A better way to this node was already expanded
+            if best_arrival
+                .get(&node.id)
+                .is_some_and(|&best| node.arrival_time.0 > best)
+            {
+                continue;
+            }
+
+            let Some(links) = self.adjacency_list.get(&node.id) else {
+                continue;
+            };
+            for &index in links {
+                let link = &self.communication_links[index];
+                if suppressed.contains(&index) || node.path.contains(&link.to) {
+                    continue;
+                }
+                let Some(arrival) = link.arrival_time(node.arrival_time.0, bundle_size) else {
+                    continue;
+                };
+                if best_arrival
+                    .get(&link.to)
+                    .is_some_and(|&best| best <= arrival)
+                {
+                    continue;
+                }
+
+                best_arrival.insert(link.to, arrival);
+                let mut path = node.path.clone();
+                path.push(link.to);
+                let mut hops = node.hops.clone();
+                hops.push(index);
+                queue.push(RouteNode {
+                    id: link.to,
+                    arrival_time: OrderedFloat::from(arrival),
+                    path,
+                    hops,
+                });
+            }
+        }
+
+        None
+    }
+
+    fn build_route(&self, node: &RouteNode) -> Option<Route> {
+        if node.hops.is_empty() {
+            return None;
+        }
+        let hops: Vec<CommunicationLink> = node
+            .hops
+            .iter()
+            .map(|&index| self.communication_links[index].clone())
+            .collect();
+        let expiry = hops
+            .iter()
+            .map(|hop| hop.end_time)
+            .fold(f64::INFINITY, f64::min);
+        let volume = hops
+            .iter()
+            .map(|hop| hop.residual_volume)
+            .fold(f64::INFINITY, f64::min);
+
+        Some(Route {
+            hops,
+            best_case_delivery_time: node.arrival_time.0,
+            expiry,
+            volume,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{time::Epoch, tracking::Booking};
+
+    const MEGABIT: f64 = 1e6;
+
+    // 1 Mbps with a 10 ms light time, so a megabit takes 1 s to send and lands 10 ms later
+    fn link(from: usize, to: usize, start_time: f64, end_time: f64) -> CommunicationLink {
+        CommunicationLink::new(from, to, start_time, end_time, 0.01, MEGABIT)
+    }
+
+    fn clock() -> SimClock {
+        SimClock::new(Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0), 10.0)
+    }
+
+    // 1 -> 2 -> 3 early on, or 1 -> 3 directly much later
+    fn router() -> CGR {
+        CGR::new(vec![
+            link(1, 2, 0.0, 100.0),
+            link(2, 3, 50.0, 200.0),
+            link(1, 3, 300.0, 400.0),
+        ])
+    }
+
+    #[test]
+    fn dijkstra_finds_the_earliest_arrival() {
+        let routes = router().find_k_best_routes(&clock(), 1, 3, MEGABIT, 1);
+        let route = &routes[0];
+        assert_eq!(route.nodes(), [1, 2, 3]);
+        // Waits at 2 for the second contact to open, then 1 s on the air and 10 ms in flight
+        assert!((route.best_case_delivery_time - 51.01).abs() < 1e-9);
+        assert_eq!(route.expiry, 100.0);
+        assert_eq!(route.volume, 100.0 * MEGABIT);
+    }
+
+    #[test]
+    fn dijkstra_skips_contacts_that_close_mid_transmission() {
+        // The relay's contact closes half a second after it gets the bundle
+        let router = CGR::new(vec![
+            link(1, 2, 0.0, 100.0),
+            link(2, 3, 0.0, 1.5),
+            link(1, 3, 300.0, 400.0),
+        ]);
+        let routes = router.find_k_best_routes(&clock(), 1, 3, MEGABIT, 1);
+        assert_eq!(routes[0].nodes(), [1, 3]);
+        assert!((routes[0].best_case_delivery_time - 301.01).abs() < 1e-9);
+
+        assert!(router
+            .find_k_best_routes(&clock(), 1, 3, 500.0 * MEGABIT, 1)
+            .is_empty());
+    }
+
+    #[test]
+    fn k_best_routes_are_contact_disjoint_and_ordered() {
+        let routes = router().find_k_best_routes(&clock(), 1, 3, MEGABIT, 3);
+        let nodes: Vec<Vec<usize>> = routes.iter().map(Route::nodes).collect();
+        assert_eq!(nodes, [vec![1, 2, 3], vec![1, 3]]);
+        assert!(routes[0].best_case_delivery_time < routes[1].best_case_delivery_time);
+    }
+
+    #[test]
+    fn route_tables_only_hold_for_their_plan_version() {
+        let mut table = RouteTable::new(7);
+        assert!(table.routes_to(3).is_none());
+        table.insert(3, router().find_k_best_routes(&clock(), 1, 3, MEGABIT, 2));
+        assert_eq!(table.routes_to(3).map(<[Route]>::len), Some(2));
+        assert!(table.is_valid_for(7));
+        assert!(!table.is_valid_for(8));
+    }
+
+    #[test]
+    fn bundle_walks_its_route_through_the_state_machine() {
+        let mut bundle = Bundle::new(1, 1, 3, MEGABIT, 0.0, DEFAULT_BUNDLE_TTL);
+        bundle.transition(0.0, CGREvent::NewPacketArrived);
+        bundle.transition(0.0, CGREvent::CommunicationLinksAvailable);
+        bundle.transition(0.0, CGREvent::RouteComputed);
+        assert_eq!(bundle.state(), &CGRState::TransmitData);
+
+        let route = router()
+            .find_k_best_routes(&clock(), 1, 3, MEGABIT, 1)
+            .remove(0);
+        bundle.follow(route);
+        assert_eq!(bundle.hop_window, Some((0.0, 1.0)));
+        assert!(!bundle.complete_hop(1.0));
+        assert!(bundle.complete_hop(1.01));
+        bundle.transition(1.01, CGREvent::HopCompleted);
+        assert_eq!(bundle.current_node, 2);
+        assert_eq!(bundle.hop_window, Some((50.0, 51.0)));
+
+        assert!(bundle.complete_hop(51.01));
+        bundle.transition(51.01, CGREvent::DataSent);
+        assert_eq!(bundle.state(), &CGRState::Delivered);
+        assert!(bundle.is_finished());
+        assert!(bundle.current_hop().is_none());
+        assert_eq!(bundle.log().len(), 5);
+        assert_eq!(bundle.log()[3].node, 2);
+    }
+
+    #[test]
+    fn held_bundles_retransmit_or_time_out() {
+        let mut bundle = Bundle::new(1, 1, 3, MEGABIT, 0.0, DEFAULT_BUNDLE_TTL);
+        for event in [
+            CGREvent::NewPacketArrived,
+            CGREvent::CommunicationLinksAvailable,
+            CGREvent::RouteComputed,
+            CGREvent::CommunicationLinkLost,
+        ] {
+            bundle.transition(0.0, event);
+        }
+        assert_eq!(bundle.state(), &CGRState::HoldData);
+
+        // Events that don't apply leave the state alone but are still logged
+        bundle.transition(1.0, CGREvent::DataSent);
+        assert_eq!(bundle.state(), &CGRState::HoldData);
+        assert_eq!(bundle.log().len(), 5);
+
+        let mut retried = bundle.clone();
+        retried.transition(2.0, CGREvent::CommunicationLinkRestored);
+        assert_eq!(retried.state(), &CGRState::Retransmit);
+        retried.transition(3.0, CGREvent::DataSent);
+        assert_eq!(retried.state(), &CGRState::Delivered);
+
+        bundle.transition(2.0, CGREvent::Timeout);
+        assert_eq!(bundle.state(), &CGRState::Failed);
+        assert!(bundle.is_finished());
+    }
+
+    #[test]
+    fn backlog_delays_the_bundle_and_takes_up_volume() {
+        let link = link(1, 2, 0.0, 10.0).with_backlog(4.0 * MEGABIT);
+        assert_eq!(link.residual_volume, 6.0 * MEGABIT);
+        assert_eq!(link.earliest_transmission(1.0), 4.0);
+        assert_eq!(link.arrival_time(1.0, 6.0 * MEGABIT), Some(10.01));
+        assert_eq!(link.arrival_time(1.0, 7.0 * MEGABIT), None);
+    }
+
+    #[test]
+    fn routes_see_what_is_booked_on_the_contact_plan() {
+        let contact = |source, destination, start_time, end_time| {
+            Contact::constant(source, destination, start_time, end_time, MEGABIT)
+        };
+        let mut graph = ContactGraph::new();
+        graph.extend([contact(1, 2, 0.0, 100.0), contact(2, 3, 50.0, 200.0)]);
+        graph.book(
+            2,
+            3,
+            Booking {
+                owner: 9,
+                time: 60.0,
+                volume: 120.0 * MEGABIT,
+            },
+        );
+
+        let routes = CGR::from_contact_graph(&graph).find_k_best_routes(&clock(), 1, 3, MEGABIT, 1);
+        // The 120 queued megabits go out first, and only 30 are left on the second hop
+        assert!((routes[0].best_case_delivery_time - 171.01).abs() < 1e-9);
+        assert_eq!(routes[0].volume, 30.0 * MEGABIT);
+
+        graph.release(9, 2, 3);
+        let routes = CGR::from_contact_graph(&graph).find_k_best_routes(&clock(), 1, 3, MEGABIT, 1);
+        assert_eq!(routes[0].volume, 100.0 * MEGABIT);
+    }
+
+    #[test]
+    fn splitting_keeps_track_of_the_payload() {
+        let mut bundle = Bundle::new(1, 1, 3, 10.0 * MEGABIT, 0.0, DEFAULT_BUNDLE_TTL);
+        bundle.transition(0.0, CGREvent::NewPacketArrived);
+        let mut rest = bundle.split(2, 4.0 * MEGABIT);
+        let last = rest.split(3, 3.0 * MEGABIT);
+
+        assert_eq!(bundle.size, 4.0 * MEGABIT);
+        assert_eq!(
+            (rest.id, rest.size, rest.offset),
+            (2, 3.0 * MEGABIT, 4.0 * MEGABIT)
+        );
+        assert_eq!(
+            (last.id, last.size, last.offset),
+            (3, 3.0 * MEGABIT, 7.0 * MEGABIT)
+        );
+        for fragment in [&rest, &last] {
+            assert_eq!(fragment.payload_id, 1);
+            assert_eq!(fragment.state(), &CGRState::IDLE);
+            assert!(fragment.log().is_empty());
+        }
     }
 }
@@ -0,0 +1,290 @@
+use std::f64::consts::PI;
+
+use crate::common::{Vector3, SPEED_OF_LIGHT};
+
+use super::time::Epoch;
+
+/*
+ * Typed coordinate frames so positions in different frames can't be mixed up.
+ *      Eci      - Earth-centered inertial (TEME, as produced by the propagators), meters
+ *      Ecef     - Earth-centered Earth-fixed, rotates with the Earth, meters
+ *      Geodetic - WGS-84 latitude/longitude in degrees and altitude above the ellipsoid in meters
+ *      Enu      - local East/North/Up offsets from a ground site, meters
+ *      Aer      - azimuth/elevation in degrees and slant range in meters from a ground site
+ */
+
+// WGS-84 ellipsoid
+pub const WGS84_SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+pub const WGS84_FLATTENING: f64 = 1.0 / 298.257_223_563;
+pub const EARTH_ROTATION_RATE: f64 = 7.292_115_146_706_979e-5; // rad/s, relative to the stars
+
+const WGS84_ECCENTRICITY_SQUARED: f64 = WGS84_FLATTENING * (2.0 - WGS84_FLATTENING);
+const GEODETIC_ITERATIONS: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Eci(pub Vector3);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ecef(pub Vector3);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Geodetic {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Enu {
+    pub east: f64,
+    pub north: f64,
+    pub up: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Aer {
+    pub azimuth: f64,
+    pub elevation: f64,
+    pub range: f64,
+}
+
+/**
+ * Greenwich mean sidereal time in radians (IAU-82 model, Vallado "gstime"). UTC is used in
+ * place of UT1, the sub-second difference doesn't matter at our fidelity.
+ */
+pub fn gmst(epoch: &Epoch) -> f64 {
+    let t_ut1 = (epoch.julian_date - 2_451_545.0) / 36_525.0;
+    let seconds = -6.2e-6 * t_ut1.powi(3)
+        + 0.093_104 * t_ut1.powi(2)
+        + (876_600.0 * 3600.0 + 8_640_184.812_866) * t_ut1
+        + 67_310.548_41;
+
+    // 240 seconds of sidereal time per degree
+    (seconds.to_radians() / 240.0).rem_euclid(2.0 * PI)
+}
+
+/**
+ * One-way light time in seconds over a straight line between two inertial positions.
+ */
+pub fn light_time(from: &Eci, to: &Eci) -> f64 {
+    from.distance_to(to) / 1000.0 / SPEED_OF_LIGHT
+}
+
+impl Eci {
+    pub fn distance_to(&self, other: &Eci) -> f64 {
+        self.0.distance_to(&other.0)
+    }
+
+    pub fn to_ecef(self, epoch: &Epoch) -> Ecef {
+        Ecef(rotate_z(&self.0, -gmst(epoch)))
+    }
+}
+
+impl Ecef {
+    pub fn distance_to(&self, other: &Ecef) -> f64 {
+        self.0.distance_to(&other.0)
+    }
+
+    pub fn to_eci(self, epoch: &Epoch) -> Eci {
+        Eci(rotate_z(&self.0, gmst(epoch)))
+    }
+
+    /**
+     * Iterative conversion to WGS-84 geodetic coordinates. Converges to well below a
+     * millimeter within a handful of iterations for anything from the surface up to GEO.
+     */
+    pub fn to_geodetic(self) -> Geodetic {
+        let Vector3 { x, y, z } = self.0;
+        let p = (x * x + y * y).sqrt();
+        let longitude = y.atan2(x);
+        let mut latitude = z.atan2(p * (1.0 - WGS84_ECCENTRICITY_SQUARED));
+        let mut altitude = 0.0;
+
+        for _ in 0..GEODETIC_ITERATIONS {
+            let sin_lat = latitude.sin();
+            let n = prime_vertical_radius(sin_lat);
+            // Form that stays well-behaved near the poles where cos(latitude) -> 0
+            altitude = p * latitude.cos() + z * sin_lat
+                - WGS84_SEMI_MAJOR_AXIS
+                    * (1.0 - WGS84_ECCENTRICITY_SQUARED * sin_lat * sin_lat).sqrt();
+            latitude = z.atan2(p * (1.0 - WGS84_ECCENTRICITY_SQUARED * n / (n + altitude)));
+        }
+
+        Geodetic {
+            latitude: latitude.to_degrees(),
+            longitude: longitude.to_degrees(),
+            altitude,
+        }
+    }
+
+    /**
+     * Offset of this point from `site`, expressed in the site's local East/North/Up frame.
+     */
+    pub fn to_enu(self, site: &Geodetic) -> Enu {
+        let delta = self.0 - site.to_ecef().0;
+        let (sin_lat, cos_lat) = site.latitude.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = site.longitude.to_radians().sin_cos();
+
+        Enu {
+            east: -sin_lon * delta.x + cos_lon * delta.y,
+            north: -sin_lat * cos_lon * delta.x - sin_lat * sin_lon * delta.y + cos_lat * delta.z,
+            up: cos_lat * cos_lon * delta.x + cos_lat * sin_lon * delta.y + sin_lat * delta.z,
+        }
+    }
+
+    pub fn to_aer(self, site: &Geodetic) -> Aer {
+        self.to_enu(site).to_aer()
+    }
+}
+
+impl Geodetic {
+    pub fn new(latitude: f64, longitude: f64, altitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+            altitude,
+        }
+    }
+
+    pub fn to_ecef(self) -> Ecef {
+        let (sin_lat, cos_lat) = self.latitude.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.to_radians().sin_cos();
+        let n = prime_vertical_radius(sin_lat);
+
+        Ecef(Vector3::new(
+            (n + self.altitude) * cos_lat * cos_lon,
+            (n + self.altitude) * cos_lat * sin_lon,
+            (n * (1.0 - WGS84_ECCENTRICITY_SQUARED) + self.altitude) * sin_lat,
+        ))
+    }
+}
+
+impl Enu {
+    pub fn to_aer(self) -> Aer {
+        let range = (self.east.powi(2) + self.north.powi(2) + self.up.powi(2)).sqrt();
+        let horizontal = (self.east.powi(2) + self.north.powi(2)).sqrt();
+
+        Aer {
+            azimuth: self.east.atan2(self.north).to_degrees().rem_euclid(360.0),
+            elevation: self.up.atan2(horizontal).to_degrees(),
+            range,
+        }
+    }
+}
+
+// Radius of curvature in the prime vertical at a given latitude
+fn prime_vertical_radius(sin_latitude: f64) -> f64 {
+    WGS84_SEMI_MAJOR_AXIS / (1.0 - WGS84_ECCENTRICITY_SQUARED * sin_latitude * sin_latitude).sqrt()
+}
+
+// Rotates a vector by `angle` radians about the z axis
+fn rotate_z(vector: &Vector3, angle: f64) -> Vector3 {
+    let (sin_a, cos_a) = angle.sin_cos();
+    Vector3::new(
+        cos_a * vector.x - sin_a * vector.y,
+        sin_a * vector.x + cos_a * vector.y,
+        vector.z,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn gmst_matches_vallado_example_3_5() {
+        let epoch = Epoch::from_calendar(1992, 8, 20, 12, 14, 0.0);
+        assert_close(gmst(&epoch).to_degrees(), 152.578_787_886, 1e-6);
+    }
+
+    #[test]
+    fn ecef_to_geodetic_matches_vallado_example_3_3() {
+        let geodetic = Ecef(Vector3::new(6_524_834.0, 6_862_875.0, 6_448_296.0)).to_geodetic();
+        assert_close(geodetic.latitude, 34.352_496, 1e-6);
+        assert_close(geodetic.longitude, 46.446_417, 1e-6);
+        assert_close(geodetic.altitude, 5_085_219.0, 1.0);
+    }
+
+    #[test]
+    fn geodetic_round_trips_through_ecef() {
+        for (latitude, longitude, altitude) in [
+            (0.0, 0.0, 0.0),
+            (45.0, -120.0, 500_000.0),
+            (-33.9, 18.4, 25.0),
+            (89.999, 10.0, 800_000.0),
+            (-60.0, 179.9, 35_786_000.0),
+        ] {
+            let back = Geodetic::new(latitude, longitude, altitude)
+                .to_ecef()
+                .to_geodetic();
+            assert_close(back.latitude, latitude, 1e-9);
+            assert_close(back.longitude, longitude, 1e-9);
+            assert_close(back.altitude, altitude, 1e-3);
+        }
+
+        // The poles sit one semi-minor axis from the center
+        let pole = Geodetic::new(90.0, 0.0, 0.0).to_ecef().0;
+        assert_close(
+            pole.z,
+            WGS84_SEMI_MAJOR_AXIS * (1.0 - WGS84_FLATTENING),
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn eci_and_ecef_differ_by_the_earth_rotation() {
+        let epoch = Epoch::from_calendar(2024, 3, 20, 3, 6, 0.0);
+        let eci = Eci(Vector3::new(7_000_000.0, -1_200_000.0, 300_000.0));
+        let ecef = eci.to_ecef(&epoch);
+        assert_close(ecef.0.norm(), eci.0.norm(), 1e-6);
+        assert_close(ecef.0.z, eci.0.z, 1e-9);
+        assert_close(
+            ecef.0.y.atan2(ecef.0.x),
+            (eci.0.y.atan2(eci.0.x) - gmst(&epoch) + PI).rem_euclid(2.0 * PI) - PI,
+            1e-12,
+        );
+
+        let back = ecef.to_eci(&epoch);
+        assert_close(back.distance_to(&eci), 0.0, 1e-6);
+
+        // A sidereal day later the Earth has turned back to the same orientation, give or take
+        // the tens of microseconds a Julian date can resolve
+        let later = epoch.plus_seconds(2.0 * PI / EARTH_ROTATION_RATE);
+        assert_close(eci.to_ecef(&later).distance_to(&ecef), 0.0, 10.0);
+    }
+
+    #[test]
+    fn aer_points_the_right_way_from_a_site() {
+        let site = Geodetic::new(40.0, -105.0, 1_600.0);
+
+        let overhead = Geodetic::new(40.0, -105.0, 501_600.0)
+            .to_ecef()
+            .to_aer(&site);
+        assert_close(overhead.elevation, 90.0, 1e-6);
+        assert_close(overhead.range, 500_000.0, 1e-3);
+
+        let north = Geodetic::new(41.0, -105.0, 1_600.0).to_ecef().to_aer(&site);
+        assert_close(north.azimuth, 0.0, 1e-6);
+        assert!(north.elevation < 0.0);
+
+        let east = Geodetic::new(40.0, -104.0, 1_600.0).to_ecef().to_enu(&site);
+        assert!(east.east > 0.0);
+        let aer = east.to_aer();
+        assert!(aer.azimuth > 80.0 && aer.azimuth < 100.0);
+        assert_close(
+            aer.range,
+            (east.east.powi(2) + east.north.powi(2) + east.up.powi(2)).sqrt(),
+            1e-9,
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use crate::common::{EARTH_MU, EARTH_RADIUS};
+
+/*
+ * Orbit decay and what it takes to fight it. Drag is modeled with a piecewise-exponential
+ * atmosphere and the satellite's ballistic coefficient, on near-circular orbits where it only
+ * shrinks the semi-major axis. A station-keeping policy raises satellites back up with
+ * propellant-limited burns once they sink out of their deadband; whatever runs dry keeps
+ * decaying until it reenters.
+ */
+
+pub const REENTRY_ALTITUDE: f64 = 120.0; // km, below this the orbit is gone within a revolution
+pub const DEFAULT_BALLISTIC_COEFFICIENT: f64 = 50.0; // kg/m², m / (Cd A) of a 6U CubeSat
+pub const DEFAULT_DRY_MASS: f64 = 10.0; // kg
+pub const DEFAULT_PROPELLANT: f64 = 0.5; // kg
+pub const DEFAULT_SPECIFIC_IMPULSE: f64 = 220.0; // s, hydrazine monopropellant
+pub const DEFAULT_STATION_KEEPING_DEADBAND: f64 = 2.0; // km below the target before a burn
+pub const STANDARD_GRAVITY: f64 = 9.80665; // m/s²
+const MAX_DECAY_STEP: f64 = 600.0; // seconds of drag applied at one density
+
+// Base altitude (km), density there (kg/m³) and scale height (km), Vallado table 8-4
+const ATMOSPHERE: [(f64, f64, f64); 28] = [
+    (0.0, 1.225, 7.249),
+    (25.0, 3.899e-2, 6.349),
+    (30.0, 1.774e-2, 6.682),
+    (40.0, 3.972e-3, 7.554),
+    (50.0, 1.057e-3, 8.382),
+    (60.0, 3.206e-4, 7.714),
+    (70.0, 8.770e-5, 6.549),
+    (80.0, 1.905e-5, 5.799),
+    (90.0, 3.396e-6, 5.382),
+    (100.0, 5.297e-7, 5.877),
+    (110.0, 9.661e-8, 7.263),
+    (120.0, 2.438e-8, 9.473),
+    (130.0, 8.484e-9, 12.636),
+    (140.0, 3.845e-9, 16.149),
+    (150.0, 2.070e-9, 22.523),
+    (180.0, 5.464e-10, 29.740),
+    (200.0, 2.789e-10, 37.105),
+    (250.0, 7.248e-11, 45.546),
+    (300.0, 2.418e-11, 53.628),
+    (350.0, 9.518e-12, 53.298),
+    (400.0, 3.725e-12, 58.515),
+    (450.0, 1.585e-12, 60.828),
+    (500.0, 6.967e-13, 63.822),
+    (600.0, 1.454e-13, 71.835),
+    (700.0, 3.614e-14, 88.667),
+    (800.0, 1.170e-14, 124.64),
+    (900.0, 5.245e-15, 181.05),
+    (1000.0, 3.019e-15, 268.00),
+];
+
+// Atmospheric density in kg/m³ at `altitude` km
+pub fn atmospheric_density(altitude: f64) -> f64 {
+    let altitude = altitude.max(0.0);
+    let layer = ATMOSPHERE.partition_point(|(base, _, _)| *base <= altitude) - 1;
+    let (base, density, scale_height) = ATMOSPHERE[layer];
+    density * (-(altitude - base) / scale_height).exp()
+}
+
+/**
+ * Rate of change of the semi-major axis (m/s, negative) of a circular orbit of radius
+ * `semi_major_axis` meters: da/dt = -ρ sqrt(μ a) / B.
+ */
+pub fn decay_rate(semi_major_axis: f64, ballistic_coefficient: f64) -> f64 {
+    let altitude = (semi_major_axis - EARTH_RADIUS) / 1000.0;
+    -atmospheric_density(altitude) * (EARTH_MU * semi_major_axis).sqrt() / ballistic_coefficient
+}
+
+/**
+ * Semi-major axis after `seconds` of drag, integrated in steps short enough for the density
+ * to be taken as constant over each.
+ */
+pub fn decayed_semi_major_axis(
+    mut semi_major_axis: f64,
+    ballistic_coefficient: f64,
+    seconds: f64,
+) -> f64 {
+    let mut remaining = seconds;
+    while remaining > 0.0 {
+        let step = remaining.min(MAX_DECAY_STEP);
+        semi_major_axis += decay_rate(semi_major_axis, ballistic_coefficient) * step;
+        remaining -= step;
+    }
+    semi_major_axis
+}
+
+// Total delta-v (m/s) of a Hohmann transfer between circular orbits of radius `from` and `to`
+pub fn hohmann_delta_v(from: f64, to: f64) -> f64 {
+    let transfer = (from + to) / 2.0;
+    let departure = (EARTH_MU / from).sqrt() * ((to / transfer).sqrt() - 1.0);
+    let arrival = (EARTH_MU / to).sqrt() * (1.0 - (from / transfer).sqrt());
+    departure.abs() + arrival.abs()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Propulsion {
+    pub dry_mass: f64,         // kg
+    pub propellant: f64,       // kg left
+    pub specific_impulse: f64, // s
+}
+
+impl Default for Propulsion {
+    fn default() -> Self {
+        Self {
+            dry_mass: DEFAULT_DRY_MASS,
+            propellant: DEFAULT_PROPELLANT,
+            specific_impulse: DEFAULT_SPECIFIC_IMPULSE,
+        }
+    }
+}
+
+impl Propulsion {
+    pub fn mass(&self) -> f64 {
+        self.dry_mass + self.propellant
+    }
+
+    // Delta-v (m/s) the remaining propellant can still give, from the rocket equation
+    pub fn delta_v_capacity(&self) -> f64 {
+        self.specific_impulse * STANDARD_GRAVITY * (self.mass() / self.dry_mass).ln()
+    }
+
+    /**
+     * Burns for `delta_v` m/s, or for as long as the propellant lasts. Returns the delta-v
+     * actually delivered.
+     */
+    pub fn burn(&mut self, delta_v: f64) -> f64 {
+        let delta_v = delta_v.clamp(0.0, self.delta_v_capacity());
+        let exhaust_velocity = self.specific_impulse * STANDARD_GRAVITY;
+        let used = self.mass() * (1.0 - (-delta_v / exhaust_velocity).exp());
+        self.propellant = (self.propellant - used).max(0.0);
+        delta_v
+    }
+}
+
+// A planned orbit raise back to `target_altitude` km, burned at simulation time `time`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Maneuver {
+    pub satellite: u32,
+    pub time: f64,
+    pub delta_v: f64, // m/s, as planned or as flown once executed
+    pub target_altitude: f64,
+}
+
+/**
+ * Keeps satellites within `deadband` km below their target altitude. Burns are planned for the
+ * next ascending node, so an operator can tell in advance where along the orbit they happen.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StationKeeping {
+    pub deadband: f64, // km
+}
+
+impl Default for StationKeeping {
+    fn default() -> Self {
+        Self {
+            deadband: DEFAULT_STATION_KEEPING_DEADBAND,
+        }
+    }
+}
+
+impl StationKeeping {
+    // Whether a satellite `altitude` km up with a `target_altitude` km target needs a burn
+    pub fn needs_burn(&self, altitude: f64, target_altitude: f64) -> bool {
+        altitude < target_altitude - self.deadband
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn density_follows_the_table_between_layers() {
+        for (base, density, _) in ATMOSPHERE {
+            assert_eq!(atmospheric_density(base), density);
+        }
+        let (_, density, scale_height) = ATMOSPHERE[20];
+        assert_eq!(
+            atmospheric_density(405.0),
+            density * (-5.0 / scale_height).exp()
+        );
+        assert_eq!(atmospheric_density(-10.0), ATMOSPHERE[0].1);
+        assert!(atmospheric_density(2000.0) < ATMOSPHERE[27].1);
+    }
+
+    #[test]
+    fn lower_orbits_decay_faster() {
+        let at = |altitude: f64| EARTH_RADIUS + altitude * 1000.0;
+        let high = decay_rate(at(550.0), DEFAULT_BALLISTIC_COEFFICIENT);
+        let low = decay_rate(at(350.0), DEFAULT_BALLISTIC_COEFFICIENT);
+        assert!(low < high && high < 0.0);
+
+        // A day at 350 km loses a few hundred meters
+        let decayed = decayed_semi_major_axis(at(350.0), DEFAULT_BALLISTIC_COEFFICIENT, 86_400.0);
+        assert!((100.0..1000.0).contains(&(at(350.0) - decayed)));
+    }
+
+    #[test]
+    fn hohmann_transfer_to_geostationary() {
+        // Vallado example 6-1: 191.34 km up to GEO takes 3.935 km/s
+        let delta_v = hohmann_delta_v(6_569_400.0, 42_159_190.0);
+        assert!((delta_v - 3935.0).abs() < 5.0, "{} m/s", delta_v);
+        assert_eq!(hohmann_delta_v(42_159_190.0, 6_569_400.0), delta_v);
+        assert_eq!(hohmann_delta_v(7_000_000.0, 7_000_000.0), 0.0);
+    }
+
+    #[test]
+    fn burns_stop_when_the_propellant_runs_out() {
+        let mut propulsion = Propulsion::default();
+        let capacity = propulsion.delta_v_capacity();
+        assert!((capacity - 220.0 * STANDARD_GRAVITY * (10.5_f64 / 10.0).ln()).abs() < 1e-9);
+
+        assert_eq!(propulsion.burn(10.0), 10.0);
+        assert!(propulsion.propellant < DEFAULT_PROPELLANT);
+        assert!((propulsion.delta_v_capacity() - (capacity - 10.0)).abs() < 1e-9);
+
+        let delivered = propulsion.burn(1000.0);
+        assert!((delivered - (capacity - 10.0)).abs() < 1e-9);
+        assert!(propulsion.propellant < 1e-12);
+        assert_eq!(propulsion.burn(1.0), 0.0);
+    }
+
+    #[test]
+    fn station_keeping_waits_for_the_deadband() {
+        let policy = StationKeeping::default();
+        assert!(!policy.needs_burn(550.0, 550.0));
+        assert!(!policy.needs_burn(548.0, 550.0));
+        assert!(policy.needs_burn(547.9, 550.0));
+    }
+}
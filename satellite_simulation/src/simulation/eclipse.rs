@@ -0,0 +1,274 @@
+use std::f64::consts::PI;
+
+use crate::common::{Vector3, EARTH_RADIUS};
+
+use super::{
+    satellite::Satellite, sun::sun_position, time::Epoch, tracking::find_visibility_windows,
+};
+
+/*
+ * Earth's shadow as a cone rather than a cylinder. Seen from the satellite, the Sun and the
+ * Earth are two disks: while they don't overlap the satellite is in full sunlight, while the
+ * Earth's disk partly covers the Sun's it is in penumbra, and once it covers it entirely it is
+ * in umbra. The lit fraction of the Sun's disk is what the solar array gets to work with.
+ */
+
+pub const SUN_RADIUS: f64 = 696_000_000.0; // meters
+pub const DEFAULT_ECLIPSE_SEARCH_STEP: f64 = 30.0; // seconds, LEO eclipses last half an hour
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shadow {
+    Sunlight,
+    Penumbra,
+    Umbra,
+}
+
+impl Shadow {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shadow::Sunlight => "sunlight",
+            Shadow::Penumbra => "penumbra",
+            Shadow::Umbra => "umbra",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EclipseEventKind {
+    PenumbraEntry,
+    UmbraEntry,
+    UmbraExit,
+    PenumbraExit,
+}
+
+impl EclipseEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EclipseEventKind::PenumbraEntry => "entered penumbra",
+            EclipseEventKind::UmbraEntry => "entered umbra",
+            EclipseEventKind::UmbraExit => "left umbra",
+            EclipseEventKind::PenumbraExit => "left penumbra",
+        }
+    }
+}
+
+// A satellite crossing a shadow boundary, at a simulation timestamp
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EclipseEvent {
+    pub satellite: u32,
+    pub time: f64,
+    pub kind: EclipseEventKind,
+}
+
+impl EclipseEvent {
+    pub fn is_entry(&self) -> bool {
+        matches!(
+            self.kind,
+            EclipseEventKind::PenumbraEntry | EclipseEventKind::UmbraEntry
+        )
+    }
+}
+
+/**
+ * Apparent radii of the Sun and the Earth and the angle between their centers, in radians,
+ * as seen from an ECI position in meters.
+ */
+fn disks(position: &Vector3, sun: &Vector3) -> (f64, f64, f64) {
+    let to_sun = *sun - *position;
+    let to_earth = *position * -1.0;
+    let sun_radius = (SUN_RADIUS / to_sun.norm()).min(1.0).asin();
+    let earth_radius = (EARTH_RADIUS / to_earth.norm()).min(1.0).asin();
+    let cos_separation = to_sun.dot(&to_earth) / (to_sun.norm() * to_earth.norm());
+    (
+        sun_radius,
+        earth_radius,
+        cos_separation.clamp(-1.0, 1.0).acos(),
+    )
+}
+
+// Positive while any of the Sun is hidden
+fn penumbra_margin(position: &Vector3, sun: &Vector3) -> f64 {
+    let (sun_radius, earth_radius, separation) = disks(position, sun);
+    sun_radius + earth_radius - separation
+}
+
+// Positive while all of the Sun is hidden
+fn umbra_margin(position: &Vector3, sun: &Vector3) -> f64 {
+    let (sun_radius, earth_radius, separation) = disks(position, sun);
+    earth_radius - sun_radius - separation
+}
+
+/**
+ * Fraction of the Sun's disk visible from an ECI position, 1 in full sunlight and 0 in umbra.
+ * In between it is one minus the area where the two disks overlap.
+ */
+pub fn illumination(position: &Vector3, epoch: &Epoch) -> f64 {
+    let (a, b, c) = disks(position, &sun_position(epoch));
+    if c >= a + b {
+        return 1.0;
+    }
+    if c <= b - a {
+        return 0.0;
+    }
+    let x = (c * c + a * a - b * b) / (2.0 * c);
+    let y = (a * a - x * x).max(0.0).sqrt();
+    let overlap = a * a * (x / a).clamp(-1.0, 1.0).acos()
+        + b * b * ((c - x) / b).clamp(-1.0, 1.0).acos()
+        - c * y;
+    (1.0 - overlap / (PI * a * a)).clamp(0.0, 1.0)
+}
+
+pub fn shadow(position: &Vector3, epoch: &Epoch) -> Shadow {
+    let sun = sun_position(epoch);
+    if umbra_margin(position, &sun) > 0.0 {
+        Shadow::Umbra
+    } else if penumbra_margin(position, &sun) > 0.0 {
+        Shadow::Penumbra
+    } else {
+        Shadow::Sunlight
+    }
+}
+
+/**
+ * Every shadow boundary `satellite` crosses in the `horizon` seconds after `from`, in time
+ * order. Each boundary is found on its own cone, so a penumbra shorter than `step` is still
+ * caught on both sides; shadows already entered at `from` only report their exit.
+ */
+pub fn predict_eclipses(
+    satellite: &Satellite,
+    from: f64,
+    horizon: f64,
+    step: f64,
+) -> Vec<EclipseEvent> {
+    let end = from + horizon;
+    let sample_count = (horizon / step).ceil().max(1.0) as usize;
+    let times: Vec<f64> = (0..=sample_count)
+        .map(|k| (from + k as f64 * step).min(end))
+        .collect();
+    let margin_at = |margin: fn(&Vector3, &Vector3) -> f64| {
+        move |time: f64| {
+            let epoch = satellite.epoch.plus_seconds(time);
+            margin(&satellite.state_at(time).position, &sun_position(&epoch))
+        }
+    };
+
+    let mut events = Vec::new();
+    for (margin, entry, exit) in [
+        (
+            penumbra_margin as fn(&Vector3, &Vector3) -> f64,
+            EclipseEventKind::PenumbraEntry,
+            EclipseEventKind::PenumbraExit,
+        ),
+        (
+            umbra_margin,
+            EclipseEventKind::UmbraEntry,
+            EclipseEventKind::UmbraExit,
+        ),
+    ] {
+        let margin_at = margin_at(margin);
+        let margins: Vec<f64> = times.iter().map(|time| margin_at(*time)).collect();
+        for (start, stop) in find_visibility_windows(&times, &margins, margin_at) {
+            if start > from {
+                events.push(EclipseEvent {
+                    satellite: satellite.id,
+                    time: start,
+                    kind: entry,
+                });
+            }
+            if stop < end {
+                events.push(EclipseEvent {
+                    satellite: satellite.id,
+                    time: stop,
+                    kind: exit,
+                });
+            }
+        }
+    }
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::orbit::OrbitalElements;
+
+    fn epoch() -> Epoch {
+        Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0)
+    }
+
+    /**
+     * A point 7000 km from the Earth's center, `angle` radians around from the anti-Sun
+     * direction towards one perpendicular to it.
+     */
+    fn around_the_terminator(angle: f64) -> Vector3 {
+        let sun = sun_position(&epoch());
+        let towards_sun = sun * (1.0 / sun.norm());
+        let across = towards_sun.cross(&Vector3::new(0.0, 0.0, 1.0));
+        let across = across * (1.0 / across.norm());
+        (towards_sun * -angle.cos() + across * angle.sin()) * 7_000_000.0
+    }
+
+    #[test]
+    fn shadow_deepens_towards_the_anti_sun_direction() {
+        let epoch = epoch();
+        assert_eq!(shadow(&around_the_terminator(0.0), &epoch), Shadow::Umbra);
+        assert_eq!(illumination(&around_the_terminator(0.0), &epoch), 0.0);
+        assert_eq!(shadow(&around_the_terminator(PI), &epoch), Shadow::Sunlight);
+        assert_eq!(illumination(&around_the_terminator(PI), &epoch), 1.0);
+
+        // Where the Earth's limb crosses the middle of the Sun, about half of it is hidden
+        let limb = (EARTH_RADIUS / 7_000_000.0).asin();
+        let half_lit = around_the_terminator(limb);
+        assert_eq!(shadow(&half_lit, &epoch), Shadow::Penumbra);
+        assert!((illumination(&half_lit, &epoch) - 0.5).abs() < 0.05);
+
+        let lit: Vec<f64> = (0..=100)
+            .map(|k| {
+                illumination(
+                    &around_the_terminator(limb + (k as f64 - 50.0) * 1e-4),
+                    &epoch,
+                )
+            })
+            .collect();
+        assert!(lit.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!((lit[0], lit[100]), (0.0, 1.0));
+    }
+
+    #[test]
+    fn eclipses_go_through_penumbra_on_both_sides() {
+        let elements = OrbitalElements::circular(550.0, 53.0_f64.to_radians(), 0.0, 0.0);
+        let satellite = Satellite::from_elements(1, elements, &epoch());
+        let events = predict_eclipses(&satellite, 0.0, 4.0 * 3600.0, DEFAULT_ECLIPSE_SEARCH_STEP);
+        assert!(events.windows(2).all(|pair| pair[0].time <= pair[1].time));
+
+        // Skip whatever shadow the satellite starts in, then check every full eclipse
+        let first = events
+            .iter()
+            .position(|event| event.kind == EclipseEventKind::PenumbraEntry)
+            .unwrap();
+        let eclipses: Vec<&[EclipseEvent]> = events[first..].chunks_exact(4).collect();
+        assert!(eclipses.len() >= 2, "{} eclipses", eclipses.len());
+        for eclipse in eclipses {
+            let kinds: Vec<EclipseEventKind> = eclipse.iter().map(|event| event.kind).collect();
+            assert_eq!(
+                kinds,
+                [
+                    EclipseEventKind::PenumbraEntry,
+                    EclipseEventKind::UmbraEntry,
+                    EclipseEventKind::UmbraExit,
+                    EclipseEventKind::PenumbraExit,
+                ]
+            );
+            // Seconds of penumbra around half an hour of umbra
+            assert!(eclipse[1].time - eclipse[0].time < 30.0);
+            assert!(eclipse[3].time - eclipse[2].time < 30.0);
+            assert!((1200.0..2400.0).contains(&(eclipse[2].time - eclipse[1].time)));
+
+            let middle = (eclipse[1].time + eclipse[2].time) / 2.0;
+            let position = satellite.state_at(middle).position;
+            let epoch = satellite.epoch.plus_seconds(middle);
+            assert_eq!(shadow(&position, &epoch), Shadow::Umbra);
+        }
+    }
+}
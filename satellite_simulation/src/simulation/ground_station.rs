@@ -0,0 +1,492 @@
+use std::{fmt, ops::RangeInclusive, str::FromStr};
+
+use crate::{common::Vector3, communication::link_budget::Radio};
+
+use super::{
+    coordinates::{Aer, Eci, Geodetic, EARTH_ROTATION_RATE},
+    orbit::StateVector,
+    satellite::Satellite,
+    time::Epoch,
+    tracking::bisect_crossing,
+};
+
+/*
+ * The ground segment. Each station has its own location, horizon mask, antenna and the hours
+ * it is actually ours to use. A satellite can downlink to a station while the station is
+ * available and sees it above its elevation mask.
+ *
+ * Stations take node ids just below GROUND_STATION_ID so they never clash with satellites.
+ * GROUND_STATION_ID itself names no station: data addressed to it is delivered by whichever
+ * station receives it first, since every station hands off to the same terrestrial network.
+ */
+
+pub const GROUND_STATION_ID: u32 = u32::MAX;
+pub const MAX_GROUND_STATIONS: u32 = 1024;
+pub const FIRST_GROUND_STATION_ID: u32 = GROUND_STATION_ID - MAX_GROUND_STATIONS;
+pub const DEFAULT_ELEVATION_MASK: f64 = 10.0; // degrees
+pub const DEFAULT_PASS_SEARCH_HORIZON: f64 = 24.0 * 3600.0; // seconds
+pub const DEFAULT_PASS_SEARCH_STEP: f64 = 30.0; // seconds, shorter passes can be missed
+const TCA_TOLERANCE: f64 = 0.1; // seconds
+const LATITUDE_RANGE: RangeInclusive<f64> = -90.0..=90.0; // degrees
+const LONGITUDE_RANGE: RangeInclusive<f64> = -180.0..=180.0; // degrees
+const ELEVATION_MASK_RANGE: RangeInclusive<f64> = 0.0..=90.0; // degrees
+const GOLDEN_RATIO: f64 = 1.618_033_988_749_895;
+
+pub fn is_ground_station(id: u32) -> bool {
+    id >= FIRST_GROUND_STATION_ID
+}
+
+// Whether data sitting at `node` has arrived at `destination`
+pub fn reaches(node: u32, destination: u32) -> bool {
+    node == destination || (destination == GROUND_STATION_ID && is_ground_station(node))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Antenna {
+    pub diameter: f64,      // meters
+    pub gain: f64,          // peak gain, dBi
+    pub beamwidth: f64,     // half-power beamwidth, degrees
+    pub max_slew_rate: f64, // degrees per second
+}
+
+impl Default for Antenna {
+    // A 3.7 m S-band dish, the usual small-satellite ground station
+    fn default() -> Self {
+        Self {
+            diameter: 3.7,
+            gain: 36.0,
+            beamwidth: 2.8,
+            max_slew_rate: 6.0,
+        }
+    }
+}
+
+/**
+ * One pass of a satellite over a station, in simulation seconds: acquisition of signal (rising
+ * through the elevation mask), time of closest approach (highest elevation) and loss of
+ * signal. A pass still in progress at the end of the search is clipped there. The azimuths
+ * are where the antenna has to point at AOS and LOS.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pass {
+    pub satellite: u32,
+    pub station: u32,
+    pub aos: f64,
+    pub tca: f64,
+    pub los: f64,
+    pub max_elevation: f64, // degrees
+    pub aos_azimuth: f64,   // degrees clockwise from north
+    pub los_azimuth: f64,   // degrees clockwise from north
+}
+
+impl Pass {
+    pub fn duration(&self) -> f64 {
+        self.los - self.aos
+    }
+
+    pub fn is_in_progress(&self, time: f64) -> bool {
+        self.aos <= time && time <= self.los
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GroundStation {
+    pub id: u32, // assigned when the station is added to the network
+    pub name: String,
+    pub location: Geodetic,  // WGS-84, altitude in meters
+    pub elevation_mask: f64, // degrees above the horizon
+    pub antenna: Antenna,
+    pub radio: Radio,                  // kept in step with `antenna` on gain
+    pub availability: Vec<(f64, f64)>, // simulation-time windows we may use it in, empty = always
+}
+
+impl GroundStation {
+    pub fn new(name: &str, latitude: f64, longitude: f64, altitude: f64) -> Self {
+        Self {
+            id: FIRST_GROUND_STATION_ID,
+            name: name.to_string(),
+            location: Geodetic::new(latitude, longitude, altitude),
+            elevation_mask: DEFAULT_ELEVATION_MASK,
+            antenna: Antenna::default(),
+            radio: Radio::ground_station(Antenna::default().gain),
+            availability: Vec::new(),
+        }
+    }
+
+    pub fn with_elevation_mask(mut self, elevation_mask: f64) -> Self {
+        self.elevation_mask = elevation_mask;
+        self
+    }
+
+    pub fn with_antenna(mut self, antenna: Antenna) -> Self {
+        self.antenna = antenna;
+        self.radio.antenna_gain = antenna.gain;
+        self
+    }
+
+    pub fn with_availability(mut self, mut windows: Vec<(f64, f64)>) -> Self {
+        windows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.availability = windows;
+        self
+    }
+
+    pub fn is_available_at(&self, time: f64) -> bool {
+        self.availability.is_empty()
+            || self
+                .availability
+                .iter()
+                .any(|(start, end)| *start <= time && time <= *end)
+    }
+
+    // The parts of [start, end] the station is available for
+    pub fn available_within(&self, start: f64, end: f64) -> Vec<(f64, f64)> {
+        if self.availability.is_empty() {
+            return vec![(start, end)];
+        }
+        self.availability
+            .iter()
+            .map(|(from, to)| (from.max(start), to.min(end)))
+            .filter(|(from, to)| from < to)
+            .collect()
+    }
+
+    pub fn eci_at(&self, epoch: &Epoch) -> Eci {
+        self.location.to_ecef().to_eci(epoch)
+    }
+
+    // Inertial state of the station, carried around by the Earth's rotation
+    pub fn state_at(&self, epoch: &Epoch) -> StateVector {
+        let position = self.eci_at(epoch).0;
+        StateVector {
+            position,
+            velocity: Vector3::new(0.0, 0.0, EARTH_ROTATION_RATE).cross(&position),
+        }
+    }
+
+    pub fn look_angles(&self, position: &Eci, epoch: &Epoch) -> Aer {
+        position.to_ecef(epoch).to_aer(&self.location)
+    }
+
+    // Degrees above the elevation mask, negative while the satellite is below it
+    pub fn elevation_margin(&self, position: &Eci, epoch: &Epoch) -> f64 {
+        self.look_angles(position, epoch).elevation - self.elevation_mask
+    }
+
+    /**
+     * The first pass of `satellite` over this station that hasn't ended by `from`, looking up
+     * to `horizon` seconds ahead on `step`-second samples. A pass already in progress at `from`
+     * is returned whole, its AOS in the past. Only geometry counts here; whether the station
+     * is available during the pass is up to the caller.
+     */
+    pub fn predict_pass(
+        &self,
+        satellite: &Satellite,
+        from: f64,
+        horizon: f64,
+        step: f64,
+    ) -> Option<Pass> {
+        let margin =
+            |t: f64| self.elevation_margin(&satellite.eci_at(t), &satellite.epoch.plus_seconds(t));
+        let end = from + horizon;
+
+        // Walk back to the start of a pass that is already under way
+        let mut aos = None;
+        if margin(from) > 0.0 {
+            let mut time = from;
+            while aos.is_none() {
+                let previous = time - step;
+                if margin(previous) <= 0.0 {
+                    aos = Some(bisect_crossing(&margin, previous, time));
+                } else if from - previous >= horizon {
+                    aos = Some(previous); // never sets, e.g. geostationary
+                }
+                time = previous;
+            }
+        }
+
+        let mut los = None;
+        let mut time = from;
+        while los.is_none() && time < end {
+            let next = (time + step).min(end);
+            match aos {
+                None if margin(next) > 0.0 => aos = Some(bisect_crossing(&margin, time, next)),
+                Some(_) if margin(next) <= 0.0 => los = Some(bisect_crossing(&margin, time, next)),
+                _ => {}
+            }
+            time = next;
+        }
+        let aos = aos?;
+        let los = los.unwrap_or(end);
+
+        // Elevation rises and falls once over a pass, so a golden-section search finds the peak
+        let elevation = |t: f64| margin(t) + self.elevation_mask;
+        let (mut low, mut high) = (aos, los);
+        while high - low > TCA_TOLERANCE {
+            let lower = high - (high - low) / GOLDEN_RATIO;
+            let upper = low + (high - low) / GOLDEN_RATIO;
+            if elevation(lower) < elevation(upper) {
+                low = lower;
+            } else {
+                high = upper;
+            }
+        }
+        let tca = (low + high) / 2.0;
+        let azimuth = |t: f64| {
+            self.look_angles(&satellite.eci_at(t), &satellite.epoch.plus_seconds(t))
+                .azimuth
+        };
+
+        Some(Pass {
+            satellite: satellite.id,
+            station: self.id,
+            aos,
+            tca,
+            los,
+            max_elevation: elevation(tca),
+            aos_azimuth: azimuth(aos),
+            los_azimuth: azimuth(los),
+        })
+    }
+
+    // Every pass that hasn't ended by `from` within the next `horizon` seconds
+    pub fn predict_passes(&self, satellite: &Satellite, from: f64, horizon: f64) -> Vec<Pass> {
+        let end = from + horizon;
+        let mut passes = Vec::new();
+        let mut time = from;
+        while let Some(pass) =
+            self.predict_pass(satellite, time, end - time, DEFAULT_PASS_SEARCH_STEP)
+        {
+            passes.push(pass);
+            if pass.los >= end {
+                break;
+            }
+            time = pass.los + TCA_TOLERANCE;
+        }
+        passes
+    }
+
+    // Whether the satellite can downlink to this station at its current simulation time
+    pub fn can_see(&self, satellite: &Satellite) -> bool {
+        self.is_available_at(satellite.sim_time)
+            && satellite.ecef().to_aer(&self.location).elevation >= self.elevation_mask
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroundStationError {
+    TooMany { max: u32 },
+}
+
+impl fmt::Display for GroundStationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroundStationError::TooMany { max } => {
+                write!(f, "at most {} ground stations are supported", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GroundStationError {}
+
+/**
+ * Parses "name,latitude,longitude[,altitude[,elevation mask]]" with angles in degrees and
+ * the altitude in meters, e.g. "Svalbard,78.2298,15.4078,500,5". Options may follow:
+ * "gain=<dBi>" fits a dish with that peak gain and every "window=<start>-<end>" adds a
+ * window, in simulation seconds, the station is available in. Without any window it is
+ * available around the clock.
+ */
+impl FromStr for GroundStation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let all: Vec<&str> = s.split(',').map(str::trim).collect();
+        let positional = all.iter().take_while(|field| !field.contains('=')).count();
+        let (fields, options) = all.split_at(positional);
+        if !(3..=5).contains(&fields.len()) {
+            return Err(format!(
+                "expected name,latitude,longitude[,altitude[,elevation mask]][,gain=dBi][,window=start-end...], got {:?}",
+                s
+            ));
+        }
+        let parse = |field: &str| -> Result<f64, String> {
+            field
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .ok_or_else(|| format!("{:?} is not a number in {:?}", field, s))
+        };
+        let number = |index: usize, default: f64| -> Result<f64, String> {
+            fields.get(index).map_or(Ok(default), |field| parse(field))
+        };
+        let angle = |index: usize, default: f64, name: &str, range: RangeInclusive<f64>| {
+            let value = number(index, default)?;
+            if range.contains(&value) {
+                Ok(value)
+            } else {
+                Err(format!(
+                    "{} {} is outside {}..{} degrees in {:?}",
+                    name,
+                    value,
+                    range.start(),
+                    range.end(),
+                    s
+                ))
+            }
+        };
+
+        let mut antenna = Antenna::default();
+        let mut windows = Vec::new();
+        for option in options {
+            match option.split_once('=') {
+                Some(("gain", gain)) => antenna.gain = parse(gain)?,
+                Some(("window", window)) => {
+                    let (start, end) = window
+                        .split_once('-')
+                        .ok_or_else(|| format!("expected window=start-end, got {:?}", option))?;
+                    let (start, end) = (parse(start)?, parse(end)?);
+                    if start >= end {
+                        return Err(format!("window {:?} ends before it starts", window));
+                    }
+                    windows.push((start, end));
+                }
+                _ => return Err(format!("unknown option {:?} in {:?}", option, s)),
+            }
+        }
+
+        Ok(GroundStation::new(
+            fields[0],
+            angle(1, 0.0, "latitude", LATITUDE_RANGE)?,
+            angle(2, 0.0, "longitude", LONGITUDE_RANGE)?,
+            number(3, 0.0)?,
+        )
+        .with_elevation_mask(angle(
+            4,
+            DEFAULT_ELEVATION_MASK,
+            "elevation mask",
+            ELEVATION_MASK_RANGE,
+        )?)
+        .with_antenna(antenna)
+        .with_availability(windows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::orbit::OrbitalElements;
+
+    fn satellite() -> Satellite {
+        let elements = OrbitalElements::circular(550.0, 53.0_f64.to_radians(), 0.0, 0.0);
+        Satellite::from_elements(1, elements, &Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0))
+    }
+
+    // A station right below where `satellite` is `time` seconds in
+    fn station_below(satellite: &Satellite, time: f64) -> GroundStation {
+        let epoch = satellite.epoch.plus_seconds(time);
+        let point = satellite.eci_at(time).to_ecef(&epoch).to_geodetic();
+        GroundStation::new("Below", point.latitude, point.longitude, 0.0)
+    }
+
+    #[test]
+    fn overhead_pass_peaks_at_the_zenith() {
+        let satellite = satellite();
+        let station = station_below(&satellite, 1200.0);
+        let pass = station
+            .predict_pass(&satellite, 600.0, 3600.0, DEFAULT_PASS_SEARCH_STEP)
+            .unwrap();
+
+        assert!(pass.aos < pass.tca && pass.tca < pass.los);
+        assert!((pass.tca - 1200.0).abs() < 5.0, "TCA at {}", pass.tca);
+        assert!(pass.max_elevation > 85.0);
+        // Straight overhead, so it sets on the opposite side of the sky from where it rose
+        let sweep = (pass.los_azimuth - pass.aos_azimuth).rem_euclid(360.0);
+        assert!(
+            (sweep - 180.0).abs() < 5.0,
+            "{} degrees between AOS and LOS",
+            sweep
+        );
+        // A 550 km orbit stays above 10° for a few minutes on an overhead pass
+        assert!((300.0..600.0).contains(&pass.duration()));
+        for time in [pass.aos, pass.los] {
+            let epoch = satellite.epoch.plus_seconds(time);
+            let margin = station.elevation_margin(&satellite.eci_at(time), &epoch);
+            assert!(margin.abs() < 1e-3, "{} degrees off the mask", margin);
+        }
+
+        // Searching from the middle of the pass still returns all of it
+        let again = station
+            .predict_pass(&satellite, pass.tca, 3600.0, DEFAULT_PASS_SEARCH_STEP)
+            .unwrap();
+        assert!((again.aos - pass.aos).abs() < 1e-3 && (again.los - pass.los).abs() < 1e-3);
+        assert!(again.is_in_progress(pass.tca));
+        assert!(!again.is_in_progress(600.0) && !again.is_in_progress(pass.los + 1.0));
+    }
+
+    #[test]
+    fn passes_over_a_day_are_ordered_and_clear_the_mask() {
+        let satellite = satellite();
+        let station = station_below(&satellite, 1200.0);
+        let passes = station.predict_passes(&satellite, 0.0, DEFAULT_PASS_SEARCH_HORIZON);
+
+        assert!(passes.len() >= 3, "{} passes", passes.len());
+        for pass in &passes {
+            assert!(pass.aos < pass.tca && pass.tca < pass.los);
+            assert!(pass.max_elevation >= station.elevation_mask);
+        }
+        assert!(passes.windows(2).all(|pair| pair[0].los < pair[1].aos));
+    }
+
+    #[test]
+    fn parses_stations_with_and_without_the_optional_fields() {
+        let station: GroundStation = "Svalbard, 78.23, 15.39".parse().unwrap();
+        assert_eq!(station.name, "Svalbard");
+        assert_eq!(station.location.latitude, 78.23);
+        assert_eq!(station.location.longitude, 15.39);
+        assert_eq!(station.elevation_mask, DEFAULT_ELEVATION_MASK);
+
+        let station: GroundStation = "Wallops,37.94,-75.46,10,5".parse().unwrap();
+        assert_eq!(station.elevation_mask, 5.0);
+    }
+
+    #[test]
+    fn parses_antenna_gain_and_availability_windows() {
+        let station: GroundStation = "Svalbard,78.23,15.39,gain=40,window=7200-9000,window=0-3600"
+            .parse()
+            .unwrap();
+        assert_eq!(station.elevation_mask, DEFAULT_ELEVATION_MASK);
+        assert_eq!(station.antenna.gain, 40.0);
+        assert_eq!(station.radio.antenna_gain, 40.0);
+        assert_eq!(station.availability, vec![(0.0, 3600.0), (7200.0, 9000.0)]);
+        assert!(station.is_available_at(3600.0));
+        assert!(!station.is_available_at(5000.0));
+
+        for text in [
+            "A,0,0,gain=loud",
+            "A,0,0,window=10",
+            "A,0,0,window=20-10",
+            "A,0,0,beam=3",
+            "A,0,0,gain=40,5",
+        ] {
+            assert!(text.parse::<GroundStation>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_angles_out_of_range() {
+        for text in [
+            "A,90.5,0",
+            "A,-91,0",
+            "A,0,180.5",
+            "A,0,-181",
+            "A,NaN,0",
+            "A,0,0,0,-1",
+            "A,0,0,0,91",
+            "A,0",
+            "A,north,0",
+        ] {
+            assert!(text.parse::<GroundStation>().is_err(), "{}", text);
+        }
+        assert!("A,-90,180,0,90".parse::<GroundStation>().is_ok());
+    }
+}
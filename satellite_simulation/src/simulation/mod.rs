@@ -1,9 +1,20 @@
 pub mod cgr;
+pub mod coordinates;
+pub mod drag;
+pub mod eclipse;
+pub mod ground_station;
 pub mod network;
+pub mod numerical;
+pub mod orbit;
+pub mod power;
 /**
 *  ✅ Satellites need positions before they can communicate → We need a basic orbital model to determine where they are.
    ✅ Routing & communication depend on knowing satellite locations → The graph structure must update dynamically.
    ✅ Security, storage, and messaging all rely on having a satellite network established.
 */
 pub mod satellite;
+pub mod sgp4;
+pub mod sun;
+pub mod time;
+pub mod tle;
 pub mod tracking;
@@ -1,12 +1,102 @@
-use super::tracking::Contact;
-use crate::simulation::{satellite::Satellite, tracking::create_satellites_map};
+use super::{
+    cgr::{
+        Bundle, BundleError, CGREvent, CGRState, Route, RouteTable, CGR, DEFAULT_BUNDLE_TTL,
+        DEFAULT_ROUTE_COUNT, MIN_FRAGMENT_SIZE,
+    },
+    drag::{hohmann_delta_v, Maneuver, StationKeeping},
+    eclipse::{EclipseEvent, DEFAULT_ECLIPSE_SEARCH_STEP},
+    ground_station::{
+        is_ground_station, reaches, GroundStation, GroundStationError, Pass,
+        FIRST_GROUND_STATION_ID, GROUND_STATION_ID, MAX_GROUND_STATIONS,
+    },
+    numerical::Integrator,
+    orbit::OrbitalElements,
+    sgp4::Sgp4Error,
+    time::{ClockError, Epoch, SimClock, DEFAULT_TIME_STEP},
+    tle::{load_tle_file, TleError},
+    tracking::{check_sample_step, doppler_table, Booking, ConnectionEvent, Contact, DopplerTable},
+};
+use crate::common::EARTH_RADIUS;
+use crate::communication::ground_comms::{
+    schedule_downlinks, DownlinkSchedule, SchedulerConfig, STORAGE_UNIT_BITS,
+};
+use crate::communication::link_budget::Radio;
+use crate::routing::{
+    dtn::{DeliveryStats, DtnMessage, DtnMode, DtnRouter},
+    heuristics::{RelayStrategy, WeightedScore},
+    pathfinding::{self, DownlinkPaths, SearchAlgorithm},
+};
+use crate::simulation::{
+    satellite::Satellite,
+    tracking::{
+        create_satellites_map, generate_contact_plan, ContactGraph, ContactPlanConfig,
+        ContactPlanError,
+    },
+};
 use core::f64;
 use rand::Rng;
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+// The downlink point the firmware reports (San Francisco), the network's only station by default
+pub const DEFAULT_GROUND_POSITION: (f64, f64) = (37.7749, -122.4194);
+// How often the contact plan is re-predicted while bundles are in flight, in seconds
+pub const DEFAULT_PLAN_REFRESH_INTERVAL: f64 = 3600.0;
+// Slack allowed between a planned hop and the re-predicted contact, in seconds
+const LINK_CHECK_TOLERANCE: f64 = 1.0;
+// Upper bound on state machine transitions a single bundle makes per tick
+const MAX_TRANSITIONS_PER_TICK: usize = 16;
+
+/**
+ * Subsystems (routing, storage, comms...) register a hook to run against every new network
+ * state produced by `SatelliteNetwork::run`. Closures taking the network work as hooks too.
+ */
+pub trait TickHook {
+    fn on_tick(&mut self, network: &mut SatelliteNetwork);
+}
+
+impl<F: FnMut(&mut SatelliteNetwork)> TickHook for F {
+    fn on_tick(&mut self, network: &mut SatelliteNetwork) {
+        self(network)
+    }
+}
+
+// Pauses the clock once simulation time reaches the given number of seconds since the epoch
+pub struct PauseAt(pub f64);
+
+impl TickHook for PauseAt {
+    fn on_tick(&mut self, network: &mut SatelliteNetwork) {
+        if network.clock().now() >= self.0 && !network.clock().is_paused() {
+            network.clock_mut().pause();
+        }
+    }
+}
 
 pub struct SatelliteNetwork {
     satellites_dict: HashMap<u32, Satellite>,
     satellites_network: HashMap<u32, Vec<Contact>>,
+    ground_links: HashSet<(u32, u32)>, // (satellite, station) pairs in view at the last update
+    connection_events: Vec<ConnectionEvent>, // link changes found by the last update
+    eclipse_events: Vec<EclipseEvent>, // shadow boundaries crossed during the last tick
+    clock: SimClock,
+    contact_graph: ContactGraph, // predicted contact plan, see `refresh_contact_plan`
+    ground_stations: Vec<GroundStation>,
+    route_tables: HashMap<u32, RouteTable>, // per-node CGR route cache, see `routes_to`
+    plan_config: ContactPlanConfig,
+    plan_refreshed_at: Option<f64>,
+    bundles: Vec<Bundle>,
+    next_bundle_id: u32,
+    dtn_router: Option<DtnRouter>, // opportunistic routing run next to CGR, see `set_dtn_mode`
+    downlink_schedule: Option<DownlinkSchedule>, // executed every tick, see `schedule_downlinks`
+    station_keeping: Option<StationKeeping>, // None lets every orbit decay
+    integrator: Option<Integrator>, // numerical propagation for every satellite, see `set_integrator`
+    planned_maneuvers: Vec<Maneuver>,
+    maneuvers: Vec<Maneuver>, // burns flown so far, with the delta-v actually delivered
+    reentries: Vec<(u32, f64)>, // (satellite, time) of every satellite lost to reentry
+    tick_hooks: Vec<Box<dyn TickHook>>,
+    relay_strategy: Box<dyn RelayStrategy>, // see `find_best_relay`
 }
 
 impl SatelliteNetwork {
@@ -14,25 +104,700 @@ impl SatelliteNetwork {
         Self {
             satellites_dict: HashMap::new(),
             satellites_network: HashMap::new(),
+            ground_links: HashSet::new(),
+            connection_events: Vec::new(),
+            eclipse_events: Vec::new(),
+            clock: SimClock::new(Epoch::now(), DEFAULT_TIME_STEP),
+            contact_graph: ContactGraph::new(),
+            ground_stations: vec![GroundStation {
+                id: FIRST_GROUND_STATION_ID,
+                ..GroundStation::new(
+                    "San Francisco",
+                    DEFAULT_GROUND_POSITION.0,
+                    DEFAULT_GROUND_POSITION.1,
+                    0.0,
+                )
+            }],
+            route_tables: HashMap::new(),
+            plan_config: ContactPlanConfig::default(),
+            plan_refreshed_at: None,
+            bundles: Vec::new(),
+            next_bundle_id: 0,
+            dtn_router: None,
+            downlink_schedule: None,
+            station_keeping: None,
+            integrator: None,
+            planned_maneuvers: Vec::new(),
+            maneuvers: Vec::new(),
+            reentries: Vec::new(),
+            tick_hooks: Vec::new(),
+            relay_strategy: Box::new(WeightedScore::default()),
+        }
+    }
+
+    pub fn clock(&self) -> &SimClock {
+        &self.clock
+    }
+
+    pub fn contact_graph(&self) -> &ContactGraph {
+        &self.contact_graph
+    }
+
+    pub fn clock_mut(&mut self) -> &mut SimClock {
+        &mut self.clock
+    }
+
+    pub fn satellites(&self) -> &HashMap<u32, Satellite> {
+        &self.satellites_dict
+    }
+
+    /**
+     * Predicts every inter-satellite and ground contact from now until the configured
+     * horizon, as opposed to `satellites_network` which only reflects the current geometry.
+     */
+    pub fn generate_contact_plan(
+        &self,
+        config: &ContactPlanConfig,
+    ) -> Result<Vec<Contact>, ContactPlanError> {
+        generate_contact_plan(
+            &self.satellites_dict,
+            &self.ground_stations,
+            &self.clock,
+            config,
+        )
+    }
+
+    /**
+     * Regenerates the contact plan from now over the configured horizon and merges it into
+     * the contact graph. Returns how many contacts the update contained.
+     */
+    pub fn refresh_contact_plan(
+        &mut self,
+        config: &ContactPlanConfig,
+    ) -> Result<usize, ContactPlanError> {
+        let now = self.clock.now();
+        let plan = self.generate_contact_plan(config)?;
+        let count = plan.len();
+        self.contact_graph.merge(now, now + config.horizon, plan);
+        self.plan_refreshed_at = Some(now);
+        Ok(count)
+    }
+
+    // Configuration used when the network re-predicts the plan on its own during a run
+    pub fn contact_plan_config(&self) -> &ContactPlanConfig {
+        &self.plan_config
+    }
+
+    // Rejects a config the plan can't be sampled with, keeping the current one
+    pub fn set_contact_plan_config(
+        &mut self,
+        config: ContactPlanConfig,
+    ) -> Result<(), ContactPlanError> {
+        config.validate()?;
+        self.plan_config = config;
+        Ok(())
+    }
+
+    /**
+     * Cached routes from `source` to `destination` that haven't expired by `ready_at` and
+     * still have room for `bundle_size` bits given everything booked on them so far.
+     */
+    pub fn usable_routes(
+        &mut self,
+        source: u32,
+        destination: u32,
+        bundle_size: f64,
+        ready_at: f64,
+    ) -> Vec<Route> {
+        let routes = self.routes_to(source, destination).to_vec();
+        routes
+            .into_iter()
+            .filter(|route| {
+                route.expiry > ready_at
+                    && self.relays_available(route)
+                    && self.route_volume(route, ready_at) >= bundle_size
+            })
+            .collect()
+    }
+
+    /**
+     * Earliest-arrival route for a bundle of `bundle_size` bits leaving `source` now, searched
+     * afresh over the current plan and its bookings rather than taken from the route cache.
+     * The cache is filled for an empty bundle, so this finds room the cached routes may lack.
+     */
+    pub fn find_route(&self, source: u32, destination: u32, bundle_size: f64) -> Option<Route> {
+        CGR::from_contact_graph(&self.contact_graph)
+            .find_best_route(
+                &self.clock,
+                source as usize,
+                destination as usize,
+                bundle_size,
+            )
+            .filter(|route| self.relays_available(route))
+    }
+
+    // Whether every satellite between the two ends of `route` has the charge to relay
+    fn relays_available(&self, route: &Route) -> bool {
+        let nodes = route.nodes();
+        nodes
+            .iter()
+            .skip(1)
+            .take(nodes.len().saturating_sub(2))
+            .all(|node| {
+                self.satellites_dict
+                    .get(&(*node as u32))
+                    .is_none_or(Satellite::can_relay)
+            })
+    }
+
+    // Radio time on both ends of a link for `bits` sent at `data_rate`
+    fn spend_link_energy(&mut self, from: u32, to: u32, bits: f64, data_rate: f64) {
+        if data_rate <= 0.0 {
+            return;
+        }
+        let seconds = bits / data_rate;
+        if let Some(sat) = self.satellites_dict.get_mut(&from) {
+            sat.power.transmit(seconds);
+        }
+        if let Some(sat) = self.satellites_dict.get_mut(&to) {
+            sat.power.receive(seconds);
+        }
+    }
+
+    /**
+     * How many bits `route` can still carry for data ready at `ready_at`: the least any hop
+     * has left once its bookings are taken off, with the part of the first contact that has
+     * already gone by not counting.
+     */
+    pub fn route_volume(&self, route: &Route, ready_at: f64) -> f64 {
+        route
+            .hops
+            .iter()
+            .enumerate()
+            .map(|(index, hop)| {
+                let Some(contact) = self
+                    .contact_graph
+                    .contacts_between(hop.from as u32, hop.to as u32)
+                    .iter()
+                    .find(|contact| contact.overlaps(hop.start_time, hop.end_time))
+                else {
+                    return 0.0;
+                };
+                let residual = self.contact_graph.residual_volume(contact);
+                if index == 0 {
+                    residual.min(contact.volume_between(ready_at, contact.end_time))
+                } else {
+                    residual
+                }
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /**
+     * The k best contact-disjoint routes from `source` to `destination`, served from the
+     * source's route table. The table is thrown away when the contact plan changes; a single
+     * destination is recomputed early only once all of its cached routes have expired.
+     */
+    pub fn routes_to(&mut self, source: u32, destination: u32) -> &[Route] {
+        let now = self.clock.now();
+        let plan_version = self.contact_graph.version();
+        let table = self
+            .route_tables
+            .entry(source)
+            .or_insert_with(|| RouteTable::new(plan_version));
+        if !table.is_valid_for(plan_version) {
+            *table = RouteTable::new(plan_version);
+        }
+
+        let is_stale = table
+            .routes_to(destination as usize)
+            .is_none_or(|routes| routes.iter().all(|route| route.expiry <= now));
+        if is_stale {
+            // Routes are cached for the best case; volume is checked per bundle on lookup
+            let routes = CGR::from_contact_graph(&self.contact_graph).find_k_best_routes(
+                &self.clock,
+                source as usize,
+                destination as usize,
+                0.0,
+                DEFAULT_ROUTE_COUNT,
+            );
+            table.insert(destination as usize, routes);
+        }
+
+        table.routes_to(destination as usize).unwrap_or(&[])
+    }
+
+    pub fn add_tick_hook<H: TickHook + 'static>(&mut self, hook: H) {
+        self.tick_hooks.push(Box::new(hook));
+    }
+
+    /**
+     * Drives the simulation for `duration` seconds of simulation time in increments of
+     * `step`. How fast that happens in wall-clock time is up to the clock's mode; a paused
+     * clock stops the run. Fails without ticking if `step` isn't a positive number of seconds
+     * or `duration` isn't a finite, non-negative one.
+     */
+    pub fn run(&mut self, duration: f64, step: f64) -> Result<(), ClockError> {
+        if !(duration >= 0.0 && duration.is_finite()) {
+            return Err(ClockError::Duration(duration));
+        }
+        self.clock.set_step(step)?;
+        let end_time = self.clock.now() + duration;
+
+        while self.clock.now() < end_time {
+            if !self.tick() {
+                println!("⏸️ Clock paused at {}", self.clock.now_epoch());
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Advances the clock by one step and brings the whole network up to date: positions,
+     * time-to-downlink and communication windows, then the contact graph. Registered hooks run
+     * last, against the new state. Returns false if the clock didn't move.
+     */
+    pub fn tick(&mut self) -> bool {
+        if self.clock.tick() == 0.0 {
+            return false;
+        }
+        println!(
+            "🕒 {} (t = {:.1}s)",
+            self.clock.now_epoch(),
+            self.clock.now()
+        );
+
+        self.update_sat_positions();
+        self.keep_stations();
+        self.remove_reentered();
+        self.contact_graph.expire(self.clock.now());
+        let stations = &self.ground_stations;
+        self.satellites_dict
+            .values_mut()
+            .for_each(|sat| sat.update_time_to_downlink(stations));
+        self.update_satellite_network();
+        self.forward_bundles();
+        self.forward_dtn_messages();
+        self.execute_downlinks();
+
+        // Hooks get the network mutably, so take them out while they run
+        let mut hooks = std::mem::take(&mut self.tick_hooks);
+        for hook in hooks.iter_mut() {
+            hook.on_tick(self);
+        }
+        hooks.append(&mut self.tick_hooks); // keep any hooks registered during this tick
+        self.tick_hooks = hooks;
+
+        true
+    }
+
+    /**
+     * Queues a bundle of `size` bits at `source` for delivery to `destination` and returns
+     * its id. It starts moving on the next tick. Fails without queueing anything if the size
+     * isn't a positive, finite number of bits or either end isn't part of the network.
+     */
+    pub fn send_bundle(
+        &mut self,
+        source: u32,
+        destination: u32,
+        size: f64,
+    ) -> Result<u32, BundleError> {
+        if !(size > 0.0 && size.is_finite()) {
+            return Err(BundleError::Size(size));
+        }
+        if !self.satellites_dict.contains_key(&source) {
+            return Err(BundleError::UnknownSource(source));
+        }
+        let known_destination = destination == GROUND_STATION_ID
+            || self.satellites_dict.contains_key(&destination)
+            || self
+                .ground_stations
+                .iter()
+                .any(|station| station.id == destination);
+        if !known_destination {
+            return Err(BundleError::UnknownDestination(destination));
+        }
+        let id = self.next_bundle_id;
+        self.next_bundle_id += 1;
+        let now = self.clock.now();
+        let mut bundle = Bundle::new(
+            id,
+            source as usize,
+            destination as usize,
+            size,
+            now,
+            DEFAULT_BUNDLE_TTL,
+        );
+        bundle.transition(now, CGREvent::NewPacketArrived);
+        self.bundles.push(bundle);
+        if let Some(router) = self.dtn_router.as_mut() {
+            router.send(DtnMessage {
+                id,
+                source,
+                destination,
+                size,
+                expires_at: now + DEFAULT_BUNDLE_TTL,
+                delivered_at: None,
+            });
+        }
+        Ok(id)
+    }
+
+    /**
+     * Runs an opportunistic DTN mode next to CGR for the rest of the run. Bundles sent from now
+     * on are also handed to the DTN router, so both schemes move the same traffic over the
+     * same network and their `DeliveryStats` can be compared directly. None switches it off.
+     */
+    pub fn set_dtn_mode(&mut self, mode: Option<DtnMode>) {
+        self.dtn_router = mode.map(|mode| {
+            let mut router = DtnRouter::new(mode);
+            // Links that are already up count as just established
+            let now = self.clock.now();
+            for (id, contacts) in &self.satellites_network {
+                for contact in contacts.iter().filter(|c| *id < c.destination) {
+                    router.on_connection_event(&ConnectionEvent::established(
+                        now,
+                        *id,
+                        contact.destination,
+                    ));
+                }
+            }
+            for (sat_id, station_id) in &self.ground_links {
+                router.on_connection_event(&ConnectionEvent::established(
+                    now,
+                    *sat_id,
+                    *station_id,
+                ));
+            }
+            router
+        });
+    }
+
+    pub fn dtn_router(&self) -> Option<&DtnRouter> {
+        self.dtn_router.as_ref()
+    }
+
+    /**
+     * Delivery of the bundles CGR carried, counted per payload so fragments don't inflate the
+     * numbers: a payload is delivered once every fragment has arrived, and every hop any
+     * fragment completed counts as a transmission.
+     */
+    pub fn cgr_stats(&self) -> DeliveryStats {
+        let mut payloads: HashMap<u32, bool> = HashMap::new();
+        let mut transmissions = 0;
+        for bundle in &self.bundles {
+            let delivered = payloads.entry(bundle.payload_id).or_insert(true);
+            *delivered &= bundle.state() == &CGRState::Delivered;
+            transmissions += bundle
+                .log()
+                .iter()
+                .filter(|entry| matches!(entry.event, CGREvent::HopCompleted | CGREvent::DataSent))
+                .count();
+        }
+        DeliveryStats {
+            created: payloads.len(),
+            delivered: payloads.values().filter(|delivered| **delivered).count(),
+            transmissions,
+        }
+    }
+
+    pub fn dtn_stats(&self) -> Option<DeliveryStats> {
+        self.dtn_router.as_ref().map(DtnRouter::stats)
+    }
+
+    // Feeds the DTN router the link changes of this tick and lets it exchange messages
+    pub fn forward_dtn_messages(&mut self) {
+        let Some(router) = self.dtn_router.as_mut() else {
+            return;
+        };
+        for event in &self.connection_events {
+            router.on_connection_event(event);
+        }
+        let satellites = &self.satellites_dict;
+        let delivered = router.exchange(self.clock.now(), |node| {
+            satellites.get(&node).is_none_or(Satellite::can_relay)
+        });
+        let transfers: Vec<(u32, u32, f64)> = router
+            .last_transfers()
+            .iter()
+            .filter_map(|(from, to, id)| Some((*from, *to, router.message(*id)?.size)))
+            .collect();
+        if delivered > 0 {
+            println!(
+                "📬 {} delivered {} message(s) to the ground",
+                router.mode().name(),
+                delivered
+            );
+        }
+        for (from, to, size) in transfers {
+            let data_rate = self.link_data_rate(from, to);
+            self.spend_link_energy(from, to, size, data_rate);
+        }
+    }
+
+    /**
+     * Rate of the planned contact open on the link right now, where it is in its rate profile,
+     * else what the current snapshot gives it, else the configured ceiling.
+     */
+    fn link_data_rate(&self, from: u32, to: u32) -> f64 {
+        let now = self.clock.now();
+        self.contact_graph
+            .active_contacts_from(from, now)
+            .into_iter()
+            .find(|contact| contact.destination == to)
+            .map(|contact| contact.rate_at(now))
+            .or_else(|| {
+                self.satellites_network
+                    .get(&from)
+                    .and_then(|contacts| contacts.iter().find(|contact| contact.destination == to))
+                    .map(|contact| contact.data_rate)
+            })
+            .unwrap_or(if is_ground_station(to) || is_ground_station(from) {
+                self.plan_config.ground_data_rate
+            } else {
+                self.plan_config.isl_data_rate
+            })
+    }
+
+    pub fn bundles(&self) -> &[Bundle] {
+        &self.bundles
+    }
+
+    /**
+     * Runs every unfinished bundle's state machine against the current contact plan. The plan
+     * is re-predicted periodically while anything is in flight, which is where lost links come
+     * from: a hop whose contact no longer appears in the plan fails over to an alternate route.
+     */
+    pub fn forward_bundles(&mut self) {
+        if self.bundles.iter().all(|bundle| bundle.is_finished()) {
+            return;
+        }
+        let now = self.clock.now();
+        let refresh_due = self
+            .plan_refreshed_at
+            .is_none_or(|refreshed_at| now - refreshed_at >= DEFAULT_PLAN_REFRESH_INTERVAL);
+        if refresh_due {
+            let config = self.plan_config.clone();
+            if let Err(error) = self.refresh_contact_plan(&config) {
+                println!("❌ Contact plan not refreshed: {}", error);
+            }
+        }
+
+        let mut bundles = std::mem::take(&mut self.bundles);
+        let mut fragments = Vec::new();
+        for bundle in bundles.iter_mut().filter(|bundle| !bundle.is_finished()) {
+            self.step_bundle(bundle, &mut fragments);
+        }
+        bundles.append(&mut fragments); // they start moving on the next tick
+        self.bundles = bundles;
+    }
+
+    // Feeds one bundle the events that happened to it since the last tick
+    fn step_bundle(&mut self, bundle: &mut Bundle, fragments: &mut Vec<Bundle>) {
+        let now = self.clock.now();
+        for _ in 0..MAX_TRANSITIONS_PER_TICK {
+            let (time, event) = match bundle.state() {
+                CGRState::IDLE => (now, CGREvent::NewPacketArrived),
+                CGRState::DiscoverCommunicationLinks => {
+                    if self.usable_route(bundle).is_some() {
+                        (now, CGREvent::CommunicationLinksAvailable)
+                    } else if self.fragment(bundle, fragments) {
+                        (now, CGREvent::Fragmented)
+                    } else {
+                        (now, CGREvent::NoCommunicationLinksAvailable)
+                    }
+                }
+                CGRState::SelectRoute => {
+                    let Some(route) = self.usable_route(bundle) else {
+                        return;
+                    };
+                    self.follow_route(bundle, route);
+                    (now, CGREvent::RouteComputed)
+                }
+                CGRState::TransmitData | CGRState::Retransmit => {
+                    let hop = bundle
+                        .current_hop()
+                        .map(|hop| (hop.from as u32, hop.to as u32, hop.bandwidth));
+                    if bundle.complete_hop(now) {
+                        if let Some((from, to, data_rate)) = hop {
+                            self.spend_link_energy(from, to, bundle.size, data_rate);
+                        }
+                        let event =
+                            if reaches(bundle.current_node as u32, bundle.destination as u32) {
+                                CGREvent::DataSent
+                            } else {
+                                CGREvent::HopCompleted
+                            };
+                        (bundle.ready_at, event)
+                    } else if !self.is_hop_planned(bundle) {
+                        self.release_route(bundle);
+                        (now, CGREvent::CommunicationLinkLost)
+                    } else {
+                        return;
+                    }
+                }
+                CGRState::HoldData => {
+                    if now >= bundle.expires_at {
+                        (now, CGREvent::Timeout)
+                    } else if let Some(route) = self.usable_route(bundle) {
+                        self.follow_route(bundle, route);
+                        (now, CGREvent::CommunicationLinkRestored)
+                    } else if self.fragment(bundle, fragments) {
+                        (now, CGREvent::Fragmented)
+                    } else {
+                        return;
+                    }
+                }
+                CGRState::Delivered | CGRState::Failed => return,
+            };
+            bundle.transition(time, event);
+        }
+    }
+
+    /**
+     * Best cached route from wherever the bundle currently is that can still carry it, or a
+     * fresh search for one if none of the cached routes has room left.
+     */
+    fn usable_route(&mut self, bundle: &Bundle) -> Option<Route> {
+        let ready_at = bundle.ready_at.max(self.clock.now());
+        let (source, destination) = (bundle.current_node as u32, bundle.destination as u32);
+        self.usable_routes(source, destination, bundle.size, ready_at)
+            .into_iter()
+            .next()
+            .or_else(|| self.find_route(source, destination, bundle.size))
+    }
+
+    /**
+     * When no route can take the whole bundle, cuts it down to what the roomiest route can
+     * still carry and queues the rest as a new fragment. Returns false if not even a minimum
+     * sized fragment fits anywhere, in which case the bundle has to wait.
+     */
+    fn fragment(&mut self, bundle: &mut Bundle, fragments: &mut Vec<Bundle>) -> bool {
+        let now = self.clock.now();
+        let ready_at = bundle.ready_at.max(now);
+        let routes = self
+            .routes_to(bundle.current_node as u32, bundle.destination as u32)
+            .to_vec();
+        let capacity = routes
+            .iter()
+            .filter(|route| route.expiry > ready_at)
+            .map(|route| self.route_volume(route, ready_at))
+            .fold(0.0, f64::max);
+        if capacity < MIN_FRAGMENT_SIZE || capacity >= bundle.size {
+            return false;
+        }
+
+        let mut remainder = bundle.split(self.next_bundle_id, capacity);
+        self.next_bundle_id += 1;
+        remainder.transition(now, CGREvent::NewPacketArrived);
+        fragments.push(remainder);
+        true
+    }
+
+    // Sends the bundle down `route`, reserving its size on every contact along the way
+    fn follow_route(&mut self, bundle: &mut Bundle, route: Route) {
+        bundle.follow(route);
+        let Some(route) = &bundle.route else {
+            return;
+        };
+        let mut ready_at = bundle.ready_at;
+        for hop in &route.hops {
+            let departure = ready_at.max(hop.start_time).min(hop.end_time);
+            self.contact_graph.book(
+                hop.from as u32,
+                hop.to as u32,
+                Booking {
+                    owner: bundle.id,
+                    time: departure,
+                    volume: bundle.size,
+                },
+            );
+            ready_at = departure + bundle.size / hop.bandwidth.max(f64::EPSILON) + hop.latency;
+        }
+    }
+
+    // Gives back the bookings for the hops the bundle won't be taking after all
+    fn release_route(&mut self, bundle: &Bundle) {
+        let Some(route) = &bundle.route else {
+            return;
+        };
+        for hop in route.hops.iter().skip(bundle.hop_index) {
+            self.contact_graph
+                .release(bundle.id, hop.from as u32, hop.to as u32);
+        }
+    }
+
+    /**
+     * Whether the contact the bundle is being sent over is still in the plan for the rest of
+     * the transmission. Once the last bit is out, only the light time is left to wait for.
+     */
+    fn is_hop_planned(&self, bundle: &Bundle) -> bool {
+        let (Some(hop), Some((departure, transmitted))) = (bundle.current_hop(), bundle.hop_window)
+        else {
+            return false;
+        };
+        if transmitted <= self.clock.now() {
+            return true;
         }
+        // Pair contacts don't overlap, so only the one still open at the end can cover the hop
+        self.contact_graph
+            .next_contact(
+                hop.from as u32,
+                hop.to as u32,
+                transmitted - LINK_CHECK_TOLERANCE,
+            )
+            .is_some_and(|contact| contact.start_time <= departure + LINK_CHECK_TOLERANCE)
     }
 
-    pub fn generate_satellite_network(&mut self, num_satellites: usize) -> () {
+    /**
+     * Loads a CelesTrak-style TLE file and adds one SGP4-propagated satellite per element set,
+     * keyed by NORAD catalog number. Element sets SGP4 can't handle are skipped with a warning.
+     * Returns how many satellites were added.
+     */
+    pub fn load_tle_constellation<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, TleError> {
+        let element_sets = load_tle_file(path)?;
+        let mut satellites = Vec::new();
+
+        for tle in &element_sets {
+            match Satellite::from_tle(tle, &self.clock.epoch()) {
+                Ok(satellite) => {
+                    println!("🛰️ {}", tle);
+                    satellites.push(satellite);
+                }
+                Err(error) => println!(
+                    "⚠️ Skipping {} ({}): {}",
+                    tle.name.as_deref().unwrap_or("unnamed"),
+                    tle.catalog_number,
+                    error
+                ),
+            }
+        }
+        self.add_satellites(&satellites);
+        Ok(satellites.len())
+    }
+
+    pub fn generate_satellite_network(&mut self, num_satellites: usize) {
         // Retrieve the lazily-initialized thread-local random number generator.
         let mut rng = rand::thread_rng();
         let mut satellites = Vec::new();
 
         for num in 0..num_satellites {
             let id = num; // for now using num, no need for uuid
-            let latitude = rng.gen_range(-90.0..90.0);
-            let longitude = rng.gen_range(-180.0..180.0);
             let altitude = rng.gen_range(400.0..2000.0); // LEO range in km
-            let velocity = 7.8; // Constant LEO orbital velocity
-            satellites.push(Satellite::new(
+            let elements = OrbitalElements {
+                eccentricity: rng.gen_range(0.0..0.01), // near-circular LEO
+                ..OrbitalElements::circular(
+                    altitude,
+                    rng.gen_range(0.0_f64..98.0).to_radians(),
+                    rng.gen_range(0.0_f64..360.0).to_radians(),
+                    rng.gen_range(0.0_f64..360.0).to_radians(),
+                )
+            };
+            satellites.push(Satellite::from_elements(
                 id as u32,
-                (latitude, longitude),
-                altitude,
-                velocity,
+                elements,
+                &self.clock.epoch(),
             ));
         }
         self.add_satellites(&satellites);
@@ -45,7 +810,33 @@ impl SatelliteNetwork {
     pub fn update_satellite_network(&mut self) {
         println!("🔄 Updating satellite communication graph...");
         let updated_graph: HashMap<u32, Vec<Contact>> =
-            create_satellites_map(&self.satellites_dict);
+            match create_satellites_map(&self.satellites_dict, &self.clock, &self.plan_config) {
+                Ok(graph) => graph,
+                Err(error) => {
+                    println!("❌ Satellite graph not updated: {}", error);
+                    return;
+                }
+            };
+        let now = self.clock.now();
+        self.connection_events.clear();
+
+        // Satellites that left the network lose every link they had
+        let departed: Vec<u32> = self
+            .satellites_network
+            .keys()
+            .filter(|id| !updated_graph.contains_key(id))
+            .copied()
+            .collect();
+        for sat_id in departed {
+            let contacts = self.satellites_network.remove(&sat_id).unwrap_or_default();
+            for contact in contacts.iter().filter(|c| sat_id < c.destination) {
+                self.connection_events.push(ConnectionEvent::lost(
+                    now,
+                    sat_id,
+                    contact.destination,
+                ));
+            }
+        }
 
         // Make ASYNC
         for (sat_id, new_contacts) in updated_graph {
@@ -61,6 +852,16 @@ impl SatelliteNetwork {
                 let new_connections: Vec<&u32> =
                     new_destinations.difference(&old_destinations).collect();
 
+                // Both ends see the change, the lower id reports it
+                for id in lost_connections.iter().filter(|id| sat_id < ***id) {
+                    self.connection_events
+                        .push(ConnectionEvent::lost(now, sat_id, **id));
+                }
+                for id in new_connections.iter().filter(|id| sat_id < ***id) {
+                    self.connection_events
+                        .push(ConnectionEvent::established(now, sat_id, **id));
+                }
+
                 if !lost_connections.is_empty() {
                     println!(
                         "❌ Satellite {} lost connections with {:?}",
@@ -83,47 +884,653 @@ impl SatelliteNetwork {
                         .map(|c| c.destination)
                         .collect::<Vec<_>>()
                 );
+                for contact in new_contacts.iter().filter(|c| sat_id < c.destination) {
+                    self.connection_events.push(ConnectionEvent::established(
+                        now,
+                        sat_id,
+                        contact.destination,
+                    ));
+                }
                 self.satellites_network.insert(sat_id, new_contacts); // add new sat as neighbors
             }
         }
+
+        let mut ground_links = HashSet::new();
+        for station in &self.ground_stations {
+            for sat in self.satellites_dict.values() {
+                if station.can_see(sat) {
+                    ground_links.insert((sat.id, station.id));
+                }
+            }
+        }
+        for (sat_id, station_id) in ground_links.difference(&self.ground_links) {
+            println!(
+                "📡 Satellite {} can see ground station {}",
+                sat_id, station_id
+            );
+            self.connection_events
+                .push(ConnectionEvent::established(now, *sat_id, *station_id));
+        }
+        for (sat_id, station_id) in self.ground_links.difference(&ground_links) {
+            println!(
+                "📴 Satellite {} lost sight of ground station {}",
+                sat_id, station_id
+            );
+            self.connection_events
+                .push(ConnectionEvent::lost(now, *sat_id, *station_id));
+        }
+        self.ground_links = ground_links;
     }
 
-    pub fn find_best_relay(
-        &self,
-        source_satellite_id: u32,
-        ground_position: (f64, f64),
-    ) -> Option<u32> {
-        let source = self.satellites_dict.get(&source_satellite_id)?;
-        let mut best_relay = None;
-        let mut best_score = f64::MAX;
+    pub fn ground_stations(&self) -> &[GroundStation] {
+        &self.ground_stations
+    }
+
+    pub fn ground_station(&self, id: u32) -> Option<&GroundStation> {
+        self.ground_stations.iter().find(|station| station.id == id)
+    }
+
+    /**
+     * Adds a station to the ground segment and returns the node id it was given, unless the
+     * segment already has MAX_GROUND_STATIONS. Contacts with it show up once the contact plan
+     * is next refreshed.
+     */
+    pub fn add_ground_station(
+        &mut self,
+        mut station: GroundStation,
+    ) -> Result<u32, GroundStationError> {
+        let index = self.ground_stations.len() as u32;
+        if index >= MAX_GROUND_STATIONS {
+            return Err(GroundStationError::TooMany {
+                max: MAX_GROUND_STATIONS,
+            });
+        }
+        station.id = FIRST_GROUND_STATION_ID + index;
+        self.ground_stations.push(station);
+        self.satellites_dict
+            .values_mut()
+            .for_each(Satellite::forget_next_pass);
+        Ok(FIRST_GROUND_STATION_ID + index)
+    }
+
+    // Replaces the whole ground segment, e.g. to drop the default station
+    pub fn set_ground_stations(
+        &mut self,
+        stations: Vec<GroundStation>,
+    ) -> Result<(), GroundStationError> {
+        if stations.len() > MAX_GROUND_STATIONS as usize {
+            return Err(GroundStationError::TooMany {
+                max: MAX_GROUND_STATIONS,
+            });
+        }
+        self.ground_stations.clear();
+        self.ground_links.clear();
+        for station in stations {
+            self.add_ground_station(station)?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Every pass of `satellite_id` over each ground station in the next `horizon` seconds,
+     * in order of acquisition.
+     */
+    pub fn predict_passes(&self, satellite_id: u32, horizon: f64) -> Vec<Pass> {
+        let Some(sat) = self.satellites_dict.get(&satellite_id) else {
+            return Vec::new();
+        };
+        let now = self.clock.now();
+        let mut passes: Vec<Pass> = self
+            .ground_stations
+            .iter()
+            .flat_map(|station| station.predict_passes(sat, now, horizon))
+            .collect();
+        passes.sort_by(|a, b| a.aos.total_cmp(&b.aos));
+        passes
+    }
+
+    /**
+     * Propagates every satellite numerically with `integrator` from now on, under J2 and drag,
+     * those joining later included. Their current states are the initial conditions.
+     */
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = Some(integrator);
+        self.satellites_dict
+            .values_mut()
+            .for_each(|sat| sat.use_integrator(integrator));
+    }
+
+    /**
+     * Fits a satellite with a different radio. Link budgets follow from the next network update
+     * and contact plan refresh on, which the next forwarding or scheduling call makes sure of.
+     * Returns false if there is no such satellite.
+     */
+    pub fn set_radio(&mut self, satellite_id: u32, radio: Radio) -> bool {
+        let Some(sat) = self.satellites_dict.get_mut(&satellite_id) else {
+            return false;
+        };
+        sat.radio = radio;
+        self.plan_refreshed_at = None;
+        true
+    }
+
+    // Turns orbit maintenance on for every satellite that can maneuver, or off with None
+    pub fn set_station_keeping(&mut self, policy: Option<StationKeeping>) {
+        self.station_keeping = policy;
+        if policy.is_none() {
+            self.planned_maneuvers.clear();
+        }
+    }
+
+    // Burns scheduled for the next ascending node of each satellite that sank out of its deadband
+    pub fn planned_maneuvers(&self) -> &[Maneuver] {
+        &self.planned_maneuvers
+    }
+
+    pub fn maneuvers(&self) -> &[Maneuver] {
+        &self.maneuvers
+    }
+
+    pub fn reentries(&self) -> &[(u32, f64)] {
+        &self.reentries
+    }
+
+    /**
+     * Takes a satellite out of the network along with its planned contacts and burns. Its
+     * links are reported lost on the next network update.
+     */
+    pub fn remove_satellite(&mut self, satellite_id: u32) -> Option<Satellite> {
+        let sat = self.satellites_dict.remove(&satellite_id)?;
+        self.contact_graph.retain(|contact| {
+            contact.source != satellite_id && contact.destination != satellite_id
+        });
+        self.planned_maneuvers
+            .retain(|maneuver| maneuver.satellite != satellite_id);
+        Some(sat)
+    }
+
+    // Every shadow boundary `satellite_id` crosses in the next `horizon` seconds
+    pub fn predict_eclipses(&self, satellite_id: u32, horizon: f64) -> Vec<EclipseEvent> {
+        self.satellites_dict
+            .get(&satellite_id)
+            .map_or_else(Vec::new, |sat| {
+                sat.predict_eclipses(self.clock.now(), horizon, DEFAULT_ECLIPSE_SEARCH_STEP)
+            })
+    }
+
+    /**
+     * Frequency-correction tables for every contact `satellite_id` transmits on within the
+     * contact plan's horizon, ground passes and ISLs alike, on the satellite's own carrier.
+     * The plan is refreshed first if it has never been predicted.
+     */
+    pub fn doppler_tables(
+        &mut self,
+        satellite_id: u32,
+        step: f64,
+    ) -> Result<Vec<DopplerTable>, ContactPlanError> {
+        let step = check_sample_step(step)?;
+        if self.plan_refreshed_at.is_none() {
+            let plan_config = self.plan_config.clone();
+            self.refresh_contact_plan(&plan_config)?;
+        }
+        let Some(frequency) = self
+            .satellites_dict
+            .get(&satellite_id)
+            .map(|sat| sat.radio.frequency)
+        else {
+            return Ok(Vec::new());
+        };
+        let now = self.clock.now();
+        let mut contacts: Vec<&Contact> = self
+            .contact_graph
+            .contacts_from(satellite_id)
+            .filter(|contact| contact.overlaps(now, now + self.plan_config.horizon))
+            .collect();
+        contacts.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+
+        let state_at = |id: u32, time: f64| match self.satellites_dict.get(&id) {
+            Some(sat) => Some(sat.state_at(time)),
+            None => self
+                .ground_station(id)
+                .map(|station| station.state_at(&self.clock.epoch_at(time))),
+        };
+        contacts
+            .into_iter()
+            .filter_map(|contact| doppler_table(contact, frequency, step, state_at).transpose())
+            .collect()
+    }
 
-        for (_, sat) in &self.satellites_dict {
-            if sat.id == source_satellite_id {
+    /**
+     * Plans who downlinks to which station over the contact plan's horizon, from every
+     * satellite's queued data and the predicted passes, and executes the plan from the next
+     * tick on. The plan is refreshed first if it has never been predicted.
+     */
+    pub fn schedule_downlinks(
+        &mut self,
+        config: &SchedulerConfig,
+    ) -> Result<&DownlinkSchedule, ContactPlanError> {
+        if self.plan_refreshed_at.is_none() {
+            let plan_config = self.plan_config.clone();
+            self.refresh_contact_plan(&plan_config)?;
+        }
+        let now = self.clock.now();
+        let passes: Vec<Contact> = self
+            .contact_graph
+            .contacts_overlapping(now, now + self.plan_config.horizon)
+            .into_iter()
+            .filter(|contact| is_ground_station(contact.destination))
+            .cloned()
+            .collect();
+        let queued: HashMap<u32, f64> = self
+            .satellites_dict
+            .values()
+            .map(|sat| (sat.id, sat.storage_on_board * STORAGE_UNIT_BITS))
+            .collect();
+
+        Ok(self.downlink_schedule.insert(schedule_downlinks(
+            &passes,
+            &queued,
+            now,
+            now + self.plan_config.horizon,
+            config,
+        )))
+    }
+
+    // Drains on-board storage for every scheduled downlink that ran during the last step
+    fn execute_downlinks(&mut self) {
+        let Some(schedule) = self.downlink_schedule.as_mut() else {
+            return;
+        };
+        let now = self.clock.now();
+        let previous = now - self.clock.step();
+        for window in schedule.windows_mut() {
+            let overlap = window.end.min(now) - window.start.max(previous);
+            if overlap <= 0.0 || window.sent >= window.volume {
                 continue;
             }
-            let score = sat.calculate_relay_score(ground_position);
-            if score < best_score {
-                best_score = score;
-                best_relay = Some(sat.id);
+            let Some(sat) = self.satellites_dict.get_mut(&window.satellite) else {
+                continue;
+            };
+            let capacity = window
+                .rate_profile
+                .volume_between(window.start.max(previous), window.end.min(now));
+            let bits = capacity
+                .min(window.volume - window.sent)
+                .min(sat.storage_on_board * STORAGE_UNIT_BITS);
+            if capacity > 0.0 {
+                sat.power.transmit(overlap * bits / capacity);
             }
+            sat.storage_on_board -= bits / STORAGE_UNIT_BITS;
+            window.sent += bits;
+            println!(
+                "⬇️ Satellite {} downlinked {:.1} kB to ground station {} ({:.1} kB left on board)",
+                window.satellite,
+                bits / STORAGE_UNIT_BITS,
+                window.station,
+                sat.storage_on_board
+            );
         }
-        best_relay
     }
 
-    fn add_satellites(&mut self, satellites: &Vec<Satellite>) {
-        satellites.into_iter().for_each(|sat| {
-            self.satellites_dict.insert(sat.id, sat.clone());
+    pub fn relay_strategy(&self) -> &dyn RelayStrategy {
+        self.relay_strategy.as_ref()
+    }
+
+    /**
+     * Swaps the strategy `find_best_relay` picks relays with. Nothing else about the network
+     * changes, so strategies can be compared one after the other on exactly the same state.
+     */
+    pub fn set_relay_strategy(&mut self, strategy: Box<dyn RelayStrategy>) {
+        self.relay_strategy = strategy;
+    }
+
+    // Best relay towards the nearest ground station for `source_satellite_id`, see `set_relay_strategy`
+    pub fn find_best_relay(&self, source_satellite_id: u32) -> Option<u32> {
+        let source = self.satellites_dict.get(&source_satellite_id)?;
+        let station = nearest_station(&self.ground_stations, source)?;
+        let satellites: Vec<&Satellite> = self.satellites_dict.values().collect();
+        self.relay_strategy
+            .find_best_relay(source, &satellites, &station.location)
+    }
+
+    /**
+     * Downlink paths from `source` over the current snapshot graph: the best path under
+     * `algorithm` plus up to `max_backups` node-disjoint alternatives.
+     */
+    pub fn find_downlink_paths(
+        &self,
+        source: u32,
+        algorithm: SearchAlgorithm,
+        max_backups: usize,
+    ) -> Option<DownlinkPaths> {
+        pathfinding::find_downlink_paths(
+            &self.satellites_network,
+            &self.satellites_dict,
+            source,
+            &self.ground_stations,
+            algorithm,
+            max_backups,
+        )
+    }
+
+    fn add_satellites(&mut self, satellites: &[Satellite]) {
+        satellites.iter().for_each(|sat| {
+            self.add_satellite(sat);
         });
         println!("{:?}", satellites.len());
     }
 
+    // Satellites joining mid-run are brought up to the current simulation time
     fn add_satellite(&mut self, sat: &Satellite) {
-        self.satellites_dict.insert(sat.id, sat.clone());
+        let mut sat = sat.clone();
+        sat.propagate_to(self.clock.now());
+        if let Some(integrator) = self.integrator {
+            sat.use_integrator(integrator);
+        }
+        self.satellites_dict.insert(sat.id, sat);
+    }
+
+    /**
+     * Flies the burns that have come due, then plans a raise for every satellite the
+     * station-keeping policy finds too low, at its next ascending node.
+     */
+    fn keep_stations(&mut self) {
+        let now = self.clock.now();
+        let (due, pending): (Vec<Maneuver>, Vec<Maneuver>) =
+            std::mem::take(&mut self.planned_maneuvers)
+                .into_iter()
+                .partition(|maneuver| maneuver.time <= now);
+        self.planned_maneuvers = pending;
+        for maneuver in due {
+            let Some(sat) = self.satellites_dict.get_mut(&maneuver.satellite) else {
+                continue;
+            };
+            let from = sat.elements.semi_major_axis;
+            let to = EARTH_RADIUS + maneuver.target_altitude * 1000.0;
+            let needed = hohmann_delta_v(from, to);
+            let delivered = sat.propulsion.burn(needed);
+            if needed > 0.0 {
+                // Short on propellant, the raise falls short in proportion
+                sat.update_satellite_altitude((to - from) / 1000.0 * delivered / needed);
+            }
+            println!(
+                "🚀 Satellite {} burned {:.2} m/s up to {:.1} km, {:.3} kg of propellant left",
+                sat.id,
+                delivered,
+                sat.mean_altitude(),
+                sat.propulsion.propellant
+            );
+            self.maneuvers.push(Maneuver {
+                time: now,
+                delta_v: delivered,
+                ..maneuver
+            });
+        }
+
+        let Some(policy) = self.station_keeping else {
+            return;
+        };
+        for sat in self.satellites_dict.values() {
+            if !sat.can_maneuver()
+                || !policy.needs_burn(sat.mean_altitude(), sat.target_altitude)
+                || self
+                    .planned_maneuvers
+                    .iter()
+                    .any(|maneuver| maneuver.satellite == sat.id)
+            {
+                continue;
+            }
+            let maneuver = Maneuver {
+                satellite: sat.id,
+                time: sat.next_ascending_node(now),
+                delta_v: hohmann_delta_v(
+                    sat.elements.semi_major_axis,
+                    EARTH_RADIUS + sat.target_altitude * 1000.0,
+                ),
+                target_altitude: sat.target_altitude,
+            };
+            println!(
+                "🗓️ Satellite {} is down to {:.1} km, planned a {:.2} m/s raise for {}",
+                sat.id,
+                sat.mean_altitude(),
+                maneuver.delta_v,
+                self.clock.epoch_at(maneuver.time)
+            );
+            self.planned_maneuvers.push(maneuver);
+        }
+    }
+
+    fn remove_reentered(&mut self) {
+        let now = self.clock.now();
+        let reentered: Vec<(u32, Option<Sgp4Error>)> = self
+            .satellites_dict
+            .values()
+            .filter(|sat| sat.has_reentered())
+            .map(|sat| (sat.id, sat.decayed.clone()))
+            .collect();
+        for (id, decayed) in reentered {
+            self.remove_satellite(id);
+            self.reentries.push((id, now));
+            match decayed {
+                Some(error) => println!(
+                    "🔥 Satellite {} lost at {}: {}",
+                    id,
+                    self.clock.now_epoch(),
+                    error
+                ),
+                None => println!(
+                    "🔥 Satellite {} reentered at {}",
+                    id,
+                    self.clock.now_epoch()
+                ),
+            }
+        }
+    }
+
+    fn update_sat_positions(&mut self) {
+        let now = self.clock.now();
+        self.eclipse_events.clear();
+        for sat in self.satellites_dict.values_mut() {
+            let elapsed = now - sat.sim_time;
+            if elapsed > 0.0 {
+                self.eclipse_events.extend(sat.predict_eclipses(
+                    sat.sim_time,
+                    elapsed,
+                    DEFAULT_ECLIPSE_SEARCH_STEP,
+                ));
+            }
+            sat.apply_drag(elapsed);
+            sat.propagate_to(now);
+            sat.update_power(elapsed);
+        }
+        self.eclipse_events
+            .sort_by(|a, b| a.time.total_cmp(&b.time));
+        for event in &self.eclipse_events {
+            println!(
+                "{} Satellite {} {} at {}",
+                if event.is_entry() { "🌑" } else { "🌕" },
+                event.satellite,
+                event.kind.name(),
+                self.clock.epoch_at(event.time)
+            );
+        }
     }
+}
 
-    fn update_sat_positions(&mut self, time_step: f64) {
-        self.satellites_dict.values_mut().for_each(|sat| {
-            sat.update_satellite_position(time_step);
+// The available station closest to the satellite, falling back to the closest one overall
+fn nearest_station<'a>(
+    stations: &'a [GroundStation],
+    satellite: &Satellite,
+) -> Option<&'a GroundStation> {
+    let ecef = satellite.ecef();
+    let range = |station: &&GroundStation| ecef.distance_to(&station.location.to_ecef());
+    stations
+        .iter()
+        .filter(|station| station.is_available_at(satellite.sim_time))
+        .min_by(|a, b| range(a).total_cmp(&range(b)))
+        .or_else(|| stations.iter().min_by(|a, b| range(a).total_cmp(&range(b))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    fn network() -> SatelliteNetwork {
+        let mut network = SatelliteNetwork::new();
+        network.generate_satellite_network(3);
+        network
+    }
+
+    #[test]
+    fn hooks_run_once_per_tick_against_the_new_state() {
+        let mut network = network();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&seen);
+        network.add_tick_hook(move |network: &mut SatelliteNetwork| {
+            let now = network.clock().now();
+            // Positions are brought up to date before the hooks run
+            assert!(network.satellites().values().all(|sat| sat.sim_time == now));
+            log.borrow_mut().push(now);
         });
+
+        network.run(30.0, 10.0).unwrap();
+        assert_eq!(*seen.borrow(), vec![10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    fn run_stops_once_the_clock_is_paused() {
+        let mut network = network();
+        network.add_tick_hook(PauseAt(20.0));
+
+        network.run(100.0, 10.0).unwrap();
+        assert!(network.clock().is_paused());
+        assert_eq!(network.clock().now(), 20.0);
+    }
+
+    // Moves the clock on to `time` and lets every bundle react to it
+    fn forward_to(network: &mut SatelliteNetwork, time: f64) {
+        network.clock.set_step(time - network.clock.now()).unwrap();
+        network.clock.tick();
+        network.forward_bundles();
+    }
+
+    // A network of satellites `ids` and the default station, with no contacts planned yet
+    fn quiet_network(ids: &[u32]) -> SatelliteNetwork {
+        let mut network = SatelliteNetwork::new();
+        let epoch = network.clock().epoch();
+        let satellites: Vec<Satellite> = ids
+            .iter()
+            .map(|id| {
+                let elements = OrbitalElements::circular(550.0, 0.9, 0.0, *id as f64);
+                Satellite::from_elements(*id, elements, &epoch)
+            })
+            .collect();
+        network.add_satellites(&satellites);
+        network
+    }
+
+    #[test]
+    fn bundles_follow_contacts_opening_closing_and_going_missing() {
+        let mut network = quiet_network(&[1, 2]);
+        let station = FIRST_GROUND_STATION_ID;
+        network.contact_graph.extend([
+            Contact::constant(1, 2, 20.0, 60.0, 1e6),
+            Contact::constant(2, station, 100.0, 140.0, 1e6),
+            Contact::constant(2, station, 300.0, 340.0, 1e6),
+        ]);
+        // The plan above is all there is, nothing re-predicts it during the test
+        network.plan_refreshed_at = Some(0.0);
+
+        network.send_bundle(1, GROUND_STATION_ID, 1e6).unwrap();
+        network.forward_bundles();
+        assert_eq!(*network.bundles()[0].state(), CGRState::TransmitData);
+
+        // Nothing to report until the first contact has opened and carried the bundle
+        forward_to(&mut network, 10.0);
+        assert_eq!(network.bundles()[0].log().len(), 3);
+        forward_to(&mut network, 30.0);
+
+        // The downlink it was heading for drops out of the plan, the later one takes over
+        network
+            .contact_graph
+            .retain(|contact| !(contact.source == 2 && contact.start_time == 100.0));
+        forward_to(&mut network, 50.0);
+        assert_eq!(*network.bundles()[0].state(), CGRState::Retransmit);
+
+        forward_to(&mut network, 310.0);
+        let bundle = &network.bundles()[0];
+        assert_eq!(*bundle.state(), CGRState::Delivered);
+        let events: Vec<(CGREvent, usize)> = bundle
+            .log()
+            .iter()
+            .map(|entry| (entry.event.clone(), entry.node))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (CGREvent::NewPacketArrived, 1),
+                (CGREvent::CommunicationLinksAvailable, 1),
+                (CGREvent::RouteComputed, 1),
+                (CGREvent::HopCompleted, 2),
+                (CGREvent::CommunicationLinkLost, 2),
+                (CGREvent::CommunicationLinkRestored, 2),
+                (CGREvent::DataSent, station as usize),
+            ]
+        );
+        // Hops complete when the data is through, one second of sending plus the light time
+        let times: Vec<f64> = bundle.log().iter().map(|entry| entry.time).collect();
+        assert!((times[3] - 21.01).abs() < 1e-9 && (times[6] - 301.01).abs() < 1e-9);
+        assert_eq!(times[4], 50.0);
+    }
+
+    #[test]
+    fn bundles_need_a_size_and_both_ends_in_the_network() {
+        let mut network = quiet_network(&[1, 2]);
+        for size in [0.0, -8e6, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                network.send_bundle(1, GROUND_STATION_ID, size),
+                Err(BundleError::Size(_))
+            ));
+        }
+        assert_eq!(
+            network.send_bundle(7, GROUND_STATION_ID, 8e6),
+            Err(BundleError::UnknownSource(7))
+        );
+        assert_eq!(
+            network.send_bundle(1, 7, 8e6),
+            Err(BundleError::UnknownDestination(7))
+        );
+        assert!(network.bundles().is_empty());
+
+        assert_eq!(network.send_bundle(1, 2, 8e6), Ok(0));
+        assert_eq!(network.send_bundle(1, FIRST_GROUND_STATION_ID, 8e6), Ok(1));
+    }
+
+    #[test]
+    fn run_rejects_durations_and_steps_that_never_end() {
+        let mut network = network();
+        for duration in [f64::NAN, f64::INFINITY, -1.0] {
+            assert!(matches!(
+                network.run(duration, 10.0),
+                Err(ClockError::Duration(_))
+            ));
+        }
+        assert!(matches!(network.run(30.0, 0.0), Err(ClockError::Step(_))));
+        assert_eq!(network.clock().now(), 0.0);
+    }
+
+    #[test]
+    fn doppler_tables_reject_steps_before_planning() {
+        let mut network = quiet_network(&[1, 2]);
+        for step in [0.0, -10.0, f64::NAN] {
+            assert!(matches!(
+                network.doppler_tables(1, step),
+                Err(ContactPlanError::SampleStep(_))
+            ));
+        }
+        assert!(network.plan_refreshed_at.is_none());
+        assert!(network.doppler_tables(1, 10.0).is_ok());
     }
 }
@@ -0,0 +1,235 @@
+use std::f64::consts::PI;
+
+use crate::common::{Vector3, EARTH_MU, EARTH_RADIUS};
+
+use super::{numerical::NumericalPropagator, sgp4::Sgp4};
+
+/**
+ * Classical (Keplerian) orbital elements describing a satellite's orbit around Earth.
+ * Angles are in radians and the semi-major axis is in meters. `epoch` is the simulation
+ * time (seconds) at which `mean_anomaly` is valid.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitalElements {
+    pub semi_major_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub raan: f64, // right ascension of the ascending node
+    pub argument_of_perigee: f64,
+    pub mean_anomaly: f64,
+    pub epoch: f64,
+}
+
+/**
+ * Position (meters) and velocity (m/s) of a satellite in the Earth-centered inertial frame.
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StateVector {
+    pub position: Vector3,
+    pub velocity: Vector3,
+}
+
+/**
+ * Selects how a satellite's state is computed from its elements. `Sgp4` satellites come from
+ * TLEs and carry the offset (seconds) from the simulation epoch to the element set epoch.
+ * `Numerical` ones integrate from the state they were switched over at, the elements stay
+ * as they were then.
+ */
+#[derive(Debug, Clone)]
+pub enum Propagator {
+    Kepler,
+    Sgp4 { model: Box<Sgp4>, epoch_offset: f64 },
+    Numerical(Box<NumericalPropagator>),
+}
+
+const KEPLER_TOLERANCE: f64 = 1e-12;
+const KEPLER_MAX_ITERATIONS: usize = 50;
+
+impl OrbitalElements {
+    /**
+     * Circular orbit at `altitude` km. For a circular orbit perigee is undefined, so the
+     * satellite's position along the orbit is given directly as the argument of latitude.
+     */
+    pub fn circular(altitude: f64, inclination: f64, raan: f64, argument_of_latitude: f64) -> Self {
+        Self {
+            semi_major_axis: EARTH_RADIUS + altitude * 1000.0,
+            eccentricity: 0.0,
+            inclination,
+            raan,
+            argument_of_perigee: 0.0,
+            mean_anomaly: argument_of_latitude,
+            epoch: 0.0,
+        }
+    }
+
+    // n = sqrt(mu / a^3), in rad/s
+    pub fn mean_motion(&self) -> f64 {
+        (EARTH_MU / self.semi_major_axis.powi(3)).sqrt()
+    }
+
+    pub fn mean_anomaly_at(&self, time: f64) -> f64 {
+        (self.mean_anomaly + self.mean_motion() * (time - self.epoch)).rem_euclid(2.0 * PI)
+    }
+
+    /**
+     * The same orbit resized to `semi_major_axis` meters at `time`. The elements are re-epoched
+     * there first, so the satellite carries on from where it was instead of jumping along the
+     * orbit by the change in mean motion since the old epoch.
+     */
+    pub fn with_semi_major_axis(&self, semi_major_axis: f64, time: f64) -> Self {
+        Self {
+            semi_major_axis,
+            mean_anomaly: self.mean_anomaly_at(time),
+            epoch: time,
+            ..*self
+        }
+    }
+
+    /**
+     * Propagates the orbit to `time` (simulation seconds) by solving Kepler's equation and
+     * rotating the perifocal position and velocity into the inertial frame.
+     */
+    pub fn state_at(&self, time: f64) -> StateVector {
+        let e = self.eccentricity;
+        let a = self.semi_major_axis;
+        let eccentric_anomaly = solve_kepler(self.mean_anomaly_at(time), e);
+        let (sin_e, cos_e) = eccentric_anomaly.sin_cos();
+
+        // Position and velocity in the perifocal frame (x towards perigee)
+        let sqrt_one_minus_e2 = (1.0 - e * e).sqrt();
+        let radius = a * (1.0 - e * cos_e);
+        let perifocal_position = Vector3::new(a * (cos_e - e), a * sqrt_one_minus_e2 * sin_e, 0.0);
+        let velocity_factor = (EARTH_MU * a).sqrt() / radius;
+        let perifocal_velocity = Vector3::new(
+            -velocity_factor * sin_e,
+            velocity_factor * sqrt_one_minus_e2 * cos_e,
+            0.0,
+        );
+
+        StateVector {
+            position: self.perifocal_to_inertial(&perifocal_position),
+            velocity: self.perifocal_to_inertial(&perifocal_velocity),
+        }
+    }
+
+    // R3(-raan) * R1(-i) * R3(-argp)
+    fn perifocal_to_inertial(&self, vector: &Vector3) -> Vector3 {
+        let (sin_raan, cos_raan) = self.raan.sin_cos();
+        let (sin_i, cos_i) = self.inclination.sin_cos();
+        let (sin_w, cos_w) = self.argument_of_perigee.sin_cos();
+
+        let x = (cos_raan * cos_w - sin_raan * sin_w * cos_i) * vector.x
+            + (-cos_raan * sin_w - sin_raan * cos_w * cos_i) * vector.y;
+        let y = (sin_raan * cos_w + cos_raan * sin_w * cos_i) * vector.x
+            + (-sin_raan * sin_w + cos_raan * cos_w * cos_i) * vector.y;
+        let z = (sin_w * sin_i) * vector.x + (cos_w * sin_i) * vector.y;
+
+        Vector3::new(x, y, z)
+    }
+}
+
+/**
+ * Solves Kepler's equation M = E - e * sin(E) for the eccentric anomaly E using
+ * Newton-Raphson iteration.
+ */
+pub fn solve_kepler(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    // Starting from M converges for small e, starting from PI is safer for high e
+    let mut eccentric_anomaly = if eccentricity < 0.8 { mean_anomaly } else { PI };
+
+    for _ in 0..KEPLER_MAX_ITERATIONS {
+        let residual = eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly;
+        let step = residual / (1.0 - eccentricity * eccentric_anomaly.cos());
+        eccentric_anomaly -= step;
+        if step.abs() < KEPLER_TOLERANCE {
+            break;
+        }
+    }
+
+    eccentric_anomaly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kepler_residual(mean_anomaly: f64, eccentricity: f64) -> f64 {
+        let eccentric_anomaly = solve_kepler(mean_anomaly, eccentricity);
+        eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly
+    }
+
+    #[test]
+    fn kepler_is_solved_across_the_orbit() {
+        for eccentricity in [0.0, 0.1, 0.5, 0.79, 0.8] {
+            for step in 0..36 {
+                let mean_anomaly = step as f64 * 10f64.to_radians();
+                assert!(kepler_residual(mean_anomaly, eccentricity).abs() < 1e-10);
+            }
+        }
+        // Circular orbits have nothing to solve
+        assert_eq!(solve_kepler(1.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn kepler_converges_for_high_eccentricity() {
+        // Near perigee the Newton steps are steep, which is where a start at M overshoots
+        for eccentricity in [0.9, 0.97, 0.99] {
+            for mean_anomaly in [1e-3, 0.05, 0.5, PI, 2.0 * PI - 0.05] {
+                assert!(
+                    kepler_residual(mean_anomaly, eccentricity).abs() < 1e-10,
+                    "e = {}, M = {}",
+                    eccentricity,
+                    mean_anomaly
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn circular_orbit_keeps_its_radius_and_period() {
+        let elements = OrbitalElements::circular(500.0, 0.9, 0.3, 0.0);
+        let radius = EARTH_RADIUS + 500_000.0;
+        let period = 2.0 * PI * (radius.powi(3) / EARTH_MU).sqrt();
+        let start = elements.state_at(0.0);
+
+        for step in 0..12 {
+            let state = elements.state_at(step as f64 * period / 12.0);
+            assert!((state.position.norm() - radius).abs() < 1e-3);
+            assert!((state.velocity.norm() - (EARTH_MU / radius).sqrt()).abs() < 1e-6);
+        }
+
+        // Half a period puts it on the opposite side, a whole one back where it started
+        let half = elements.state_at(period / 2.0).position;
+        assert!((half + start.position).norm() < 1e-3);
+        let whole = elements.state_at(period).position;
+        assert!(whole.distance_to(&start.position) < 1e-3);
+    }
+
+    #[test]
+    fn eccentric_orbit_runs_between_perigee_and_apogee() {
+        let elements = OrbitalElements {
+            semi_major_axis: 26_600_000.0,
+            eccentricity: 0.74,
+            inclination: 63.4f64.to_radians(),
+            raan: 0.0,
+            argument_of_perigee: 270f64.to_radians(),
+            mean_anomaly: 0.0,
+            epoch: 0.0,
+        };
+        let period = 2.0 * PI / elements.mean_motion();
+
+        let perigee = elements.state_at(0.0);
+        let apogee = elements.state_at(period / 2.0);
+        assert!((perigee.position.norm() - 26_600_000.0 * 0.26).abs() < 1e-3);
+        assert!((apogee.position.norm() - 26_600_000.0 * 1.74).abs() < 1e-3);
+
+        // Energy stays put all the way round
+        let energy = |state: StateVector| {
+            state.velocity.dot(&state.velocity) / 2.0 - EARTH_MU / state.position.norm()
+        };
+        let expected = -EARTH_MU / (2.0 * elements.semi_major_axis);
+        for step in 0..24 {
+            let state = elements.state_at(step as f64 * period / 24.0);
+            assert!((energy(state) / expected - 1.0).abs() < 1e-9);
+        }
+    }
+}
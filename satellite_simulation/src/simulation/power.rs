@@ -0,0 +1,149 @@
+/*
+ * Electrical power subsystem. The battery is charged by the solar array in proportion to how
+ * much of the Sun the satellite sees, so output fades through penumbra, and drained by what
+ * is switched on: the bus is always on, the payload while it runs, and the radio while it
+ * transmits or receives. Relaying someone else's data costs radio time on both ends, so a
+ * satellite running low refuses relay duty until the Sun has topped it up again.
+ */
+
+// A 6U-class bus
+pub const DEFAULT_BATTERY_CAPACITY: f64 = 40.0; // Wh
+pub const DEFAULT_SOLAR_ARRAY_POWER: f64 = 20.0; // W in full sun
+pub const DEFAULT_IDLE_LOAD: f64 = 4.0; // W
+pub const DEFAULT_PAYLOAD_LOAD: f64 = 6.0; // W
+pub const DEFAULT_TRANSMIT_LOAD: f64 = 12.0; // W
+pub const DEFAULT_RECEIVE_LOAD: f64 = 2.0; // W
+pub const DEFAULT_MIN_RELAY_CHARGE: f64 = 0.3; // state of charge
+pub const DEFAULT_INITIAL_CHARGE: f64 = 1.0; // state of charge, batteries are topped up at launch
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerConfig {
+    pub battery_capacity: f64,  // Wh
+    pub solar_array_power: f64, // W
+    pub idle_load: f64,         // W, always drawn
+    pub payload_load: f64,      // W while the payload is on
+    pub transmit_load: f64,     // W while transmitting, on top of the rest
+    pub receive_load: f64,      // W while receiving, on top of the rest
+    pub min_relay_charge: f64,  // state of charge below which relay duty is refused
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            battery_capacity: DEFAULT_BATTERY_CAPACITY,
+            solar_array_power: DEFAULT_SOLAR_ARRAY_POWER,
+            idle_load: DEFAULT_IDLE_LOAD,
+            payload_load: DEFAULT_PAYLOAD_LOAD,
+            transmit_load: DEFAULT_TRANSMIT_LOAD,
+            receive_load: DEFAULT_RECEIVE_LOAD,
+            min_relay_charge: DEFAULT_MIN_RELAY_CHARGE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerSystem {
+    pub config: PowerConfig,
+    pub payload_on: bool,
+    charge: f64,   // Wh
+    sunlight: f64, // fraction of the Sun's disk the array sees
+}
+
+impl PowerSystem {
+    pub fn new(config: PowerConfig, state_of_charge: f64) -> Self {
+        Self {
+            charge: config.battery_capacity * state_of_charge.clamp(0.0, 1.0),
+            config,
+            payload_on: true,
+            sunlight: 1.0,
+        }
+    }
+
+    // Between 0 (flat) and 1 (full)
+    pub fn state_of_charge(&self) -> f64 {
+        self.charge / self.config.battery_capacity
+    }
+
+    pub fn is_in_sunlight(&self) -> bool {
+        self.sunlight > 0.0
+    }
+
+    pub fn can_relay(&self) -> bool {
+        self.state_of_charge() >= self.config.min_relay_charge
+    }
+
+    // Net power in W the array and the always-on loads leave for the battery
+    pub fn net_power(&self) -> f64 {
+        let generated = self.config.solar_array_power * self.sunlight;
+        let payload = if self.payload_on {
+            self.config.payload_load
+        } else {
+            0.0
+        };
+        generated - self.config.idle_load - payload
+    }
+
+    // Runs the array at `sunlight` of full output, and the always-on loads, for `seconds`
+    pub fn update(&mut self, seconds: f64, sunlight: f64) {
+        self.sunlight = sunlight.clamp(0.0, 1.0);
+        self.apply(self.net_power(), seconds);
+    }
+
+    pub fn transmit(&mut self, seconds: f64) {
+        self.apply(-self.config.transmit_load, seconds);
+    }
+
+    pub fn receive(&mut self, seconds: f64) {
+        self.apply(-self.config.receive_load, seconds);
+    }
+
+    fn apply(&mut self, watts: f64, seconds: f64) {
+        self.charge =
+            (self.charge + watts * seconds / 3600.0).clamp(0.0, self.config.battery_capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eclipse_drains_the_battery_below_relay_charge() {
+        let mut power = PowerSystem::new(PowerConfig::default(), 0.5);
+        assert!(power.can_relay());
+
+        // 4 W bus and 6 W payload for an hour out of 40 Wh
+        power.update(3600.0, 0.0);
+        assert!(!power.is_in_sunlight());
+        assert!((power.state_of_charge() - 0.25).abs() < 1e-12);
+        assert!(!power.can_relay());
+
+        power.update(10.0 * 3600.0, 0.0);
+        assert_eq!(power.state_of_charge(), 0.0);
+    }
+
+    #[test]
+    fn array_output_follows_the_lit_fraction() {
+        let mut power = PowerSystem::new(PowerConfig::default(), 0.5);
+        // Half the Sun exactly covers the loads
+        power.update(3600.0, 0.5);
+        assert!(power.is_in_sunlight());
+        assert_eq!(power.net_power(), 0.0);
+        assert_eq!(power.state_of_charge(), 0.5);
+
+        power.payload_on = false;
+        power.update(10.0 * 3600.0, 1.0);
+        assert_eq!(power.state_of_charge(), 1.0);
+    }
+
+    #[test]
+    fn radio_time_costs_charge() {
+        let config = PowerConfig::default();
+        let mut power = PowerSystem::new(config, 0.5);
+        power.transmit(300.0);
+        power.receive(1800.0);
+        let used = config.transmit_load * 300.0 / 3600.0 + config.receive_load * 0.5;
+        let expected = 0.5 - used / config.battery_capacity;
+        assert!((power.state_of_charge() - expected).abs() < 1e-12);
+    }
+}
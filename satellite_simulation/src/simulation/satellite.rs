@@ -1,196 +1,420 @@
+use std::f64::consts::PI;
+
 use rand::Rng;
 
-use crate::common::{calculate_angular_velocity, calculate_euclid_distance};
+use crate::common::{EARTH_MU, EARTH_RADIUS};
+use crate::communication::link_budget::Radio;
+
+use super::{
+    coordinates::{Ecef, Eci, Geodetic},
+    drag::{decayed_semi_major_axis, Propulsion, DEFAULT_BALLISTIC_COEFFICIENT, REENTRY_ALTITUDE},
+    eclipse::{illumination, predict_eclipses, shadow, EclipseEvent, Shadow},
+    ground_station::{GroundStation, Pass, DEFAULT_PASS_SEARCH_HORIZON, DEFAULT_PASS_SEARCH_STEP},
+    numerical::{ForceModel, Integrator, NumericalPropagator},
+    orbit::{OrbitalElements, Propagator, StateVector},
+    power::{PowerConfig, PowerSystem, DEFAULT_INITIAL_CHARGE},
+    sgp4::{Sgp4, Sgp4Error},
+    time::Epoch,
+    tle::TwoLineElement,
+};
 
 #[allow(warnings)]
 #[derive(Debug, Clone)]
 pub struct Satellite {
     pub id: u32,
-    pub position: (f64, f64), // latitude and longitude
+    pub position: (f64, f64), // latitude and longitude of the sub-satellite point, in degrees
     pub altitude: f64,        // in km
-    pub velocity: f64,
+    pub velocity: f64,        // in km/s
     pub storage_on_board: f64,
-    // for now we are assuming there is only one point in ground aka one point for downlink
+    pub radio: Radio,
+    // distance and timing below are toward the nearest available ground station
     pub distance_to_ground: Option<f64>,
-    pub energy_efficiency: f64,
-    pub time_to_downlink: f64,
-    pub communication_window: f64,
+    pub power: PowerSystem,
+    pub ballistic_coefficient: f64, // kg/m², m / (Cd A)
+    pub propulsion: Propulsion,
+    pub target_altitude: f64,  // km, what station-keeping holds the orbit at
+    pub time_to_downlink: f64, // seconds until the next pass starts, 0 during a pass
+    pub communication_window: f64, // seconds of that pass still ahead
+    pub next_pass: Option<Pass>, // the pass both of the above are derived from
+    pass_checked_until: f64,   // no pass needs predicting again before this time
     pub orbital_radius: f64,
     pub past_positions: Vec<(f64, f64)>, // used for storage of history
+    pub elements: OrbitalElements,
+    pub propagator: Propagator,
+    pub decayed: Option<Sgp4Error>, // why SGP4 gave up on the element set, if it has
+    pub state: StateVector,         // ECI state at `sim_time`
+    pub sim_time: f64, // seconds of simulation time the satellite has been propagated to
+    pub epoch: Epoch,  // UTC instant that simulation time zero corresponds to
 }
 
-const G: f64 = 6.674e-11; // G Constant
-const EARTH_MASS: f64 = 5.972e24; // Mass of our Earth in KG
-const EARTH_RADIUS: f64 = 6_371_000.0; // Earth radius in meters
-const MAX_ONBOARD_STORAGE: f64 = 10000.0; // will adjust on chosen storage config
-const MAX_ENERGY_CAPACITY: f64 = 100.0; // will adjust on sensor params
-const COMMUNICATION_RANGE: f64 = 1000.0; // in km
+pub const MAX_ONBOARD_STORAGE: f64 = 10000.0; // kB, will adjust on chosen storage config
+pub const COMMUNICATION_RANGE: f64 = 1000.0; // in km
 
 impl Satellite {
-    pub fn new(id: u32, position: (f64, f64), altitude: f64, velocity: f64) -> Self {
+    pub fn from_elements(id: u32, elements: OrbitalElements, epoch: &Epoch) -> Self {
         let mut rng = rand::thread_rng();
-        Self {
+        let mut satellite = Self {
             id,
-            position,
-            altitude,
-            velocity,
+            position: (0.0, 0.0),
+            altitude: 0.0,
+            velocity: 0.0,
             storage_on_board: rng.gen_range(500.0..MAX_ONBOARD_STORAGE),
+            radio: Radio::default(),
             distance_to_ground: None,
-            energy_efficiency: rng.gen_range(50.0..MAX_ENERGY_CAPACITY),
+            power: PowerSystem::new(PowerConfig::default(), DEFAULT_INITIAL_CHARGE),
+            ballistic_coefficient: DEFAULT_BALLISTIC_COEFFICIENT,
+            propulsion: Propulsion::default(),
+            target_altitude: (elements.semi_major_axis - EARTH_RADIUS) / 1000.0,
             time_to_downlink: 0.0,
             communication_window: 0.0,
-            orbital_radius: EARTH_RADIUS + (altitude * 1000.0),
+            next_pass: None,
+            pass_checked_until: f64::NEG_INFINITY,
+            orbital_radius: elements.semi_major_axis,
             past_positions: Vec::<(f64, f64)>::new(),
+            elements,
+            propagator: Propagator::Kepler,
+            decayed: None,
+            state: StateVector::default(),
+            sim_time: elements.epoch,
+            epoch: *epoch,
+        };
+        satellite.refresh_state();
+        satellite
+    }
+
+    /**
+     * Builds a satellite from a TLE, identified by its NORAD catalog number and propagated
+     * with SGP4. `sim_epoch` is the UTC instant that simulation time zero corresponds to.
+     */
+    pub fn from_tle(tle: &TwoLineElement, sim_epoch: &Epoch) -> Result<Self, Sgp4Error> {
+        let model = Sgp4::from_tle(tle)?;
+        let epoch_offset = sim_epoch.seconds_since(&tle.epoch);
+        // Mean elements at the TLE epoch, kept so Keplerian helpers (mean altitude etc.) stay usable
+        let elements = OrbitalElements {
+            semi_major_axis: model.semi_major_axis_meters(),
+            eccentricity: model.eccentricity,
+            inclination: model.inclination,
+            raan: model.raan,
+            argument_of_perigee: model.argument_of_perigee,
+            mean_anomaly: model.mean_anomaly,
+            epoch: -epoch_offset,
+        };
+        // Fail early if the element set can't be propagated to the start of the simulation
+        model.propagate(epoch_offset / 60.0)?;
+
+        let mut satellite = Self::from_elements(tle.catalog_number, elements, sim_epoch);
+        satellite.propagator = Propagator::Sgp4 {
+            model: Box::new(model),
+            epoch_offset,
+        };
+        satellite.sim_time = 0.0;
+        satellite.refresh_state();
+        Ok(satellite)
+    }
+
+    /**
+     * ECI state vector of the satellite at an arbitrary simulation time, or why SGP4 can't
+     * propagate the element set that far (e.g. it has decayed by then).
+     */
+    pub fn try_state_at(&self, time: f64) -> Result<StateVector, Sgp4Error> {
+        match &self.propagator {
+            Propagator::Kepler => Ok(self.elements.state_at(time)),
+            Propagator::Sgp4 {
+                model,
+                epoch_offset,
+            } => model.propagate((time + epoch_offset) / 60.0),
+            Propagator::Numerical(propagator) => Ok(propagator.state_at(time)),
         }
     }
 
     /**
-    *  Larger time_step = bigger movement per update, meaning we "fast forward" the simulation.
-       Smaller time_step = finer-grained movement, giving a smoother simulation.
-       Example: time_step = 10.0 (meaning we simulate 10 seconds of movement in one update)
-    */
-    pub fn update_satellite_position(&mut self, time_step: f64) {
+     * ECI state vector of the satellite at an arbitrary simulation time. Past the point where
+     * SGP4 gives up on the element set the satellite stays where it was last propagated to;
+     * it is marked `decayed` once propagation gets there and leaves the network with it.
+     */
+    pub fn state_at(&self, time: f64) -> StateVector {
+        self.try_state_at(time).unwrap_or(self.state)
+    }
+
+    /**
+     * Switches to numerical propagation from the current state on, under J2 and drag with
+     * the satellite's ballistic coefficient.
+     */
+    pub fn use_integrator(&mut self, integrator: Integrator) {
+        let forces = ForceModel {
+            j2: true,
+            ballistic_coefficient: Some(self.ballistic_coefficient),
+        };
+        let mut propagator =
+            NumericalPropagator::new(integrator, forces, self.sim_time, self.state);
+        propagator.cover(self.sim_time);
+        self.propagator = Propagator::Numerical(Box::new(propagator));
+        self.refresh_state();
+    }
+
+    /**
+     * Moves the satellite to an absolute simulation time (seconds since the clock epoch).
+     */
+    pub fn propagate_to(&mut self, time: f64) {
+        if time == self.sim_time {
+            return;
+        }
         // Keep track of 1000 past sat positions for prediction-based heuristic
         self.past_positions.push(self.position);
         if self.past_positions.len() > 1000 {
             self.past_positions.remove(0);
         }
 
-        // Change SAT's position using angular velocity (change in radians) and time_step
-        let angular_velocity = self.get_current_speed() / self.orbital_radius;
-        let theta_change = angular_velocity * time_step; // Change in angle over time step
-
-        // Update position using simple orbital model
-        self.position.0 = self.orbital_radius * (self.position.0.to_radians() + theta_change).cos();
-        self.position.1 = self.orbital_radius * (self.position.1.to_radians() + theta_change).sin();
+        self.sim_time = time;
+        if let Propagator::Numerical(propagator) = &mut self.propagator {
+            propagator.cover(time);
+        }
+        self.refresh_state();
     }
 
+    /**
+     * Raises (or lowers) the orbit by `altitude_diff` km from the current simulation time on.
+     * A numerical orbit gets the along-track burn that changes its osculating semi-major axis
+     * by as much.
+     */
     pub fn update_satellite_altitude(&mut self, altitude_diff: f64) {
-        self.altitude += altitude_diff;
-        self.orbital_radius = EARTH_RADIUS + (self.altitude * 1000.0);
+        self.elements = self.elements.with_semi_major_axis(
+            self.elements.semi_major_axis + altitude_diff * 1000.0,
+            self.sim_time,
+        );
+        if let Propagator::Numerical(propagator) = &mut self.propagator {
+            // Vis-viva before and after at the current radius
+            let (radius, speed) = (self.state.position.norm(), self.state.velocity.norm());
+            let semi_major_axis = 1.0 / (2.0 / radius - speed * speed / EARTH_MU);
+            let target = semi_major_axis + altitude_diff * 1000.0;
+            let new_speed = (EARTH_MU * (2.0 / radius - 1.0 / target)).max(0.0).sqrt();
+            let state = StateVector {
+                position: self.state.position,
+                velocity: self.state.velocity * (new_speed / speed),
+            };
+            propagator.restart(self.sim_time, state);
+            propagator.cover(self.sim_time);
+        }
+        self.refresh_state();
     }
 
-    pub(crate) fn calculate_relay_score(&self, ground_position: (f64, f64)) -> f64 {
-        let distance_to_ground = self.get_distance_from_ground();
-        let storage_score = 1.0 / (self.storage_on_board + 1.0); // avoids division by zero
-        let energy_avail_score = 1.0 / (self.energy_efficiency + 1.0);
-        let time_to_downlink_score = self.time_to_downlink;
-        let communication_window_score = 1.0 / (self.communication_window + 1.0);
+    /**
+     * Altitude in km of the orbit's semi-major axis over a spherical Earth, the one altitude
+     * station-keeping and reentry both go by. Unlike `altitude` it doesn't swing with latitude
+     * as the Earth flattens, and it comes from the current state by vis-viva, so it follows
+     * decay whatever propagates the satellite.
+     */
+    pub fn mean_altitude(&self) -> f64 {
+        let radius = self.state.position.norm();
+        let speed = self.state.velocity.norm();
+        let semi_major_axis = 1.0 / (2.0 / radius - speed * speed / EARTH_MU);
+        (semi_major_axis - EARTH_RADIUS) / 1000.0
+    }
 
-        (distance_to_ground * 1.5)
-            + (storage_score * 0.5)
-            + (energy_avail_score * 0.3)
-            + (time_to_downlink_score * 1.0)
-            + (communication_window_score * 0.2)
+    /**
+     * Shrinks the orbit by `seconds` of drag from the current simulation time. SGP4 satellites
+     * already decay through their B* term and numerical ones through their force model, so
+     * only Keplerian orbits are touched.
+     */
+    pub fn apply_drag(&mut self, seconds: f64) {
+        if !matches!(self.propagator, Propagator::Kepler) || seconds <= 0.0 {
+            return;
+        }
+        let semi_major_axis = decayed_semi_major_axis(
+            self.elements.semi_major_axis,
+            self.ballistic_coefficient,
+            seconds,
+        );
+        self.update_satellite_altitude((semi_major_axis - self.elements.semi_major_axis) / 1000.0);
     }
 
-    // This represents the height above Earth's surface in meters.
-    pub(crate) fn get_distance_from_ground(&self) -> f64 {
-        self.altitude * 1000.0 // km to meters
+    /**
+     * Only Keplerian orbits can be maneuvered: a TLE is what it is, and a numerical orbit's
+     * osculating altitude swings too far under J2 for a deadband to mean anything.
+     */
+    pub fn can_maneuver(&self) -> bool {
+        matches!(self.propagator, Propagator::Kepler) && self.propulsion.propellant > 0.0
     }
 
-    // Update when using docker for storage availability per satellite
-    // Aka we are gonna have alocated space of 10MB per satellite and
-    // exchange information when the Ground Control asks for downlink.
-    // Generation of data will be simulated for now. Later step would be
-    // To use one satellite as the collection satellite and have it be
-    // hooked to reading information from the different sensors of a
-    // STM32F3/L4 board and collect that info every 30 secs.
-    pub(crate) fn get_satellite_storage(&self) -> f64 {
-        let mut rng = rand::thread_rng();
-        rng.gen_range(500.0..MAX_ONBOARD_STORAGE)
+    // Next time after `after` a (near-circular) orbit crosses the equator northbound
+    pub fn next_ascending_node(&self, after: f64) -> f64 {
+        let argument_of_latitude =
+            self.elements.argument_of_perigee + self.elements.mean_anomaly_at(after);
+        after + (-argument_of_latitude).rem_euclid(2.0 * PI) / self.elements.mean_motion()
     }
 
-    pub(crate) fn get_current_speed(&self) -> f64 {
-        (G * EARTH_MASS / self.orbital_radius).sqrt()
+    pub fn has_reentered(&self) -> bool {
+        self.decayed.is_some() || self.mean_altitude() < REENTRY_ALTITUDE
+    }
+
+    // UTC instant the satellite has been propagated to
+    pub fn current_epoch(&self) -> Epoch {
+        self.epoch.plus_seconds(self.sim_time)
+    }
+
+    // Battery state of charge in percent
+    pub fn energy_level(&self) -> f64 {
+        self.power.state_of_charge() * 100.0
+    }
+
+    // Storage in kB still free for data from other satellites, `storage_on_board` is queued data
+    pub fn free_storage(&self) -> f64 {
+        (MAX_ONBOARD_STORAGE - self.storage_on_board).max(0.0)
+    }
+
+    pub fn can_relay(&self) -> bool {
+        self.power.can_relay()
+    }
+
+    pub fn shadow(&self) -> Shadow {
+        shadow(&self.state.position, &self.current_epoch())
+    }
+
+    // Fraction of the Sun's disk in view, 0 in umbra
+    pub fn illumination(&self) -> f64 {
+        illumination(&self.state.position, &self.current_epoch())
+    }
+
+    // Shadow boundaries crossed in the `horizon` seconds after `from`
+    pub fn predict_eclipses(&self, from: f64, horizon: f64, step: f64) -> Vec<EclipseEvent> {
+        predict_eclipses(self, from, horizon, step)
+    }
+
+    // Charges or drains the battery for the `seconds` leading up to the current state
+    pub fn update_power(&mut self, seconds: f64) {
+        let sunlight = self.illumination();
+        self.power.update(seconds, sunlight);
+    }
+
+    pub fn eci(&self) -> Eci {
+        Eci(self.state.position)
+    }
+
+    // Inertial position at simulation time `time`
+    pub fn eci_at(&self, time: f64) -> Eci {
+        Eci(self.state_at(time).position)
+    }
+
+    pub fn ecef(&self) -> Ecef {
+        self.eci().to_ecef(&self.current_epoch())
+    }
+
+    pub fn geodetic(&self) -> Geodetic {
+        self.ecef().to_geodetic()
     }
 
     /**
-     * Estimates how much time the satellite has until it reaches the closest point to
-     * the ground station in its circular orbit.
+     * Re-propagates the orbit to `sim_time` and derives the geodetic sub-satellite point,
+     * altitude, radius and speed from the resulting state vector.
      */
-    pub(crate) fn update_time_to_downlink(&mut self, ground_position: (f64, f64)) {
-        let angular_velocity =
-            calculate_angular_velocity(self.get_current_speed(), self.orbital_radius); // w = v / r
-
-        // Satellite latitude -> radians
-        let theta_sat_lat = self.position.0.to_radians();
-        let theta_sat_lon = self.position.1.to_radians();
-        // Ground station latitude -> radians
-        let theta_ground_lat = ground_position.0.to_radians();
-        let theta_ground_lon = ground_position.1.to_radians();
-
-        // Angular distance between satellite and ground using spherical law of cosines
-        let delta_theta = ((theta_sat_lat.sin() * theta_ground_lat.sin())
-            + (theta_sat_lat.cos()
-                * theta_ground_lat.cos()
-                * (theta_sat_lon - theta_ground_lon).cos()))
-        .acos();
-        let delta_theta_future = ((theta_sat_lat.sin() * theta_ground_lat.sin())
-            + ((theta_sat_lat.cos() * theta_ground_lat.cos()
-                + (theta_sat_lon + angular_velocity * 10.0)
-                - theta_ground_lon)
-                .cos()))
-        .acos();
-        let is_approaching = delta_theta_future < delta_theta;
-        if is_approaching {
-            // time until closest approach: t = theta / w
-            self.time_to_downlink = delta_theta / angular_velocity;
-        } else {
-            let full_orbit_time = 2.0 * std::f64::consts::PI / angular_velocity;
-            let time_until_next_phase = full_orbit_time - (delta_theta / angular_velocity);
-            self.time_to_downlink = time_until_next_phase;
+    fn refresh_state(&mut self) {
+        match self.try_state_at(self.sim_time) {
+            Ok(state) => self.state = state,
+            Err(error) => {
+                self.decayed.get_or_insert(error);
+            }
         }
+        let geodetic = self.geodetic();
+        self.orbital_radius = self.state.position.norm();
+        self.altitude = geodetic.altitude / 1000.0;
+        self.velocity = self.state.velocity.norm() / 1000.0;
+        self.position = (geodetic.latitude, geodetic.longitude);
     }
 
     /**
-    *  Computes the total duration the satellite spends inside the communication range.
-       Uses the subtended angle of the communication arc and orbital angular velocity
-       to determine time in range.
-    */
-    pub(crate) fn update_communication_window(&mut self) {
-        let subtended_angle = COMMUNICATION_RANGE * 1000.0 / self.orbital_radius;
-        let total_angle = 2.0 * subtended_angle;
-
-        // w = v / r
-        let angular_velocity =
-            calculate_angular_velocity(self.get_current_speed(), self.orbital_radius);
-        self.communication_window = total_angle / angular_velocity; // Time in seconds
-    }
-}
-
-pub struct NeighboringSatelliteInformation {
-    pub id: u32,                           // id of the neighboring satellite
-    pub distance_from_source: Option<f64>, // distance from the source satellite
-    pub speed: Option<f64>,                // speed of the neighboring satellite
-    pub distance_from_ground: Option<f64>, // distance of the current satellite from the ground receiver
-    pub available_storage: Option<f64>,    // how much memory storage is available on board
-}
+     * Derives `time_to_downlink` and `communication_window` from the earliest pass over any
+     * station that is available for at least part of it. Passes are only re-predicted once
+     * the current one is over, or once the search horizon has run out without finding one.
+     */
+    pub(crate) fn update_time_to_downlink(&mut self, ground_stations: &[GroundStation]) {
+        let now = self.sim_time;
+        if now > self.pass_checked_until {
+            self.next_pass = ground_stations
+                .iter()
+                .filter_map(|station| {
+                    let pass = station.predict_pass(
+                        self,
+                        now,
+                        DEFAULT_PASS_SEARCH_HORIZON,
+                        DEFAULT_PASS_SEARCH_STEP,
+                    )?;
+                    (!station.available_within(pass.aos, pass.los).is_empty()).then_some(pass)
+                })
+                .min_by(|a, b| a.aos.total_cmp(&b.aos));
+            self.pass_checked_until = self
+                .next_pass
+                .map_or(now + DEFAULT_PASS_SEARCH_HORIZON, |pass| pass.los);
+        }
 
-impl NeighboringSatelliteInformation {
-    pub fn new(id: &u32) -> Self {
-        NeighboringSatelliteInformation {
-            id: id.clone(),
-            distance_from_source: None,
-            speed: None,
-            distance_from_ground: None,
-            available_storage: None,
+        match self.next_pass {
+            Some(pass) => {
+                self.time_to_downlink = (pass.aos - now).max(0.0);
+                self.communication_window = pass.los - pass.aos.max(now);
+            }
+            None => {
+                // Nothing within the horizon, which is as much as we can say
+                self.time_to_downlink = DEFAULT_PASS_SEARCH_HORIZON;
+                self.communication_window = 0.0;
+            }
         }
     }
 
-    pub fn get_distance_from_ground(&mut self, distance: f64) {
-        self.distance_from_ground = Some(distance);
+    // Forces the next pass to be predicted again, e.g. after the ground segment changed
+    pub(crate) fn forget_next_pass(&mut self) {
+        self.next_pass = None;
+        self.pass_checked_until = f64::NEG_INFINITY;
     }
+}
 
-    pub fn get_distance_from_source(&mut self, distance: f64) {
-        self.distance_from_source = Some(distance);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decaying_tle() -> TwoLineElement {
+        let mut tle = TwoLineElement::parse(
+            None,
+            "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753",
+            "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667",
+        )
+        .unwrap();
+        tle.eccentricity = 0.001;
+        tle.mean_motion = 16.2;
+        tle.bstar = 0.01;
+        tle
     }
 
-    pub fn get_available_storage(&mut self, available_storage: f64) {
-        self.available_storage = Some(available_storage);
+    #[test]
+    fn reentry_and_station_keeping_share_one_altitude() {
+        let epoch = Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0);
+        let elements = OrbitalElements::circular(REENTRY_ALTITUDE + 5.0, 0.0, 0.0, 0.0);
+        let mut satellite = Satellite::from_elements(1, elements, &epoch);
+        assert!((satellite.mean_altitude() - (REENTRY_ALTITUDE + 5.0)).abs() < 1e-6);
+        assert!((satellite.mean_altitude() - satellite.target_altitude).abs() < 1e-6);
+        // Over the equator the geodetic altitude sits ~7 km lower, below the reentry line
+        assert!(satellite.altitude < REENTRY_ALTITUDE);
+        assert!(!satellite.has_reentered());
+
+        satellite.update_satellite_altitude(-4.9);
+        assert!(!satellite.has_reentered());
+        satellite.update_satellite_altitude(-0.2);
+        assert!(satellite.has_reentered());
     }
 
-    pub fn get_speed(&mut self, speed: f64) {
-        self.speed = Some(speed);
+    #[test]
+    fn sgp4_failure_marks_the_satellite_decayed() {
+        let tle = decaying_tle();
+        let mut satellite = Satellite::from_tle(&tle, &tle.epoch).unwrap();
+        assert!(!satellite.has_reentered());
+
+        satellite.propagate_to(3600.0);
+        let last_state = satellite.state;
+        assert!(satellite.decayed.is_none());
+
+        satellite.propagate_to(14.0 * 86_400.0);
+        assert!(satellite.decayed.is_some());
+        assert!(satellite.has_reentered());
+        assert!(satellite.try_state_at(14.0 * 86_400.0).is_err());
+        // The state isn't replaced by some other orbit's
+        assert_eq!(satellite.state.position, last_state.position);
     }
 }
@@ -0,0 +1,510 @@
+use std::{f64::consts::PI, fmt};
+
+use crate::common::Vector3;
+
+use super::{orbit::StateVector, tle::TwoLineElement};
+
+/*
+ * SGP4 (Simplified General Perturbations) propagator for near-Earth element sets, following
+ * the formulation in Vallado et al., "Revisiting Spacetrack Report #3" (AIAA 2006-6753).
+ * TLE mean elements are only meaningful when propagated with SGP4 using the same WGS-72
+ * constants they were fitted with. Output is in the TEME frame, which we treat as our ECI frame.
+ */
+
+// WGS-72 constants used to generate the published element sets
+const RADIUS_EARTH_KM: f64 = 6378.135;
+const MU_KM: f64 = 398_600.8;
+const J2: f64 = 0.001_082_616;
+const J3: f64 = -0.000_002_538_81;
+const J4: f64 = -0.000_001_655_97;
+const J3_OVER_J2: f64 = J3 / J2;
+const MINUTES_PER_DAY: f64 = 1440.0;
+const TWO_THIRDS: f64 = 2.0 / 3.0;
+// Orbits with a period of 225 minutes or more need the deep-space (SDP4) terms
+const DEEP_SPACE_PERIOD_MINUTES: f64 = 225.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sgp4Error {
+    // Period >= 225 minutes; lunar/solar resonance terms are not modeled
+    DeepSpace { period_minutes: f64 },
+    InvalidElements,
+    MeanMotionNotPositive,
+    EccentricityOutOfRange { eccentricity: f64 },
+    SemiLatusRectumNegative,
+    Decayed,
+}
+
+impl fmt::Display for Sgp4Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sgp4Error::DeepSpace { period_minutes } => write!(
+                f,
+                "orbit period of {:.1} minutes requires the deep-space model",
+                period_minutes
+            ),
+            Sgp4Error::InvalidElements => write!(f, "element set is not a valid orbit"),
+            Sgp4Error::MeanMotionNotPositive => write!(f, "mean motion dropped below zero"),
+            Sgp4Error::EccentricityOutOfRange { eccentricity } => {
+                write!(f, "eccentricity {} is out of range", eccentricity)
+            }
+            Sgp4Error::SemiLatusRectumNegative => write!(f, "semi-latus rectum is negative"),
+            Sgp4Error::Decayed => write!(f, "satellite has decayed"),
+        }
+    }
+}
+
+impl std::error::Error for Sgp4Error {}
+
+#[derive(Debug, Clone)]
+pub struct Sgp4 {
+    // Mean elements at epoch (radians, radians/minute)
+    pub inclination: f64,
+    pub raan: f64,
+    pub eccentricity: f64,
+    pub argument_of_perigee: f64,
+    pub mean_anomaly: f64,
+    pub mean_motion: f64,     // un-Kozai'd (Brouwer) mean motion
+    pub semi_major_axis: f64, // in earth radii
+    pub bstar: f64,
+    is_simple: bool, // perigee below 220 km: drop the higher order drag terms
+    aycof: f64,
+    con41: f64,
+    cc1: f64,
+    cc4: f64,
+    cc5: f64,
+    d2: f64,
+    d3: f64,
+    d4: f64,
+    delmo: f64,
+    eta: f64,
+    argpdot: f64,
+    omgcof: f64,
+    sinmao: f64,
+    t2cof: f64,
+    t3cof: f64,
+    t4cof: f64,
+    t5cof: f64,
+    x1mth2: f64,
+    x7thm1: f64,
+    mdot: f64,
+    nodedot: f64,
+    xlcof: f64,
+    xmcof: f64,
+    nodecf: f64,
+}
+
+impl Sgp4 {
+    pub fn from_tle(tle: &TwoLineElement) -> Result<Self, Sgp4Error> {
+        Self::new(
+            tle.inclination.to_radians(),
+            tle.raan.to_radians(),
+            tle.eccentricity,
+            tle.argument_of_perigee.to_radians(),
+            tle.mean_anomaly.to_radians(),
+            tle.mean_motion * 2.0 * PI / MINUTES_PER_DAY,
+            tle.bstar,
+        )
+    }
+
+    /**
+     * Initializes the propagator from mean elements. Angles are in radians and the (Kozai)
+     * mean motion is in radians per minute.
+     */
+    pub fn new(
+        inclination: f64,
+        raan: f64,
+        eccentricity: f64,
+        argument_of_perigee: f64,
+        mean_anomaly: f64,
+        kozai_mean_motion: f64,
+        bstar: f64,
+    ) -> Result<Self, Sgp4Error> {
+        if !(0.0..1.0).contains(&eccentricity) || kozai_mean_motion <= 0.0 {
+            return Err(Sgp4Error::InvalidElements);
+        }
+        let xke = xke();
+
+        // Recover the original mean motion and semi-major axis from the Kozai mean motion
+        let eccsq = eccentricity * eccentricity;
+        let omeosq = 1.0 - eccsq;
+        let rteosq = omeosq.sqrt();
+        let cosio = inclination.cos();
+        let cosio2 = cosio * cosio;
+        let ak = (xke / kozai_mean_motion).powf(TWO_THIRDS);
+        let d1 = 0.75 * J2 * (3.0 * cosio2 - 1.0) / (rteosq * omeosq);
+        let mut del = d1 / (ak * ak);
+        let adel = ak * (1.0 - del * del - del * (1.0 / 3.0 + 134.0 * del * del / 81.0));
+        del = d1 / (adel * adel);
+        let no = kozai_mean_motion / (1.0 + del);
+
+        let period_minutes = 2.0 * PI / no;
+        if period_minutes >= DEEP_SPACE_PERIOD_MINUTES {
+            return Err(Sgp4Error::DeepSpace { period_minutes });
+        }
+
+        let ao = (xke / no).powf(TWO_THIRDS);
+        let sinio = inclination.sin();
+        let po = ao * omeosq;
+        let con42 = 1.0 - 5.0 * cosio2;
+        let con41 = -con42 - cosio2 - cosio2;
+        let posq = po * po;
+        let rp = ao * (1.0 - eccentricity);
+
+        // Atmospheric density parameters, adjusted for low perigees
+        let ss = 78.0 / RADIUS_EARTH_KM + 1.0;
+        let qzms2t = ((120.0 - 78.0) / RADIUS_EARTH_KM).powi(4);
+        let is_simple = rp < 220.0 / RADIUS_EARTH_KM + 1.0;
+        let mut sfour = ss;
+        let mut qzms24 = qzms2t;
+        let perigee = (rp - 1.0) * RADIUS_EARTH_KM;
+        if perigee < 156.0 {
+            sfour = if perigee < 98.0 { 20.0 } else { perigee - 78.0 };
+            qzms24 = ((120.0 - sfour) / RADIUS_EARTH_KM).powi(4);
+            sfour = sfour / RADIUS_EARTH_KM + 1.0;
+        }
+
+        let pinvsq = 1.0 / posq;
+        let tsi = 1.0 / (ao - sfour);
+        let eta = ao * eccentricity * tsi;
+        let etasq = eta * eta;
+        let eeta = eccentricity * eta;
+        let psisq = (1.0 - etasq).abs();
+        let coef = qzms24 * tsi.powi(4);
+        let coef1 = coef / psisq.powf(3.5);
+        let cc2 = coef1
+            * no
+            * (ao * (1.0 + 1.5 * etasq + eeta * (4.0 + etasq))
+                + 0.375 * J2 * tsi / psisq * con41 * (8.0 + 3.0 * etasq * (8.0 + etasq)));
+        let cc1 = bstar * cc2;
+        let cc3 = if eccentricity > 1.0e-4 {
+            -2.0 * coef * tsi * J3_OVER_J2 * no * sinio / eccentricity
+        } else {
+            0.0
+        };
+        let x1mth2 = 1.0 - cosio2;
+        let cc4 = 2.0
+            * no
+            * coef1
+            * ao
+            * omeosq
+            * (eta * (2.0 + 0.5 * etasq) + eccentricity * (0.5 + 2.0 * etasq)
+                - J2 * tsi / (ao * psisq)
+                    * (-3.0 * con41 * (1.0 - 2.0 * eeta + etasq * (1.5 - 0.5 * eeta))
+                        + 0.75
+                            * x1mth2
+                            * (2.0 * etasq - eeta * (1.0 + etasq))
+                            * (2.0 * argument_of_perigee).cos()));
+        let cc5 = 2.0 * coef1 * ao * omeosq * (1.0 + 2.75 * (etasq + eeta) + eeta * etasq);
+
+        // Secular rates from J2/J4
+        let cosio4 = cosio2 * cosio2;
+        let temp1 = 1.5 * J2 * pinvsq * no;
+        let temp2 = 0.5 * temp1 * J2 * pinvsq;
+        let temp3 = -0.46875 * J4 * pinvsq * pinvsq * no;
+        let mdot = no
+            + 0.5 * temp1 * rteosq * con41
+            + 0.0625 * temp2 * rteosq * (13.0 - 78.0 * cosio2 + 137.0 * cosio4);
+        let argpdot = -0.5 * temp1 * con42
+            + 0.0625 * temp2 * (7.0 - 114.0 * cosio2 + 395.0 * cosio4)
+            + temp3 * (3.0 - 36.0 * cosio2 + 49.0 * cosio4);
+        let xhdot1 = -temp1 * cosio;
+        let nodedot = xhdot1
+            + (0.5 * temp2 * (4.0 - 19.0 * cosio2) + 2.0 * temp3 * (3.0 - 7.0 * cosio2)) * cosio;
+
+        let omgcof = bstar * cc3 * argument_of_perigee.cos();
+        let xmcof = if eccentricity > 1.0e-4 {
+            -TWO_THIRDS * coef * bstar / eeta
+        } else {
+            0.0
+        };
+        let nodecf = 3.5 * omeosq * xhdot1 * cc1;
+        let t2cof = 1.5 * cc1;
+        // Avoid the singularity for retrograde equatorial orbits
+        let xlcof_denominator = if (cosio + 1.0).abs() > 1.5e-12 {
+            1.0 + cosio
+        } else {
+            1.5e-12
+        };
+        let xlcof = -0.25 * J3_OVER_J2 * sinio * (3.0 + 5.0 * cosio) / xlcof_denominator;
+        let aycof = -0.5 * J3_OVER_J2 * sinio;
+        let delmo = (1.0 + eta * mean_anomaly.cos()).powi(3);
+        let sinmao = mean_anomaly.sin();
+        let x7thm1 = 7.0 * cosio2 - 1.0;
+
+        let (mut d2, mut d3, mut d4, mut t3cof, mut t4cof, mut t5cof) =
+            (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        if !is_simple {
+            let cc1sq = cc1 * cc1;
+            d2 = 4.0 * ao * tsi * cc1sq;
+            let temp = d2 * tsi * cc1 / 3.0;
+            d3 = (17.0 * ao + sfour) * temp;
+            d4 = 0.5 * temp * ao * tsi * (221.0 * ao + 31.0 * sfour) * cc1;
+            t3cof = d2 + 2.0 * cc1sq;
+            t4cof = 0.25 * (3.0 * d3 + cc1 * (12.0 * d2 + 10.0 * cc1sq));
+            t5cof = 0.2
+                * (3.0 * d4 + 12.0 * cc1 * d3 + 6.0 * d2 * d2 + 15.0 * cc1sq * (2.0 * d2 + cc1sq));
+        }
+
+        Ok(Self {
+            inclination,
+            raan,
+            eccentricity,
+            argument_of_perigee,
+            mean_anomaly,
+            mean_motion: no,
+            semi_major_axis: ao,
+            bstar,
+            is_simple,
+            aycof,
+            con41,
+            cc1,
+            cc4,
+            cc5,
+            d2,
+            d3,
+            d4,
+            delmo,
+            eta,
+            argpdot,
+            omgcof,
+            sinmao,
+            t2cof,
+            t3cof,
+            t4cof,
+            t5cof,
+            x1mth2,
+            x7thm1,
+            mdot,
+            nodedot,
+            xlcof,
+            xmcof,
+            nodecf,
+        })
+    }
+
+    /**
+     * Propagates to `minutes_since_epoch` and returns the TEME position (meters) and
+     * velocity (m/s).
+     */
+    pub fn propagate(&self, minutes_since_epoch: f64) -> Result<StateVector, Sgp4Error> {
+        let t = minutes_since_epoch;
+        let xke = xke();
+
+        // Secular gravity and atmospheric drag
+        let xmdf = self.mean_anomaly + self.mdot * t;
+        let argpdf = self.argument_of_perigee + self.argpdot * t;
+        let nodedf = self.raan + self.nodedot * t;
+        let mut argpm = argpdf;
+        let mut mm = xmdf;
+        let t2 = t * t;
+        let mut nodem = nodedf + self.nodecf * t2;
+        let mut tempa = 1.0 - self.cc1 * t;
+        let mut tempe = self.bstar * self.cc4 * t;
+        let mut templ = self.t2cof * t2;
+
+        if !self.is_simple {
+            let delomg = self.omgcof * t;
+            let delm = self.xmcof * ((1.0 + self.eta * xmdf.cos()).powi(3) - self.delmo);
+            let temp = delomg + delm;
+            mm = xmdf + temp;
+            argpm = argpdf - temp;
+            let t3 = t2 * t;
+            let t4 = t3 * t;
+            tempa = tempa - self.d2 * t2 - self.d3 * t3 - self.d4 * t4;
+            tempe += self.bstar * self.cc5 * (mm.sin() - self.sinmao);
+            templ += self.t3cof * t3 + t4 * (self.t4cof + t * self.t5cof);
+        }
+
+        if self.mean_motion <= 0.0 {
+            return Err(Sgp4Error::MeanMotionNotPositive);
+        }
+        let am = (xke / self.mean_motion).powf(TWO_THIRDS) * tempa * tempa;
+        let nm = xke / am.powf(1.5);
+        let mut em = self.eccentricity - tempe;
+        if !(-0.001..1.0).contains(&em) {
+            return Err(Sgp4Error::EccentricityOutOfRange { eccentricity: em });
+        }
+        em = em.max(1.0e-6);
+        mm += self.mean_motion * templ;
+        let xlm = mm + argpm + nodem;
+        nodem %= 2.0 * PI;
+        argpm %= 2.0 * PI;
+        let xlm = xlm % (2.0 * PI);
+        mm = (xlm - argpm - nodem) % (2.0 * PI);
+
+        // Long period periodics
+        let (sinip, cosip) = self.inclination.sin_cos();
+        let axnl = em * argpm.cos();
+        let temp = 1.0 / (am * (1.0 - em * em));
+        let aynl = em * argpm.sin() + temp * self.aycof;
+        let xl = mm + argpm + nodem + temp * self.xlcof * axnl;
+
+        // Solve Kepler's equation in the equinoctial form
+        let u = (xl - nodem) % (2.0 * PI);
+        let mut eo1 = u;
+        let (mut sineo1, mut coseo1) = (0.0, 0.0);
+        for _ in 0..10 {
+            sineo1 = eo1.sin();
+            coseo1 = eo1.cos();
+            let mut tem5 = 1.0 - coseo1 * axnl - sineo1 * aynl;
+            tem5 = (u - aynl * coseo1 + axnl * sineo1 - eo1) / tem5;
+            if tem5.abs() >= 0.95 {
+                tem5 = 0.95 * tem5.signum();
+            }
+            eo1 += tem5;
+            if tem5.abs() < 1.0e-12 {
+                break;
+            }
+        }
+
+        // Short period preliminary quantities
+        let ecose = axnl * coseo1 + aynl * sineo1;
+        let esine = axnl * sineo1 - aynl * coseo1;
+        let el2 = axnl * axnl + aynl * aynl;
+        let pl = am * (1.0 - el2);
+        if pl < 0.0 {
+            return Err(Sgp4Error::SemiLatusRectumNegative);
+        }
+        let rl = am * (1.0 - ecose);
+        let rdotl = am.sqrt() * esine / rl;
+        let rvdotl = pl.sqrt() / rl;
+        let betal = (1.0 - el2).sqrt();
+        let temp = esine / (1.0 + betal);
+        let sinu = am / rl * (sineo1 - aynl - axnl * temp);
+        let cosu = am / rl * (coseo1 - axnl + aynl * temp);
+        let mut su = sinu.atan2(cosu);
+        let sin2u = (cosu + cosu) * sinu;
+        let cos2u = 1.0 - 2.0 * sinu * sinu;
+        let temp = 1.0 / pl;
+        let temp1 = 0.5 * J2 * temp;
+        let temp2 = temp1 * temp;
+
+        // Update for short period periodics
+        let mrt = rl * (1.0 - 1.5 * temp2 * betal * self.con41) + 0.5 * temp1 * self.x1mth2 * cos2u;
+        su -= 0.25 * temp2 * self.x7thm1 * sin2u;
+        let xnode = nodem + 1.5 * temp2 * cosip * sin2u;
+        let xinc = self.inclination + 1.5 * temp2 * cosip * sinip * cos2u;
+        let mvt = rdotl - nm * temp1 * self.x1mth2 * sin2u / xke;
+        let rvdot = rvdotl + nm * temp1 * (self.x1mth2 * cos2u + 1.5 * self.con41) / xke;
+
+        if mrt < 1.0 {
+            return Err(Sgp4Error::Decayed);
+        }
+
+        // Orientation vectors
+        let (sinsu, cossu) = su.sin_cos();
+        let (snod, cnod) = xnode.sin_cos();
+        let (sini, cosi) = xinc.sin_cos();
+        let xmx = -snod * cosi;
+        let xmy = cnod * cosi;
+        let u_vec = Vector3::new(
+            xmx * sinsu + cnod * cossu,
+            xmy * sinsu + snod * cossu,
+            sini * sinsu,
+        );
+        let v_vec = Vector3::new(
+            xmx * cossu - cnod * sinsu,
+            xmy * cossu - snod * sinsu,
+            sini * cossu,
+        );
+
+        let radius_m = RADIUS_EARTH_KM * 1000.0;
+        let velocity_m_per_s = radius_m * xke / 60.0;
+        Ok(StateVector {
+            position: u_vec * (mrt * radius_m),
+            velocity: (u_vec * mvt + v_vec * rvdot) * velocity_m_per_s,
+        })
+    }
+
+    pub fn semi_major_axis_meters(&self) -> f64 {
+        self.semi_major_axis * RADIUS_EARTH_KM * 1000.0
+    }
+}
+
+// sqrt(mu) in earth radii^1.5 per minute
+fn xke() -> f64 {
+    60.0 / (RADIUS_EARTH_KM.powi(3) / MU_KM).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE1: &str = "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753";
+    const LINE2: &str = "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667";
+
+    fn vanguard() -> Sgp4 {
+        Sgp4::from_tle(&TwoLineElement::parse(None, LINE1, LINE2).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn matches_the_vallado_test_vectors() {
+        // Minutes since epoch, TEME position (km) and velocity (km/s) from tcppver.out
+        let expected = [
+            (
+                0.0,
+                [7022.46529266, -1400.08296755, 0.03995155],
+                [1.893841015, 6.405893759, 4.534807250],
+            ),
+            (
+                360.0,
+                [-7154.03120202, -3783.17682504, -3536.19412294],
+                [4.741887409, -4.151817765, -2.093935425],
+            ),
+            (
+                720.0,
+                [-7134.59340119, 6531.68641334, 3260.27186483],
+                [-4.113793027, -2.911922039, -2.557327851],
+            ),
+            (
+                1080.0,
+                [5568.53901181, 4492.06992591, 3863.87641983],
+                [-4.209106476, 5.159719888, 2.744852980],
+            ),
+        ];
+
+        let model = vanguard();
+        for (minutes, position, velocity) in expected {
+            let state = model.propagate(minutes).unwrap();
+            let position = Vector3::new(position[0], position[1], position[2]) * 1000.0;
+            let velocity = Vector3::new(velocity[0], velocity[1], velocity[2]) * 1000.0;
+            assert!(
+                state.position.distance_to(&position) < 0.01,
+                "{} min: position off by {} m",
+                minutes,
+                state.position.distance_to(&position)
+            );
+            assert!(
+                state.velocity.distance_to(&velocity) < 1e-5,
+                "{} min: velocity off by {} m/s",
+                minutes,
+                state.velocity.distance_to(&velocity)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_deep_space_element_sets() {
+        // Same set slowed down to two revolutions a day, a 12 hour (GPS-like) period
+        let mut tle = TwoLineElement::parse(None, LINE1, LINE2).unwrap();
+        tle.mean_motion = 2.0;
+        match Sgp4::from_tle(&tle) {
+            Err(Sgp4Error::DeepSpace { period_minutes }) => {
+                assert!((period_minutes - 720.0).abs() < 1.0, "{}", period_minutes)
+            }
+            other => panic!("expected a deep-space error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fails_once_drag_has_brought_the_orbit_down() {
+        // A heavily dragged orbit falls apart within days rather than going underground
+        let mut tle = TwoLineElement::parse(None, LINE1, LINE2).unwrap();
+        tle.eccentricity = 0.001;
+        tle.mean_motion = 16.2;
+        tle.bstar = 0.5;
+        let model = Sgp4::from_tle(&tle).unwrap();
+        assert!(model.propagate(0.0).is_ok());
+        assert!(model.propagate(14.0 * 1440.0).is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use crate::common::Vector3;
+
+use super::time::Epoch;
+
+/*
+ * Where the Sun is, for power and thermal purposes. The low-precision solar coordinates of the
+ * Astronomical Almanac are good to about 0.01° between 1950 and 2050, far better than a
+ * shadow model needs. The shadow itself is in `eclipse`.
+ */
+
+pub const ASTRONOMICAL_UNIT: f64 = 149_597_870_700.0; // meters
+
+/**
+ * Geocentric position of the Sun in meters, in the mean equator and equinox of date. That is
+ * close enough to TEME that satellite positions can be compared against it directly.
+ */
+pub fn sun_position(epoch: &Epoch) -> Vector3 {
+    let t = (epoch.julian_date - 2_451_545.0) / 36_525.0;
+    let mean_longitude = 280.460 + 36_000.771 * t;
+    let mean_anomaly = (357.529_109_2 + 35_999.050_34 * t).to_radians();
+    let ecliptic_longitude = (mean_longitude
+        + 1.914_666_471 * mean_anomaly.sin()
+        + 0.019_994_643 * (2.0 * mean_anomaly).sin())
+    .to_radians();
+    let distance = 1.000_140_612
+        - 0.016_708_617 * mean_anomaly.cos()
+        - 0.000_139_589 * (2.0 * mean_anomaly).cos();
+    let obliquity = (23.439_291 - 0.013_004_2 * t).to_radians();
+
+    Vector3::new(
+        ecliptic_longitude.cos(),
+        obliquity.cos() * ecliptic_longitude.sin(),
+        obliquity.sin() * ecliptic_longitude.sin(),
+    ) * (distance * ASTRONOMICAL_UNIT)
+}
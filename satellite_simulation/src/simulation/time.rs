@@ -0,0 +1,409 @@
+use std::{
+    fmt, thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::common::TIME_LOOKAHEAD_SECS;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2_440_587.5; // 1970-01-01T00:00:00 UTC
+pub const DEFAULT_TIME_STEP: f64 = 1.0; // seconds of simulation time per tick
+
+/**
+ * An absolute UTC instant stored as a Julian date. Simulation time is expressed as seconds
+ * relative to one of these, and element sets (TLEs) carry their own epoch.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Epoch {
+    pub julian_date: f64,
+}
+
+impl Epoch {
+    pub fn from_julian_date(julian_date: f64) -> Self {
+        Self { julian_date }
+    }
+
+    pub fn from_calendar(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: f64,
+    ) -> Self {
+        // Valid for years 1900 to 2100 (Vallado, "jday")
+        let year = year as f64;
+        let month = month as f64;
+        let julian_day = 367.0 * year
+            - (7.0 * (year + ((month + 9.0) / 12.0).floor()) * 0.25).floor()
+            + (275.0 * month / 9.0).floor()
+            + day as f64
+            + 1_721_013.5;
+        let day_fraction = ((second / 60.0 + minute as f64) / 60.0 + hour as f64) / 24.0;
+
+        Self::from_julian_date(julian_day + day_fraction)
+    }
+
+    /**
+     * Two-line element sets store the epoch as a two-digit year and a fractional day of year,
+     * where 57-99 map to 1957-1999 and 00-56 map to 2000-2056.
+     */
+    pub fn from_tle_epoch(two_digit_year: u32, day_of_year: f64) -> Self {
+        let year = if two_digit_year < 57 {
+            2000 + two_digit_year as i32
+        } else {
+            1900 + two_digit_year as i32
+        };
+        let start_of_year = Self::from_calendar(year, 1, 1, 0, 0, 0.0);
+
+        start_of_year.plus_seconds((day_of_year - 1.0) * SECONDS_PER_DAY)
+    }
+
+    pub fn from_unix_seconds(seconds: f64) -> Self {
+        Self::from_julian_date(UNIX_EPOCH_JULIAN_DATE + seconds / SECONDS_PER_DAY)
+    }
+
+    pub fn now() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs_f64())
+            .unwrap_or(0.0);
+        Self::from_unix_seconds(seconds)
+    }
+
+    pub fn plus_seconds(&self, seconds: f64) -> Self {
+        Self::from_julian_date(self.julian_date + seconds / SECONDS_PER_DAY)
+    }
+
+    // Signed number of seconds from `other` to this epoch
+    pub fn seconds_since(&self, other: &Epoch) -> f64 {
+        (self.julian_date - other.julian_date) * SECONDS_PER_DAY
+    }
+}
+
+impl fmt::Display for Epoch {
+    // ISO-8601 UTC, e.g. 2025-03-01T12:00:00.000Z (Meeus, "Astronomical Algorithms" ch. 7)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shifted = self.julian_date + 0.5;
+        let z = shifted.floor();
+        let mut seconds_of_day = ((shifted - z) * SECONDS_PER_DAY * 1000.0).round() / 1000.0;
+        let mut z = z as i64;
+        if seconds_of_day >= SECONDS_PER_DAY {
+            seconds_of_day -= SECONDS_PER_DAY;
+            z += 1;
+        }
+
+        let alpha = ((z as f64 - 1_867_216.25) / 36_524.25).floor() as i64;
+        let a = if z < 2_299_161 {
+            z
+        } else {
+            z + 1 + alpha - alpha / 4
+        };
+        let b = a + 1524;
+        let c = ((b as f64 - 122.1) / 365.25).floor() as i64;
+        let d = (365.25 * c as f64).floor() as i64;
+        let e = ((b - d) as f64 / 30.6001).floor() as i64;
+        let day = b - d - (30.6001 * e as f64).floor() as i64;
+        let month = if e < 14 { e - 1 } else { e - 13 };
+        let year = if month > 2 { c - 4716 } else { c - 4715 };
+
+        let hour = (seconds_of_day / 3600.0).floor();
+        let minute = ((seconds_of_day - hour * 3600.0) / 60.0).floor();
+        let second = seconds_of_day - hour * 3600.0 - minute * 60.0;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:06.3}Z",
+            year, month, day, hour as u32, minute as u32, second
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockError {
+    Step(f64),
+    Speed(f64),
+    Lookahead(f64),
+    Duration(f64),
+}
+
+impl fmt::Display for ClockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockError::Step(step) => {
+                write!(f, "step must be a positive number of seconds, got {}", step)
+            }
+            ClockError::Speed(speed) => {
+                write!(f, "speed-up must be a positive number, got {}", speed)
+            }
+            ClockError::Lookahead(lookahead) => write!(
+                f,
+                "lookahead must be a finite, non-negative number of seconds, got {}",
+                lookahead
+            ),
+            ClockError::Duration(duration) => write!(
+                f,
+                "run duration must be a finite, non-negative number of seconds, got {}",
+                duration
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ClockError {}
+
+// A step has to move time forward by a finite amount, or a run never reaches its end
+pub fn check_step(step: f64) -> Result<f64, ClockError> {
+    if step > 0.0 && step.is_finite() {
+        Ok(step)
+    } else {
+        Err(ClockError::Step(step))
+    }
+}
+
+// Real-time pacing sleeps for step / speed, which has to be a finite, non-negative duration
+pub fn check_speed(speed: f64) -> Result<f64, ClockError> {
+    if speed > 0.0 && speed.is_finite() {
+        Ok(speed)
+    } else {
+        Err(ClockError::Speed(speed))
+    }
+}
+
+pub fn check_lookahead(lookahead: f64) -> Result<f64, ClockError> {
+    if lookahead >= 0.0 && lookahead.is_finite() {
+        Ok(lookahead)
+    } else {
+        Err(ClockError::Lookahead(lookahead))
+    }
+}
+
+/**
+ * How the clock advances when the simulation asks it to tick.
+ *      Paused      - time stands still, ticks advance nothing
+ *      FastForward - every tick advances a full step immediately, as fast as we can compute
+ *      RealTime    - ticks are paced against the wall clock; `speed` 1.0 is real time,
+ *                    10.0 runs ten simulated seconds per wall-clock second
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockMode {
+    Paused,
+    FastForward,
+    RealTime { speed: f64 },
+}
+
+/**
+ * The simulation's notion of time. Every timestamp in the simulator (satellite states,
+ * contacts, routes) is seconds since `epoch`, so the clock is the single place that maps
+ * simulation time back to UTC. The network owns the clock and hands it by reference to the
+ * contact computation and the router.
+ */
+#[derive(Debug, Clone)]
+pub struct SimClock {
+    epoch: Epoch,
+    elapsed: f64,
+    step: f64,
+    mode: ClockMode,
+    lookahead: f64, // how far ahead contact prediction looks, in seconds
+    last_tick: Option<Instant>,
+}
+
+impl SimClock {
+    pub fn new(epoch: Epoch, step: f64) -> Self {
+        Self {
+            epoch,
+            elapsed: 0.0,
+            step,
+            mode: ClockMode::FastForward,
+            lookahead: TIME_LOOKAHEAD_SECS,
+            last_tick: None,
+        }
+    }
+
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
+    // Current simulation time in seconds since the epoch
+    pub fn now(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn now_epoch(&self) -> Epoch {
+        self.epoch_at(self.elapsed)
+    }
+
+    // Converts a simulation timestamp into an absolute UTC instant
+    pub fn epoch_at(&self, time: f64) -> Epoch {
+        self.epoch.plus_seconds(time)
+    }
+
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    pub fn set_step(&mut self, step: f64) -> Result<(), ClockError> {
+        self.step = check_step(step)?;
+        Ok(())
+    }
+
+    pub fn lookahead(&self) -> f64 {
+        self.lookahead
+    }
+
+    pub fn set_lookahead(&mut self, lookahead: f64) -> Result<(), ClockError> {
+        self.lookahead = check_lookahead(lookahead)?;
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == ClockMode::Paused
+    }
+
+    // Modes are only set through the methods below, so a real-time speed is always usable
+    fn set_mode(&mut self, mode: ClockMode) {
+        self.mode = mode;
+        self.last_tick = None;
+    }
+
+    pub fn pause(&mut self) {
+        self.set_mode(ClockMode::Paused);
+    }
+
+    pub fn real_time(&mut self, speed: f64) -> Result<(), ClockError> {
+        self.set_mode(ClockMode::RealTime {
+            speed: check_speed(speed)?,
+        });
+        Ok(())
+    }
+
+    /**
+     * Advances the clock by one step according to the current mode and returns how many
+     * seconds of simulation time passed. In real-time mode this blocks until the wall clock
+     * has caught up with the step.
+     */
+    pub fn tick(&mut self) -> f64 {
+        match self.mode {
+            ClockMode::Paused => return 0.0,
+            ClockMode::FastForward => {}
+            ClockMode::RealTime { speed } => {
+                let target = Duration::from_secs_f64(self.step / speed);
+                if let Some(last_tick) = self.last_tick {
+                    if let Some(remaining) = target.checked_sub(last_tick.elapsed()) {
+                        thread::sleep(remaining);
+                    }
+                }
+                self.last_tick = Some(Instant::now());
+            }
+        }
+        self.elapsed += self.step;
+        self.step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calendar_dates_map_to_julian_dates() {
+        // J2000.0 and the Unix epoch
+        let j2000 = Epoch::from_calendar(2000, 1, 1, 12, 0, 0.0);
+        assert_eq!(j2000.julian_date, 2_451_545.0);
+        assert_eq!(
+            Epoch::from_unix_seconds(0.0).julian_date,
+            UNIX_EPOCH_JULIAN_DATE
+        );
+        assert_eq!(j2000.to_string(), "2000-01-01T12:00:00.000Z");
+
+        let later = j2000.plus_seconds(90_061.5);
+        assert_eq!(later.to_string(), "2000-01-02T13:01:01.500Z");
+        assert!((later.seconds_since(&j2000) - 90_061.5).abs() < 1e-4);
+        // Two-digit TLE years wrap at 57
+        assert_eq!(
+            Epoch::from_tle_epoch(57, 1.5).to_string(),
+            "1957-01-01T12:00:00.000Z"
+        );
+        assert_eq!(
+            Epoch::from_tle_epoch(24, 60.0).to_string(),
+            "2024-02-29T00:00:00.000Z"
+        );
+    }
+
+    #[test]
+    fn simulation_time_maps_back_to_utc() {
+        let epoch = Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0);
+        let mut clock = SimClock::new(epoch, 30.0);
+        clock.tick();
+        clock.tick();
+
+        assert_eq!(clock.now(), 60.0);
+        assert_eq!(clock.now_epoch().to_string(), "2024-01-01T00:01:00.000Z");
+        assert_eq!(
+            clock.epoch_at(-3600.0).to_string(),
+            "2023-12-31T23:00:00.000Z"
+        );
+    }
+
+    fn clock() -> SimClock {
+        SimClock::new(Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0), 5.0)
+    }
+
+    #[test]
+    fn rejects_steps_speeds_and_lookaheads_that_never_end() {
+        let mut clock = clock();
+        for bad in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(clock.set_step(bad), Err(ClockError::Step(_))));
+            assert!(matches!(clock.real_time(bad), Err(ClockError::Speed(_))));
+        }
+        for bad in [-1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                clock.set_lookahead(bad),
+                Err(ClockError::Lookahead(_))
+            ));
+        }
+
+        // Nothing was changed by the rejected values
+        assert_eq!(clock.step(), 5.0);
+        assert_eq!(clock.mode, ClockMode::FastForward);
+        assert_eq!(clock.lookahead(), TIME_LOOKAHEAD_SECS);
+        assert_eq!(clock.set_lookahead(0.0), Ok(()));
+        assert_eq!(clock.set_step(0.5), Ok(()));
+        assert_eq!(clock.step(), 0.5);
+    }
+
+    #[test]
+    fn fast_forward_advances_a_step_per_tick() {
+        let mut clock = clock();
+        let started = Instant::now();
+        for _ in 0..1000 {
+            assert_eq!(clock.tick(), 5.0);
+        }
+        assert_eq!(clock.now(), 5000.0);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn paused_clock_stands_still() {
+        let mut clock = clock();
+        clock.tick();
+        clock.pause();
+        assert!(clock.is_paused());
+        assert_eq!(clock.tick(), 0.0);
+        assert_eq!(clock.now(), 5.0);
+    }
+
+    #[test]
+    fn real_time_paces_ticks_against_the_wall_clock() {
+        let mut clock = clock();
+        clock.set_step(0.5).unwrap();
+        clock.real_time(10.0).unwrap();
+
+        // The first tick has nothing to wait for, later ones wait step / speed = 50 ms each
+        let started = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(clock.tick(), 0.5);
+        }
+        assert!(started.elapsed() >= Duration::from_millis(95));
+        assert_eq!(clock.now(), 1.5);
+    }
+}
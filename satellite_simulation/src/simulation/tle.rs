@@ -0,0 +1,448 @@
+use std::{fmt, fs, path::Path};
+
+use super::time::Epoch;
+
+/**
+ * A parsed two-line element set in the format published by NORAD / CelesTrak.
+ * Angles are kept in degrees and the mean motion in revolutions per day, exactly as they
+ * appear in the element set; the SGP4 propagator converts them to its own units.
+ */
+#[derive(Debug, Clone)]
+pub struct TwoLineElement {
+    pub name: Option<String>,
+    pub catalog_number: u32,
+    pub classification: char,
+    pub international_designator: String,
+    pub epoch: Epoch,
+    pub mean_motion_dot: f64, // first derivative of mean motion / 2, rev/day^2
+    pub mean_motion_ddot: f64, // second derivative of mean motion / 6, rev/day^3
+    pub bstar: f64,           // drag term, 1 / earth radii
+    pub element_set_number: u32,
+    pub inclination: f64, // degrees
+    pub raan: f64,        // degrees
+    pub eccentricity: f64,
+    pub argument_of_perigee: f64, // degrees
+    pub mean_anomaly: f64,        // degrees
+    pub mean_motion: f64,         // revolutions per day
+    pub revolution_number: u32,
+}
+
+/**
+ * Everything that can go wrong while reading an element set. `line` is the element set
+ * line number (1 or 2) the problem was found on.
+ */
+#[derive(Debug)]
+pub enum TleError {
+    Io(std::io::Error),
+    MissingLine {
+        after: String,
+    },
+    LineLength {
+        line: u8,
+        length: usize,
+    },
+    LineNumber {
+        expected: u8,
+        found: String,
+    },
+    Checksum {
+        line: u8,
+        expected: u32,
+        computed: u32,
+    },
+    CatalogMismatch {
+        line1: u32,
+        line2: u32,
+    },
+    InvalidField {
+        line: u8,
+        field: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for TleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TleError::Io(error) => write!(f, "failed to read TLE file: {}", error),
+            TleError::MissingLine { after } => {
+                write!(f, "element set is missing a line after {:?}", after)
+            }
+            TleError::LineLength { line, length } => write!(
+                f,
+                "line {} is {} characters long, expected {}",
+                line, length, TLE_LINE_LENGTH
+            ),
+            TleError::LineNumber { expected, found } => {
+                write!(f, "expected line {} but found {:?}", expected, found)
+            }
+            TleError::Checksum {
+                line,
+                expected,
+                computed,
+            } => write!(
+                f,
+                "line {} checksum mismatch: expected {}, computed {}",
+                line, expected, computed
+            ),
+            TleError::CatalogMismatch { line1, line2 } => write!(
+                f,
+                "catalog numbers differ between lines ({} vs {})",
+                line1, line2
+            ),
+            TleError::InvalidField { line, field, value } => {
+                write!(f, "line {} has an invalid {}: {:?}", line, field, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TleError {}
+
+impl From<std::io::Error> for TleError {
+    fn from(error: std::io::Error) -> Self {
+        TleError::Io(error)
+    }
+}
+
+const TLE_LINE_LENGTH: usize = 69;
+
+impl TwoLineElement {
+    /**
+     * Parses a single element set. Both lines must be the full 69 columns including the
+     * trailing modulo-10 checksum.
+     */
+    pub fn parse(name: Option<&str>, line1: &str, line2: &str) -> Result<Self, TleError> {
+        let line1 = validate_line(line1, 1)?;
+        let line2 = validate_line(line2, 2)?;
+
+        let catalog_number = parse_field::<u32>(line1, 1, 2..7, "catalog number")?;
+        let catalog_number_line2 = parse_field::<u32>(line2, 2, 2..7, "catalog number")?;
+        if catalog_number != catalog_number_line2 {
+            return Err(TleError::CatalogMismatch {
+                line1: catalog_number,
+                line2: catalog_number_line2,
+            });
+        }
+
+        let epoch_year = parse_field::<u32>(line1, 1, 18..20, "epoch year")?;
+        let epoch_day = parse_field::<f64>(line1, 1, 20..32, "epoch day")?;
+        if !(1.0..367.0).contains(&epoch_day) {
+            return Err(invalid_field(line1, 1, 20..32, "epoch day"));
+        }
+
+        Ok(Self {
+            name: name
+                .map(|name| name.trim().trim_start_matches("0 ").to_string())
+                .filter(|name| !name.is_empty()),
+            catalog_number,
+            classification: line1[7..8].chars().next().unwrap_or('U'),
+            international_designator: line1[9..17].trim().to_string(),
+            epoch: Epoch::from_tle_epoch(epoch_year, epoch_day),
+            mean_motion_dot: parse_field(line1, 1, 33..43, "mean motion derivative")?,
+            mean_motion_ddot: parse_implied_decimal(
+                line1,
+                1,
+                44..52,
+                "mean motion second derivative",
+            )?,
+            bstar: parse_implied_decimal(line1, 1, 53..61, "bstar")?,
+            element_set_number: parse_field(line1, 1, 64..68, "element set number")?,
+            inclination: parse_field(line2, 2, 8..16, "inclination")?,
+            raan: parse_field(line2, 2, 17..25, "right ascension")?,
+            eccentricity: parse_field::<f64>(line2, 2, 26..33, "eccentricity")? * 1e-7,
+            argument_of_perigee: parse_field(line2, 2, 34..42, "argument of perigee")?,
+            mean_anomaly: parse_field(line2, 2, 43..51, "mean anomaly")?,
+            mean_motion: parse_field(line2, 2, 52..63, "mean motion")?,
+            revolution_number: parse_field(line2, 2, 63..68, "revolution number")?,
+        })
+    }
+}
+
+impl fmt::Display for TwoLineElement {
+    // One-line summary of the identification fields, e.g. for the load log
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}{}, {}) element set {} at rev {}, epoch {}, ndot/2 {:e} rev/day², nddot/6 {:e} rev/day³",
+            self.name.as_deref().unwrap_or("unnamed"),
+            self.catalog_number,
+            self.classification,
+            self.international_designator,
+            self.element_set_number,
+            self.revolution_number,
+            self.epoch,
+            self.mean_motion_dot,
+            self.mean_motion_ddot
+        )
+    }
+}
+
+/**
+ * Parses CelesTrak-style text containing any number of element sets, either in the
+ * two-line form or the three-line form with a leading satellite name.
+ */
+pub fn parse_tle_text(text: &str) -> Result<Vec<TwoLineElement>, TleError> {
+    let mut element_sets = Vec::new();
+    let mut lines = text
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.trim().is_empty());
+
+    while let Some(line) = lines.next() {
+        let (name, line1) = if line.starts_with("1 ") {
+            (None, line)
+        } else {
+            let line1 = lines.next().ok_or_else(|| TleError::MissingLine {
+                after: line.to_string(),
+            })?;
+            (Some(line), line1)
+        };
+        let line2 = lines.next().ok_or_else(|| TleError::MissingLine {
+            after: line1.to_string(),
+        })?;
+
+        element_sets.push(TwoLineElement::parse(name, line1, line2)?);
+    }
+
+    Ok(element_sets)
+}
+
+pub fn load_tle_file<P: AsRef<Path>>(path: P) -> Result<Vec<TwoLineElement>, TleError> {
+    parse_tle_text(&fs::read_to_string(path)?)
+}
+
+/**
+ * The checksum is the sum of all digits in the first 68 columns, with each minus sign
+ * counting as 1, modulo 10.
+ */
+pub fn tle_checksum(line: &str) -> u32 {
+    line.chars()
+        .take(TLE_LINE_LENGTH - 1)
+        .map(|c| match c {
+            '-' => 1,
+            _ => c.to_digit(10).unwrap_or(0),
+        })
+        .sum::<u32>()
+        % 10
+}
+
+fn validate_line(line: &str, line_number: u8) -> Result<&str, TleError> {
+    let line = line.trim_end();
+    if !line.is_ascii() || line.len() != TLE_LINE_LENGTH {
+        return Err(TleError::LineLength {
+            line: line_number,
+            length: line.chars().count(),
+        });
+    }
+    if !line.starts_with(&format!("{} ", line_number)) {
+        return Err(TleError::LineNumber {
+            expected: line_number,
+            found: line[..2].to_string(),
+        });
+    }
+
+    let expected = line[68..]
+        .chars()
+        .next()
+        .and_then(|c| c.to_digit(10))
+        .ok_or_else(|| invalid_field(line, line_number, 68..69, "checksum"))?;
+    let computed = tle_checksum(line);
+    if expected != computed {
+        return Err(TleError::Checksum {
+            line: line_number,
+            expected,
+            computed,
+        });
+    }
+
+    Ok(line)
+}
+
+fn parse_field<T: std::str::FromStr>(
+    line: &str,
+    line_number: u8,
+    columns: std::ops::Range<usize>,
+    field: &'static str,
+) -> Result<T, TleError> {
+    let value = line[columns.clone()].trim();
+    // Leading-dot decimals (".00001234") and signs are accepted by Rust's f64 parser
+    value
+        .parse::<T>()
+        .map_err(|_| invalid_field(line, line_number, columns, field))
+}
+
+/**
+ * Parses the "assumed decimal point" exponent notation, e.g. " 28098-4" == 0.28098e-4.
+ */
+fn parse_implied_decimal(
+    line: &str,
+    line_number: u8,
+    columns: std::ops::Range<usize>,
+    field: &'static str,
+) -> Result<f64, TleError> {
+    let value = line[columns.clone()].trim();
+    if value.is_empty() {
+        return Ok(0.0);
+    }
+    // At least one mantissa digit in front of the signed exponent digit
+    if value.len() < 3 {
+        return Err(invalid_field(line, line_number, columns, field));
+    }
+
+    let (mantissa, exponent) = value.split_at(value.len() - 2);
+    let (sign, digits) = match mantissa.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, mantissa.trim_start_matches('+')),
+    };
+    let mantissa = format!("0.{}", digits.trim()).parse::<f64>();
+    let exponent = exponent.parse::<i32>();
+
+    match (mantissa, exponent) {
+        (Ok(mantissa), Ok(exponent)) => Ok(sign * mantissa * 10f64.powi(exponent)),
+        _ => Err(invalid_field(line, line_number, columns, field)),
+    }
+}
+
+fn invalid_field(
+    line: &str,
+    line_number: u8,
+    columns: std::ops::Range<usize>,
+    field: &'static str,
+) -> TleError {
+    TleError::InvalidField {
+        line: line_number,
+        field,
+        value: line[columns].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vallado's "Revisiting Spacetrack Report #3" test case 00005
+    const LINE1: &str = "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753";
+    const LINE2: &str = "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667";
+
+    // `line` with `columns` overwritten by `value` and the checksum recomputed
+    fn edited(line: &str, columns: std::ops::Range<usize>, value: &str) -> String {
+        let mut line = line[..TLE_LINE_LENGTH - 1].to_string();
+        line.replace_range(columns, value);
+        format!("{}{}", line, tle_checksum(&line))
+    }
+
+    #[test]
+    fn parses_every_field() {
+        let tle = TwoLineElement::parse(Some("0 VANGUARD 1"), LINE1, LINE2).unwrap();
+        assert_eq!(tle.name.as_deref(), Some("VANGUARD 1"));
+        assert_eq!(tle.catalog_number, 5);
+        assert_eq!(tle.classification, 'U');
+        assert_eq!(tle.international_designator, "58002B");
+        assert_eq!(tle.epoch.to_string(), "2000-06-27T18:50:19.734Z");
+        assert!((tle.mean_motion_dot - 2.3e-7).abs() < 1e-15);
+        assert_eq!(tle.mean_motion_ddot, 0.0);
+        assert!((tle.bstar - 2.8098e-5).abs() < 1e-15);
+        assert_eq!(tle.element_set_number, 475);
+        assert_eq!(tle.inclination, 34.2682);
+        assert_eq!(tle.raan, 348.7242);
+        assert!((tle.eccentricity - 0.1859667).abs() < 1e-12);
+        assert_eq!(tle.argument_of_perigee, 331.7664);
+        assert_eq!(tle.mean_anomaly, 19.3264);
+        assert_eq!(tle.mean_motion, 10.82419157);
+        assert_eq!(tle.revolution_number, 41366);
+        assert_eq!(
+            tle.to_string(),
+            "VANGUARD 1 (5U, 58002B) element set 475 at rev 41366, epoch 2000-06-27T18:50:19.734Z, \
+             ndot/2 2.3e-7 rev/day², nddot/6 0e0 rev/day³"
+        );
+    }
+
+    #[test]
+    fn checksum_counts_digits_and_minus_signs() {
+        assert_eq!(tle_checksum(LINE1), 3);
+        assert_eq!(tle_checksum(LINE2), 7);
+        assert_eq!(tle_checksum("1 -1-"), 4);
+    }
+
+    #[test]
+    fn rejects_a_wrong_checksum() {
+        let line1 = format!("{}9", &LINE1[..68]);
+        match TwoLineElement::parse(None, &line1, LINE2) {
+            Err(TleError::Checksum {
+                line: 1,
+                expected: 9,
+                computed: 3,
+            }) => {}
+            other => panic!("expected a checksum error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_lines_of_the_wrong_length() {
+        match TwoLineElement::parse(None, LINE1, &LINE2[..60]) {
+            Err(TleError::LineLength {
+                line: 2,
+                length: 60,
+            }) => {}
+            other => panic!("expected a line length error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_swapped_lines() {
+        assert!(matches!(
+            TwoLineElement::parse(None, LINE2, LINE1),
+            Err(TleError::LineNumber { expected: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_mismatched_catalog_numbers() {
+        let line2 = edited(LINE2, 2..7, "00006");
+        assert!(matches!(
+            TwoLineElement::parse(None, LINE1, &line2),
+            Err(TleError::CatalogMismatch { line1: 5, line2: 6 })
+        ));
+    }
+
+    #[test]
+    fn rejects_garbage_in_a_column() {
+        let line2 = edited(LINE2, 8..16, " 34.2x82");
+        match TwoLineElement::parse(None, LINE1, &line2) {
+            Err(TleError::InvalidField { line: 2, field, .. }) => assert_eq!(field, "inclination"),
+            other => panic!("expected an invalid field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_implied_decimals_too_short_for_an_exponent() {
+        let line1 = edited(LINE1, 53..61, "      -4");
+        match TwoLineElement::parse(None, &line1, LINE2) {
+            Err(TleError::InvalidField { line: 1, field, .. }) => assert_eq!(field, "bstar"),
+            other => panic!("expected an invalid field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_implied_decimal_exponents() {
+        let line1 = edited(LINE1, 53..61, "-11606-4");
+        let tle = TwoLineElement::parse(None, &line1, LINE2).unwrap();
+        assert!((tle.bstar + 1.1606e-5).abs() < 1e-15);
+    }
+
+    #[test]
+    fn parses_two_and_three_line_sets() {
+        let text = format!("VANGUARD 1\n{}\n{}\n\n{}\n{}\n", LINE1, LINE2, LINE1, LINE2);
+        let sets = parse_tle_text(&text).unwrap();
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].name.as_deref(), Some("VANGUARD 1"));
+        assert_eq!(sets[1].name, None);
+
+        let truncated = format!("VANGUARD 1\n{}\n", LINE1);
+        assert!(matches!(
+            parse_tle_text(&truncated),
+            Err(TleError::MissingLine { .. })
+        ));
+    }
+}
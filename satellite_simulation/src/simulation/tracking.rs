@@ -1,17 +1,30 @@
 use crate::{
-    common::{
-        calculate_euclid_distance, calculate_future_satellite_position, SPEED_OF_LIGHT,
-        TIME_LOOKAHEAD_SECS,
+    common::{calculate_future_satellite_position, Vector3, SPEED_OF_LIGHT},
+    communication::{
+        acm::RateProfile,
+        link_budget::{LinkBudget, LinkBudgetConfig},
+    },
+    simulation::{
+        coordinates::{light_time, Ecef, Eci, WGS84_FLATTENING},
+        ground_station::GroundStation,
+        orbit::StateVector,
+        satellite::Satellite,
+        time::SimClock,
     },
-    simulation::satellite::Satellite,
 };
-use std::{cmp::min, collections::HashMap};
-
-use super::satellite::NeighboringSatelliteInformation;
+use std::{collections::HashMap, fmt};
 
 const COMMUNICATION_RANGE: f64 = 1000.0;
 
-/**
+pub const DEFAULT_ISL_DATA_RATE: f64 = 10e6; // bits per second, modem ceiling
+pub const DEFAULT_GROUND_DATA_RATE: f64 = 50e6; // bits per second, modem ceiling
+pub const DEFAULT_GRAZING_ALTITUDE: f64 = 80_000.0; // meters, lowest a link may pass over the Earth
+pub const DEFAULT_DOPPLER_STEP: f64 = 10.0; // seconds between rows of a frequency-correction table
+const DEFAULT_PLAN_HORIZON: f64 = 24.0 * 3600.0; // seconds
+const DEFAULT_PLAN_SAMPLE_STEP: f64 = 30.0; // seconds
+const RISE_SET_TOLERANCE: f64 = 0.01; // seconds
+
+/*
  * Tracking module is meant to calculate a map of the current satellites position in space.
  * It is also meant to update the map every X seconds. Lets say 3 seconds for now.
  * And it is also the one to give to the satellite its current list of neighbors.
@@ -19,7 +32,7 @@ const COMMUNICATION_RANGE: f64 = 1000.0;
  * That would be the pathfinding module's job that uses a certain set of heuristics aka routing!
  */
 
-/**
+/*
  * Now lets assument the tracking information gets updated every 3 seconds. We have to call an update on the existing graph
  * but also keep track of who is in the network vss who is out of the network that was initially in it.
  * Is that efficient? Can a certain module just be the one tracking that information in the background?
@@ -42,26 +55,512 @@ const COMMUNICATION_RANGE: f64 = 1000.0;
 
 */
 
+/**
+ * A window during which `source` can transmit to `destination`. Start and end times are
+ * absolute simulation timestamps (seconds since the SimClock epoch), latency is the one-way
+ * light time in seconds and rates are in bits per second. The rate follows the link budget
+ * over the window as ACM steps through MODCODs; `data_rate` is its mean, which is all a
+ * constant-rate model such as CGR's needs. `link_budget` is taken mid-window, like the latency.
+ */
 #[derive(Debug, Clone)]
 pub struct Contact {
+    pub source: u32,
     pub destination: u32,
     pub start_time: f64,
     pub end_time: f64,
     pub latency: f64,
+    pub data_rate: f64,
+    pub grazing_altitude: Option<f64>, // meters, lowest point of the line of sight; ISLs only
+    pub link_budget: LinkBudget,
+    pub rate_profile: RateProfile,
+}
+
+impl Contact {
+    pub fn is_active_at(&self, time: f64) -> bool {
+        self.start_time <= time && time <= self.end_time
+    }
+
+    // Bits per second at `time`, nothing outside the contact
+    pub fn rate_at(&self, time: f64) -> f64 {
+        if self.is_active_at(time) {
+            self.rate_profile.rate_at(time)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn overlaps(&self, start_time: f64, end_time: f64) -> bool {
+        self.start_time <= end_time && start_time <= self.end_time
+    }
+
+    // Bits the contact can carry between `start_time` and `end_time`
+    pub fn volume_between(&self, start_time: f64, end_time: f64) -> f64 {
+        self.rate_profile
+            .volume_between(start_time.max(self.start_time), end_time.min(self.end_time))
+    }
+
+    // Total number of bits the contact can carry
+    pub fn volume(&self) -> f64 {
+        self.volume_between(self.start_time, self.end_time)
+    }
 }
 
+#[cfg(test)]
+impl Contact {
+    // Contact at a constant `data_rate` with 10 ms of light time, for tests across the crate
+    pub fn constant(
+        source: u32,
+        destination: u32,
+        start_time: f64,
+        end_time: f64,
+        data_rate: f64,
+    ) -> Self {
+        Self {
+            source,
+            destination,
+            start_time,
+            end_time,
+            latency: 0.01,
+            data_rate,
+            grazing_altitude: None,
+            link_budget: LinkBudget::default(),
+            rate_profile: RateProfile::constant(start_time, data_rate),
+        }
+    }
+}
+
+/**
+ * A link coming up or going down between two snapshots of the network. Ground stations take
+ * part under their own ids; `source` is always the lower id so each link change is reported
+ * once.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionEvent {
+    Established {
+        time: f64,
+        source: u32,
+        destination: u32,
+    },
+    Lost {
+        time: f64,
+        source: u32,
+        destination: u32,
+    },
+}
+
+impl ConnectionEvent {
+    pub fn established(time: f64, a: u32, b: u32) -> Self {
+        ConnectionEvent::Established {
+            time,
+            source: a.min(b),
+            destination: a.max(b),
+        }
+    }
+
+    pub fn lost(time: f64, a: u32, b: u32) -> Self {
+        ConnectionEvent::Lost {
+            time,
+            source: a.min(b),
+            destination: a.max(b),
+        }
+    }
+}
+
+/**
+ * Volume reserved on a contact for a bundle that has been routed over it. Bookings are keyed
+ * by the time the data is due to go out rather than by contact, so they survive the contact
+ * plan being re-predicted with slightly different rise and set times.
+ */
+#[derive(Debug, Clone)]
+pub struct Booking {
+    pub owner: u32, // bundle id
+    pub time: f64,
+    pub volume: f64, // bits
+}
+
+/**
+ * The canonical store of contacts, keyed by source then destination. Each pair's contacts
+ * are kept sorted by start time and never overlap each other; newer information about the
+ * same pair replaces whatever it overlaps. On top of that sits an interval index over every
+ * contact so time-window queries don't have to scan the whole plan.
+ */
+#[derive(Debug, Clone, Default)]
 pub struct ContactGraph {
     pub contacts: HashMap<u32, HashMap<u32, Vec<Contact>>>,
+    index: IntervalIndex,
+    version: u64, // bumped whenever the plan itself changes, so routes can be cached against it
+    bookings: HashMap<(u32, u32), Vec<Booking>>, // queued data per (source, destination)
 }
 
-impl ContactGraph {}
+impl ContactGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.entries.len()
+    }
+
+    // Changes every time contacts are added, replaced or filtered out, but not when they expire
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /**
+     * Adds contacts, each replacing whatever it overlaps between the same pair. The index is
+     * rebuilt once per call, so contacts are best added in batches.
+     */
+    pub fn extend<I: IntoIterator<Item = Contact>>(&mut self, contacts: I) {
+        for contact in contacts {
+            self.insert_without_reindex(contact);
+        }
+        self.reindex();
+        self.version += 1;
+    }
+
+    /**
+     * Drops every contact that ended before `time`. Returns how many were removed. The version
+     * is left alone: a cached route over an expired contact has expired with it, so route
+     * tables only need throwing away when the plan itself changes.
+     */
+    pub fn expire(&mut self, time: f64) -> usize {
+        let before = self.len();
+        self.retain_without_version(|contact| contact.end_time >= time);
+
+        // Bookings go with the contact they were made on
+        let contacts = &self.contacts;
+        self.bookings.retain(|(from, to), bookings| {
+            let pair_contacts = contacts
+                .get(from)
+                .and_then(|destinations| destinations.get(to));
+            bookings.retain(|booking| {
+                pair_contacts.is_some_and(|pair_contacts| {
+                    pair_contacts
+                        .iter()
+                        .any(|contact| contact.is_active_at(booking.time))
+                })
+            });
+            !bookings.is_empty()
+        });
+
+        before - self.len()
+    }
+
+    // Reserves volume from `from` to `to` for data going out at `booking.time`
+    pub fn book(&mut self, from: u32, to: u32, booking: Booking) {
+        self.bookings.entry((from, to)).or_default().push(booking);
+    }
+
+    // Gives back everything `owner` had reserved from `from` to `to`
+    pub fn release(&mut self, owner: u32, from: u32, to: u32) {
+        if let Some(bookings) = self.bookings.get_mut(&(from, to)) {
+            bookings.retain(|booking| booking.owner != owner);
+        }
+    }
+
+    // Bits already queued for `contact`
+    pub fn booked_volume(&self, contact: &Contact) -> f64 {
+        self.bookings
+            .get(&(contact.source, contact.destination))
+            .map(|bookings| {
+                bookings
+                    .iter()
+                    .filter(|booking| contact.is_active_at(booking.time))
+                    .map(|booking| booking.volume)
+                    .sum()
+            })
+            .unwrap_or(0.0)
+    }
+
+    // Bits `contact` can still take on top of what is already queued
+    pub fn residual_volume(&self, contact: &Contact) -> f64 {
+        (contact.volume() - self.booked_volume(contact)).max(0.0)
+    }
+
+    /**
+     * Applies a plan update that was computed for [window_start, window_end]. Contacts we
+     * already had starting inside that window are superseded by the update (including ones
+     * that no longer appear in it), everything outside the window is kept.
+     */
+    pub fn merge(&mut self, window_start: f64, window_end: f64, update: Vec<Contact>) {
+        self.contacts.values_mut().for_each(|destinations| {
+            destinations.values_mut().for_each(|pair_contacts| {
+                pair_contacts.retain(|contact| {
+                    contact.start_time < window_start || contact.start_time > window_end
+                })
+            })
+        });
+        self.extend(update);
+    }
+
+    pub fn contacts_from(&self, node: u32) -> impl Iterator<Item = &Contact> {
+        self.contacts
+            .get(&node)
+            .into_iter()
+            .flat_map(|destinations| destinations.values().flatten())
+    }
+
+    pub fn contacts_between(&self, from: u32, to: u32) -> &[Contact] {
+        self.contacts
+            .get(&from)
+            .and_then(|destinations| destinations.get(&to))
+            .map(|pair_contacts| pair_contacts.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // Contacts from `node` that are open at `time`
+    pub fn active_contacts_from(&self, node: u32, time: f64) -> Vec<&Contact> {
+        let Some(destinations) = self.contacts.get(&node) else {
+            return Vec::new();
+        };
+        destinations
+            .values()
+            .filter_map(|pair_contacts| {
+                // Pair contacts don't overlap, so at most one can be open at `time`
+                let next = pair_contacts.partition_point(|contact| contact.end_time < time);
+                pair_contacts
+                    .get(next)
+                    .filter(|contact| contact.is_active_at(time))
+            })
+            .collect()
+    }
+
+    /**
+     * The first contact from `from` to `to` that is still usable after `time`, i.e. either
+     * already open at `time` or the next one to open.
+     */
+    pub fn next_contact(&self, from: u32, to: u32, time: f64) -> Option<&Contact> {
+        let pair_contacts = self.contacts_between(from, to);
+        let next = pair_contacts.partition_point(|contact| contact.end_time <= time);
+        pair_contacts.get(next)
+    }
+
+    // Every contact that is open at some point during [start_time, end_time]
+    pub fn contacts_overlapping(&self, start_time: f64, end_time: f64) -> Vec<&Contact> {
+        let mut positions = Vec::new();
+        self.index.query(
+            start_time,
+            end_time,
+            0,
+            self.index.entries.len(),
+            &mut positions,
+        );
+        positions
+            .into_iter()
+            .map(|position| {
+                let entry = &self.index.entries[position];
+                &self.contacts[&entry.source][&entry.destination][entry.offset]
+            })
+            .collect()
+    }
+
+    pub fn retain<F: FnMut(&Contact) -> bool>(&mut self, keep: F) {
+        self.retain_without_version(keep);
+        self.version += 1;
+    }
+
+    fn retain_without_version<F: FnMut(&Contact) -> bool>(&mut self, mut keep: F) {
+        self.contacts.values_mut().for_each(|destinations| {
+            destinations
+                .values_mut()
+                .for_each(|pair_contacts| pair_contacts.retain(&mut keep))
+        });
+        self.reindex();
+    }
+
+    fn insert_without_reindex(&mut self, contact: Contact) {
+        let pair_contacts = self
+            .contacts
+            .entry(contact.source)
+            .or_default()
+            .entry(contact.destination)
+            .or_default();
+        pair_contacts.retain(|existing| !existing.overlaps(contact.start_time, contact.end_time));
+        let position =
+            pair_contacts.partition_point(|existing| existing.start_time < contact.start_time);
+        pair_contacts.insert(position, contact);
+    }
+
+    // Drops empty pairs and rebuilds the interval index from scratch
+    fn reindex(&mut self) {
+        self.contacts.values_mut().for_each(|destinations| {
+            destinations.retain(|_, pair_contacts| !pair_contacts.is_empty())
+        });
+        self.contacts
+            .retain(|_, destinations| !destinations.is_empty());
+
+        let entries = self
+            .contacts
+            .iter()
+            .flat_map(|(source, destinations)| {
+                destinations
+                    .iter()
+                    .flat_map(move |(destination, pair_contacts)| {
+                        pair_contacts
+                            .iter()
+                            .enumerate()
+                            .map(move |(offset, contact)| IndexEntry {
+                                start_time: contact.start_time,
+                                end_time: contact.end_time,
+                                source: *source,
+                                destination: *destination,
+                                offset,
+                            })
+                    })
+            })
+            .collect();
+        self.index = IntervalIndex::build(entries);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    start_time: f64,
+    end_time: f64,
+    source: u32,
+    destination: u32,
+    offset: usize, // position within the pair's contact list
+}
+
+/**
+ * Interval index over all contacts: entries sorted by start time form an implicit balanced
+ * binary tree (the middle of each range is its root) and every node remembers the latest end
+ * time in its subtree. A query only descends into subtrees that can still reach the window,
+ * which keeps lookups at O(log n + k) for k results.
+ */
+#[derive(Debug, Clone, Default)]
+struct IntervalIndex {
+    entries: Vec<IndexEntry>,
+    subtree_max_end: Vec<f64>,
+}
+
+impl IntervalIndex {
+    fn build(mut entries: Vec<IndexEntry>) -> Self {
+        entries.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+        let mut index = Self {
+            subtree_max_end: vec![f64::NEG_INFINITY; entries.len()],
+            entries,
+        };
+        index.build_subtree(0, index.entries.len());
+        index
+    }
+
+    fn build_subtree(&mut self, low: usize, high: usize) -> f64 {
+        if low >= high {
+            return f64::NEG_INFINITY;
+        }
+        let mid = (low + high) / 2;
+        let max_end = self.entries[mid]
+            .end_time
+            .max(self.build_subtree(low, mid))
+            .max(self.build_subtree(mid + 1, high));
+        self.subtree_max_end[mid] = max_end;
+        max_end
+    }
+
+    fn query(
+        &self,
+        start_time: f64,
+        end_time: f64,
+        low: usize,
+        high: usize,
+        results: &mut Vec<usize>,
+    ) {
+        if low >= high {
+            return;
+        }
+        let mid = (low + high) / 2;
+        // Nothing in this subtree lasts long enough to reach the window
+        if self.subtree_max_end[mid] < start_time {
+            return;
+        }
+        self.query(start_time, end_time, low, mid, results);
+        // Everything right of a node starting after the window starts after it too
+        if self.entries[mid].start_time <= end_time {
+            if self.entries[mid].end_time >= start_time {
+                results.push(mid);
+            }
+            self.query(start_time, end_time, mid + 1, high, results);
+        }
+    }
+}
+
+/**
+ * Lowest altitude in meters above the WGS-84 ellipsoid reached by the straight line between
+ * two inertial positions, negative if it cuts through the Earth. Stretching z by a/b turns the
+ * ellipsoid into a sphere, where the lowest point is simply the one closest to the center.
+ * The ellipsoid is symmetric about the z axis, so the point can be read off as if in ECEF.
+ */
+pub fn grazing_altitude(from: &Eci, to: &Eci) -> f64 {
+    let stretch = 1.0 / (1.0 - WGS84_FLATTENING);
+    let to_sphere = |p: &Eci| Vector3::new(p.0.x, p.0.y, p.0.z * stretch);
+    let (a, b) = (to_sphere(from), to_sphere(to));
+    let direction = b - a;
+    let length_squared = direction.dot(&direction);
+    let t = if length_squared > 0.0 {
+        (-a.dot(&direction) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let closest = a + direction * t;
+
+    Ecef(Vector3::new(closest.x, closest.y, closest.z / stretch))
+        .to_geodetic()
+        .altitude
+}
+
+// Range and grazing margins in meters between two positions, the link is up while positive
+fn isl_margin(p1: &Eci, p2: &Eci, config: &ContactPlanConfig) -> f64 {
+    (COMMUNICATION_RANGE * 1000.0 - p1.distance_to(p2))
+        .min(grazing_altitude(p1, p2) - config.min_grazing_altitude)
+}
+
+/**
+ * Visibility window of two satellites that come into view within `lookahead` seconds of
+ * `now`: from the rise, or from now if they are already in view, to the set. The set is
+ * searched for in steps of the plan's sample step, no further out than its horizon.
+ */
+fn isl_window(
+    sat1: &Satellite,
+    sat2: &Satellite,
+    now: f64,
+    lookahead: f64,
+    config: &ContactPlanConfig,
+) -> Option<(f64, f64)> {
+    let margin_at = |t: f64| isl_margin(&sat1.eci_at(t), &sat2.eci_at(t), config);
+    let end = now + config.horizon.max(lookahead);
+    let mut times = vec![now, now + lookahead];
+    let mut margins: Vec<f64> = times.iter().map(|t| margin_at(*t)).collect();
+    let mut time = now + lookahead;
+    while time < end && margins.last().is_some_and(|margin| *margin > 0.0) {
+        time = (time + config.sample_step).min(end);
+        times.push(time);
+        margins.push(margin_at(time));
+    }
+    find_visibility_windows(&times, &margins, margin_at)
+        .into_iter()
+        .next()
+}
 
 /**
  * Computes a dynamic map of contacts between satellites. Each satellite
- * has a list of Contact objects representing future communication windows.
+ * has a list of Contact objects representing future communication windows,
+ * stamped against the shared simulation clock. A pair only counts as linked when it is in
+ * range with the line of sight clearing the configured grazing altitude and the link budget
+ * closing, now or within the lookahead. Its contact spans the pair's visibility window,
+ * which is searched for with the config's sample step.
  */
-pub fn create_satellites_map(satellites: &HashMap<u32, Satellite>) -> HashMap<u32, Vec<Contact>> {
+pub fn create_satellites_map(
+    satellites: &HashMap<u32, Satellite>,
+    clock: &SimClock,
+    config: &ContactPlanConfig,
+) -> Result<HashMap<u32, Vec<Contact>>, ContactPlanError> {
+    config.validate()?;
     let mut connections = HashMap::new();
+    let now = clock.now();
+    let lookahead = clock.lookahead();
+    // Visibility is the same both ways, so each pair's window is searched for once
+    let mut windows: HashMap<(u32, u32), Option<(f64, f64)>> = HashMap::new();
 
     for (id1, sat1) in satellites.iter() {
         let mut contact_list: Vec<Contact> = Vec::new();
@@ -70,35 +569,800 @@ pub fn create_satellites_map(satellites: &HashMap<u32, Satellite>) -> HashMap<u3
             if id1 == id2 {
                 continue;
             }
-            // Calculate the current Euclidean distance btw sat1 & sat2
-            let distance_btw_sats = calculate_euclid_distance(&sat1.position, &sat2.position);
-            let predicted_distance = predict_future_distance(sat1, sat2, TIME_LOOKAHEAD_SECS);
-
-            // If within communication range (now or in 10 seconds), create a contact
-            if distance_btw_sats <= COMMUNICATION_RANGE || predicted_distance <= COMMUNICATION_RANGE
-            {
-                let start_time = sat1.time_to_downlink;
-                let end_time = start_time + sat1.communication_window;
-                let latency =
-                    (distance_btw_sats / SPEED_OF_LIGHT).min(predicted_distance / SPEED_OF_LIGHT); // Speed of light delay in ms
+            // Calculate the current and predicted distance btw sat1 & sat2 in km
+            let (future_sat1, future_sat2) = (
+                calculate_future_satellite_position(sat1, lookahead),
+                calculate_future_satellite_position(sat2, lookahead),
+            );
+            let distance_btw_sats = sat1.eci().distance_to(&sat2.eci()) / 1000.0;
+            let predicted_distance = future_sat1.distance_to(&future_sat2) / 1000.0;
+            let (grazing_now, grazing_future) = (
+                grazing_altitude(&sat1.eci(), &sat2.eci()),
+                grazing_altitude(&future_sat1, &future_sat2),
+            );
+            let budget_at = |distance: f64| {
+                LinkBudget::compute(
+                    &sat1.radio,
+                    &sat2.radio,
+                    distance * 1000.0,
+                    config.isl_data_rate,
+                    &config.link_budget,
+                )
+            };
+            let (budget_now, budget_future) =
+                (budget_at(distance_btw_sats), budget_at(predicted_distance));
+            let linked_now = distance_btw_sats <= COMMUNICATION_RANGE
+                && grazing_now >= config.min_grazing_altitude
+                && budget_now.closes();
+            let linked_later = predicted_distance <= COMMUNICATION_RANGE
+                && grazing_future >= config.min_grazing_altitude
+                && budget_future.closes();
+
+            // If linked (now or within the lookahead), create a contact
+            let window = if linked_now || linked_later {
+                *windows
+                    .entry((*id1.min(id2), *id1.max(id2)))
+                    .or_insert_with(|| isl_window(sat1, sat2, now, lookahead, config))
+            } else {
+                None
+            };
+            if let Some((start_time, end_time)) = window {
+                let (grazing_altitude, link_budget) = if linked_now {
+                    (grazing_now, budget_now)
+                } else {
+                    (grazing_future, budget_future)
+                };
+                // Speed of light delay in seconds
+                let latency = light_time(&sat1.eci(), &sat2.eci())
+                    .min(light_time(&future_sat1, &future_sat2));
 
                 contact_list.push(Contact {
+                    source: *id1,
                     destination: *id2,
                     start_time,
                     end_time,
                     latency,
+                    data_rate: link_budget.data_rate,
+                    grazing_altitude: Some(grazing_altitude),
+                    link_budget,
+                    rate_profile: RateProfile::constant(start_time, link_budget.data_rate),
                 });
             }
         }
         connections.insert(*id1, contact_list);
     }
 
-    connections
+    Ok(connections)
+}
+
+/**
+ * Settings for long-horizon contact plan generation. The sample step bounds the shortest
+ * contact we are guaranteed to see; rise and set times themselves are refined well below it.
+ */
+#[derive(Debug, Clone)]
+pub struct ContactPlanConfig {
+    pub horizon: f64,              // seconds past the current clock time to plan for
+    pub sample_step: f64,          // seconds between coarse visibility samples
+    pub isl_data_rate: f64,        // ceiling, the link budget decides below it
+    pub ground_data_rate: f64,     // ceiling, the link budget decides below it
+    pub min_grazing_altitude: f64, // meters, ISLs passing lower are blocked by the atmosphere
+    pub link_budget: LinkBudgetConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContactPlanError {
+    SampleStep(f64),
+    Horizon(f64),
+}
+
+impl fmt::Display for ContactPlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContactPlanError::SampleStep(step) => write!(
+                f,
+                "sample step must be a positive number of seconds, got {}",
+                step
+            ),
+            ContactPlanError::Horizon(horizon) => write!(
+                f,
+                "horizon must be a finite, non-negative number of seconds, got {}",
+                horizon
+            ),
+        }
+    }
 }
 
-fn predict_future_distance(sat1: &Satellite, sat2: &Satellite, time_step: f64) -> f64 {
-    calculate_euclid_distance(
-        &(&calculate_future_satellite_position(sat1, time_step)),
-        &(calculate_future_satellite_position(sat2, time_step)),
-    )
+impl std::error::Error for ContactPlanError {}
+
+impl Default for ContactPlanConfig {
+    fn default() -> Self {
+        Self {
+            horizon: DEFAULT_PLAN_HORIZON,
+            sample_step: DEFAULT_PLAN_SAMPLE_STEP,
+            isl_data_rate: DEFAULT_ISL_DATA_RATE,
+            ground_data_rate: DEFAULT_GROUND_DATA_RATE,
+            min_grazing_altitude: DEFAULT_GRAZING_ALTITUDE,
+            link_budget: LinkBudgetConfig::default(),
+        }
+    }
+}
+
+impl ContactPlanConfig {
+    // Sampling only ever moves forward, and only ends, with a positive step over a finite horizon
+    pub fn validate(&self) -> Result<(), ContactPlanError> {
+        check_sample_step(self.sample_step)?;
+        if !(self.horizon >= 0.0 && self.horizon.is_finite()) {
+            return Err(ContactPlanError::Horizon(self.horizon));
+        }
+        Ok(())
+    }
+}
+
+// Sampling from a start to an end only gets there with a positive, finite step
+pub fn check_sample_step(step: f64) -> Result<f64, ContactPlanError> {
+    if step > 0.0 && step.is_finite() {
+        Ok(step)
+    } else {
+        Err(ContactPlanError::SampleStep(step))
+    }
+}
+
+/**
+ * Propagates every satellite over the configured horizon starting at the clock's current
+ * time and finds every interval in which two satellites are within COMMUNICATION_RANGE with
+ * their line of sight clearing the minimum grazing altitude, or a ground station sees a
+ * satellite above its elevation mask while the station is available. Rise and set times are
+ * found by bisecting on the range, grazing or elevation margin between the coarse samples
+ * where its sign flips. Each direction of a contact gets its own link budget and rate profile,
+ * and is left out if it never closes. ISL contacts report their grazing altitude at
+ * mid-window, like their latency. Contacts are emitted in both directions, sorted by start
+ * time. Fails if the config's sample step or horizon is unusable.
+ */
+pub fn generate_contact_plan(
+    satellites: &HashMap<u32, Satellite>,
+    ground_stations: &[GroundStation],
+    clock: &SimClock,
+    config: &ContactPlanConfig,
+) -> Result<Vec<Contact>, ContactPlanError> {
+    config.validate()?;
+    let start = clock.now();
+    let end = start + config.horizon;
+    let mut sample_times = Vec::new();
+    let mut time = start;
+    while time < end {
+        sample_times.push(time);
+        time += config.sample_step;
+    }
+    sample_times.push(end);
+
+    // Propagate each satellite once over the whole horizon
+    let mut ids: Vec<u32> = satellites.keys().copied().collect();
+    ids.sort();
+    let sampled_positions: HashMap<u32, Vec<Eci>> = ids
+        .iter()
+        .map(|id| {
+            let sat = &satellites[id];
+            let positions = sample_times.iter().map(|t| sat.eci_at(*t)).collect();
+            (*id, positions)
+        })
+        .collect();
+
+    let mut plan = Vec::new();
+    for (index, id1) in ids.iter().enumerate() {
+        let sat1 = &satellites[id1];
+
+        // Inter-satellite links, each unordered pair once
+        for id2 in &ids[index + 1..] {
+            let sat2 = &satellites[id2];
+            let margins: Vec<f64> = sampled_positions[id1]
+                .iter()
+                .zip(&sampled_positions[id2])
+                .map(|(p1, p2)| isl_margin(p1, p2, config))
+                .collect();
+            let margin_at = |t: f64| isl_margin(&sat1.eci_at(t), &sat2.eci_at(t), config);
+            let grazing_at = |t: f64| grazing_altitude(&sat1.eci_at(t), &sat2.eci_at(t));
+            let budget_at = |t: f64, forward: bool| {
+                let (from, to) = if forward { (sat1, sat2) } else { (sat2, sat1) };
+                LinkBudget::compute(
+                    &from.radio,
+                    &to.radio,
+                    from.eci_at(t).distance_to(&to.eci_at(t)),
+                    config.isl_data_rate,
+                    &config.link_budget,
+                )
+            };
+            let latency_at = |t: f64| light_time(&sat1.eci_at(t), &sat2.eci_at(t));
+
+            for (rise, set) in find_visibility_windows(&sample_times, &margins, margin_at) {
+                push_bidirectional_contact(
+                    &mut plan,
+                    (*id1, *id2),
+                    (rise, set),
+                    latency_at((rise + set) / 2.0),
+                    Some(grazing_at((rise + set) / 2.0)),
+                    budget_at,
+                    config,
+                );
+            }
+        }
+
+        // Satellite to each ground station
+        for station in ground_stations {
+            let margins: Vec<f64> = sampled_positions[id1]
+                .iter()
+                .zip(&sample_times)
+                .map(|(p, t)| station.elevation_margin(p, &clock.epoch_at(*t)))
+                .collect();
+            let margin_at = |t: f64| station.elevation_margin(&sat1.eci_at(t), &clock.epoch_at(t));
+            let latency_at =
+                |t: f64| light_time(&sat1.eci_at(t), &station.eci_at(&clock.epoch_at(t)));
+            let budget_at = |t: f64, forward: bool| {
+                let (from, to) = if forward {
+                    (&sat1.radio, &station.radio)
+                } else {
+                    (&station.radio, &sat1.radio)
+                };
+                let range = sat1
+                    .eci_at(t)
+                    .distance_to(&station.eci_at(&clock.epoch_at(t)));
+                LinkBudget::compute(
+                    from,
+                    to,
+                    range,
+                    config.ground_data_rate,
+                    &config.link_budget,
+                )
+            };
+
+            for (rise, set) in find_visibility_windows(&sample_times, &margins, margin_at) {
+                for (start, end) in station.available_within(rise, set) {
+                    push_bidirectional_contact(
+                        &mut plan,
+                        (*id1, station.id),
+                        (start, end),
+                        latency_at((start + end) / 2.0),
+                        None,
+                        budget_at,
+                        config,
+                    );
+                }
+            }
+        }
+    }
+
+    plan.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+    Ok(plan)
+}
+
+fn push_bidirectional_contact(
+    plan: &mut Vec<Contact>,
+    (node1, node2): (u32, u32),
+    (start_time, end_time): (f64, f64),
+    latency: f64,
+    grazing_altitude: Option<f64>,
+    budget_at: impl Fn(f64, bool) -> LinkBudget, // `true` for node1 -> node2
+    config: &ContactPlanConfig,
+) {
+    let duration = end_time - start_time;
+    for (source, destination, forward) in [(node1, node2, true), (node2, node1, false)] {
+        let link_budget = budget_at((start_time + end_time) / 2.0, forward);
+        let rate_profile = if config.link_budget.acm.is_some() {
+            rate_profile(
+                |t| budget_at(t, forward).data_rate,
+                (start_time, end_time),
+                config.sample_step,
+            )
+        } else {
+            RateProfile::constant(start_time, link_budget.data_rate)
+        };
+        let volume = rate_profile.volume_between(start_time, end_time);
+        if volume <= 0.0 {
+            continue;
+        }
+        plan.push(Contact {
+            source,
+            destination,
+            start_time,
+            end_time,
+            latency,
+            data_rate: if duration > 0.0 {
+                volume / duration
+            } else {
+                link_budget.data_rate
+            },
+            grazing_altitude,
+            link_budget,
+            rate_profile,
+        });
+    }
+}
+
+/**
+ * How an ACM rate changes over [start, end]: sampled every `step` seconds, with each change
+ * of MODCOD refined by bisection. Two changes within one step show up as a single one.
+ */
+fn rate_profile<F: Fn(f64) -> f64>(rate_at: F, (start, end): (f64, f64), step: f64) -> RateProfile {
+    let mut rate = rate_at(start);
+    let mut profile = RateProfile::constant(start, rate);
+    let mut time = start;
+    while time < end {
+        let next = (time + step).min(end);
+        let next_rate = rate_at(next);
+        if next_rate != rate {
+            let unchanged = |t: f64| if rate_at(t) == rate { 1.0 } else { -1.0 };
+            profile.push(bisect_crossing(&unchanged, time, next), next_rate);
+            rate = next_rate;
+        }
+        time = next;
+    }
+    profile
+}
+
+// How fast the distance between two ECI states grows, in m/s (negative while closing in)
+pub fn range_rate(from: &StateVector, to: &StateVector) -> f64 {
+    let line_of_sight = to.position - from.position;
+    let range = line_of_sight.norm();
+    if range == 0.0 {
+        return 0.0;
+    }
+    line_of_sight.dot(&(to.velocity - from.velocity)) / range
+}
+
+// First-order Doppler offset in Hz on a `frequency` Hz carrier at `range_rate` m/s
+pub fn doppler_shift(frequency: f64, range_rate: f64) -> f64 {
+    -frequency * range_rate / (SPEED_OF_LIGHT * 1000.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DopplerSample {
+    pub time: f64,
+    pub range: f64,      // meters
+    pub range_rate: f64, // m/s
+    pub doppler: f64,    // Hz, received minus transmitted frequency
+}
+
+/**
+ * Doppler over one contact, for the receiver to tune (or the transmitter to pre-compensate)
+ * by `-doppler` at each row. Rows are spaced evenly and close on the contact's end.
+ */
+#[derive(Debug, Clone)]
+pub struct DopplerTable {
+    pub source: u32,
+    pub destination: u32,
+    pub frequency: f64, // Hz, carrier as transmitted
+    pub samples: Vec<DopplerSample>,
+}
+
+impl DopplerTable {
+    // Largest offset seen over the contact, in Hz
+    pub fn max_doppler(&self) -> f64 {
+        self.samples
+            .iter()
+            .map(|sample| sample.doppler.abs())
+            .fold(0.0, f64::max)
+    }
+
+    // Fastest the offset changes between rows, in Hz per second
+    pub fn max_doppler_rate(&self) -> f64 {
+        self.samples
+            .windows(2)
+            .map(|pair| ((pair[1].doppler - pair[0].doppler) / (pair[1].time - pair[0].time)).abs())
+            .fold(0.0, f64::max)
+    }
+
+    /**
+     * The frequency-correction table as CSV, one row per sample with the UTC time, range,
+     * range-rate, Doppler offset and the correction that cancels it.
+     */
+    pub fn to_csv(&self, clock: &SimClock) -> String {
+        let mut csv = String::from("time,utc,range_m,range_rate_m_s,doppler_hz,correction_hz\n");
+        for sample in &self.samples {
+            csv.push_str(&format!(
+                "{:.3},{},{:.1},{:.3},{:.1},{:.1}\n",
+                sample.time,
+                clock.epoch_at(sample.time),
+                sample.range,
+                sample.range_rate,
+                sample.doppler,
+                -sample.doppler
+            ));
+        }
+        csv
+    }
+}
+
+/**
+ * Range-rate and Doppler on a `frequency` Hz carrier over `contact`, every `step` seconds.
+ * `state_at` gives the ECI state of a node (satellite or ground station) at a simulation
+ * time; the relative velocity comes straight from the propagated states rather than from
+ * differencing positions. None if either end has no state, an error if `step` would never
+ * reach the end of the contact.
+ */
+pub fn doppler_table<F: Fn(u32, f64) -> Option<StateVector>>(
+    contact: &Contact,
+    frequency: f64,
+    step: f64,
+    state_at: F,
+) -> Result<Option<DopplerTable>, ContactPlanError> {
+    let step = check_sample_step(step)?;
+    let mut samples = Vec::new();
+    let mut time = contact.start_time;
+    loop {
+        let (Some(from), Some(to)) = (
+            state_at(contact.source, time),
+            state_at(contact.destination, time),
+        ) else {
+            return Ok(None);
+        };
+        let rate = range_rate(&from, &to);
+        samples.push(DopplerSample {
+            time,
+            range: from.position.distance_to(&to.position),
+            range_rate: rate,
+            doppler: doppler_shift(frequency, rate),
+        });
+        if time >= contact.end_time {
+            break;
+        }
+        time = (time + step).min(contact.end_time);
+    }
+
+    Ok(Some(DopplerTable {
+        source: contact.source,
+        destination: contact.destination,
+        frequency,
+        samples,
+    }))
+}
+
+/**
+ * Given a visibility margin sampled at `times` (positive = visible), returns the
+ * (rise, set) intervals with each sign change refined by bisection on `margin_at`.
+ * Intervals already open at the first sample or still open at the last are clipped to them.
+ */
+pub(crate) fn find_visibility_windows<F: Fn(f64) -> f64>(
+    times: &[f64],
+    margins: &[f64],
+    margin_at: F,
+) -> Vec<(f64, f64)> {
+    let mut windows = Vec::new();
+    let mut rise = if margins.first().is_some_and(|m| *m > 0.0) {
+        Some(times[0])
+    } else {
+        None
+    };
+
+    for k in 1..times.len() {
+        let (was_visible, is_visible) = (margins[k - 1] > 0.0, margins[k] > 0.0);
+        if was_visible == is_visible {
+            continue;
+        }
+        let crossing = bisect_crossing(&margin_at, times[k - 1], times[k]);
+        match rise.take() {
+            Some(rise_time) => windows.push((rise_time, crossing)),
+            None => rise = Some(crossing),
+        }
+    }
+    if let (Some(rise_time), Some(last)) = (rise, times.last()) {
+        windows.push((rise_time, *last));
+    }
+
+    windows
+}
+
+// Finds where `margin` changes sign between `low` and `high`
+pub(crate) fn bisect_crossing<F: Fn(f64) -> f64>(margin: &F, mut low: f64, mut high: f64) -> f64 {
+    let low_visible = margin(low) > 0.0;
+    while high - low > RISE_SET_TOLERANCE {
+        let mid = (low + high) / 2.0;
+        if (margin(mid) > 0.0) == low_visible {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{
+        cgr::{RouteTable, CGR},
+        coordinates::WGS84_SEMI_MAJOR_AXIS,
+        orbit::OrbitalElements,
+        time::Epoch,
+    };
+    use std::f64::consts::PI;
+
+    fn graph() -> ContactGraph {
+        let mut graph = ContactGraph::new();
+        graph.extend([
+            Contact::constant(1, 2, 0.0, 100.0, 1e6),
+            Contact::constant(1, 2, 200.0, 300.0, 1e6),
+            Contact::constant(1, 3, 50.0, 150.0, 1e6),
+            Contact::constant(2, 3, 400.0, 500.0, 1e6),
+        ]);
+        graph
+    }
+
+    // Two satellites in planes 10° apart, in and out of range a couple of times per orbit
+    fn crossing_pair(epoch: &Epoch) -> HashMap<u32, Satellite> {
+        [(1, 0.0), (2, 10.0_f64.to_radians())]
+            .into_iter()
+            .map(|(id, raan)| {
+                let elements = OrbitalElements::circular(550.0, 53.0_f64.to_radians(), raan, 0.0);
+                (id, Satellite::from_elements(id, elements, epoch))
+            })
+            .collect()
+    }
+
+    fn pair_margin(satellites: &HashMap<u32, Satellite>, time: f64) -> f64 {
+        isl_margin(
+            &satellites[&1].eci_at(time),
+            &satellites[&2].eci_at(time),
+            &ContactPlanConfig::default(),
+        )
+    }
+
+    #[test]
+    fn visibility_windows_are_refined_to_the_crossings() {
+        let times: Vec<f64> = (0..=20).map(|k| 0.5 + k as f64 * 0.5).collect();
+        let margins: Vec<f64> = times.iter().map(|t| t.sin()).collect();
+        let windows = find_visibility_windows(&times, &margins, f64::sin);
+
+        // Already visible at the first sample, then one full window, then nothing left open
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].0, 0.5);
+        for (actual, expected) in [
+            (windows[0].1, PI),
+            (windows[1].0, 2.0 * PI),
+            (windows[1].1, 3.0 * PI),
+        ] {
+            assert!((actual - expected).abs() <= RISE_SET_TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn plan_contacts_rise_and_set_on_the_range_limit() {
+        let epoch = Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0);
+        let clock = SimClock::new(epoch, 10.0);
+        let satellites = crossing_pair(&epoch);
+        let config = ContactPlanConfig {
+            horizon: 3.0 * 3600.0,
+            ..ContactPlanConfig::default()
+        };
+        let plan = generate_contact_plan(&satellites, &[], &clock, &config).unwrap();
+
+        let forward: Vec<&Contact> = plan.iter().filter(|c| c.source == 1).collect();
+        let backward: Vec<&Contact> = plan.iter().filter(|c| c.source == 2).collect();
+        assert!(forward.len() >= 3);
+        assert_eq!(forward.len(), backward.len());
+        for contact in forward {
+            let midpoint = (contact.start_time + contact.end_time) / 2.0;
+            assert!(pair_margin(&satellites, midpoint) > 0.0);
+            if contact.end_time < config.horizon {
+                // 7.5 km/s of closing speed over the bisection tolerance
+                assert!(pair_margin(&satellites, contact.start_time).abs() < 100.0);
+                assert!(pair_margin(&satellites, contact.end_time).abs() < 100.0);
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_contacts_span_the_pair_window() {
+        let epoch = Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0);
+        let mut clock = SimClock::new(epoch, 1000.0);
+        clock.tick();
+        let mut satellites = crossing_pair(&epoch);
+        satellites
+            .values_mut()
+            .for_each(|sat| sat.propagate_to(clock.now()));
+        let config = ContactPlanConfig::default();
+
+        let window = generate_contact_plan(&satellites, &[], &clock, &config)
+            .unwrap()
+            .into_iter()
+            .find(|c| c.source == 1)
+            .unwrap();
+        assert_eq!(window.start_time, clock.now());
+
+        let snapshot = create_satellites_map(&satellites, &clock, &config).unwrap();
+        let contact = &snapshot[&1][0];
+        assert_eq!(contact.destination, 2);
+        assert_eq!(contact.start_time, clock.now());
+        assert!((contact.end_time - window.end_time).abs() <= RISE_SET_TOLERANCE);
+    }
+
+    #[test]
+    fn rejects_steps_that_never_reach_the_horizon() {
+        let epoch = Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0);
+        let clock = SimClock::new(epoch, 10.0);
+        let satellites = crossing_pair(&epoch);
+        for (sample_step, horizon) in [
+            (0.0, 3600.0),
+            (-30.0, 3600.0),
+            (f64::NAN, 3600.0),
+            (30.0, f64::INFINITY),
+            (30.0, -1.0),
+        ] {
+            let config = ContactPlanConfig {
+                sample_step,
+                horizon,
+                ..ContactPlanConfig::default()
+            };
+            assert!(generate_contact_plan(&satellites, &[], &clock, &config).is_err());
+            assert!(create_satellites_map(&satellites, &clock, &config).is_err());
+        }
+    }
+
+    // Two equatorial positions `altitude` meters up, `half_angle` radians either side of the x axis
+    fn equatorial_pair(altitude: f64, half_angle: f64) -> (Eci, Eci) {
+        let radius = WGS84_SEMI_MAJOR_AXIS + altitude;
+        let at = |angle: f64| {
+            Eci(Vector3::new(
+                radius * angle.cos(),
+                radius * angle.sin(),
+                0.0,
+            ))
+        };
+        (at(-half_angle), at(half_angle))
+    }
+
+    #[test]
+    fn grazing_altitude_blocks_links_through_the_atmosphere() {
+        let config = ContactPlanConfig::default();
+        let min = config.min_grazing_altitude;
+        // The chord's midpoint is the lowest point, this half angle puts it right on the limit
+        let grazing_angle = ((WGS84_SEMI_MAJOR_AXIS + min) / (WGS84_SEMI_MAJOR_AXIS + 90e3)).acos();
+
+        // Clear: well above the atmosphere, only the range limits the link
+        let (p1, p2) = equatorial_pair(550e3, 3.0_f64.to_radians());
+        let expected =
+            (WGS84_SEMI_MAJOR_AXIS + 550e3) * 3.0_f64.to_radians().cos() - WGS84_SEMI_MAJOR_AXIS;
+        assert!((grazing_altitude(&p1, &p2) - expected).abs() < 1.0);
+        let range_margin = COMMUNICATION_RANGE * 1000.0 - p1.distance_to(&p2);
+        assert!((isl_margin(&p1, &p2, &config) - range_margin).abs() < 1e-6);
+        assert!(range_margin > 0.0);
+
+        // Grazing: the link skims the limit, well within range
+        let (p1, p2) = equatorial_pair(90e3, grazing_angle);
+        assert!((grazing_altitude(&p1, &p2) - min).abs() < 1.0);
+        assert!(isl_margin(&p1, &p2, &config).abs() < 1.0);
+
+        // Blocked: still within range, but the line dips below the limit
+        let (p1, p2) = equatorial_pair(90e3, grazing_angle + 1.0_f64.to_radians());
+        assert!(p1.distance_to(&p2) < COMMUNICATION_RANGE * 1000.0);
+        assert!(grazing_altitude(&p1, &p2) < min);
+        assert!(isl_margin(&p1, &p2, &config) < 0.0);
+
+        // Far apart: the line runs deep through the Earth
+        let (p1, p2) = equatorial_pair(550e3, 80.0_f64.to_radians());
+        assert!(grazing_altitude(&p1, &p2) < -WGS84_SEMI_MAJOR_AXIS / 2.0);
+        assert!(isl_margin(&p1, &p2, &config) < 0.0);
+    }
+
+    #[test]
+    fn answers_time_queries() {
+        let graph = graph();
+        let mut overlapping: Vec<(u32, u32, f64)> = graph
+            .contacts_overlapping(90.0, 210.0)
+            .into_iter()
+            .map(|c| (c.source, c.destination, c.start_time))
+            .collect();
+        overlapping.sort_by(|a, b| a.2.total_cmp(&b.2));
+        assert_eq!(overlapping, [(1, 2, 0.0), (1, 3, 50.0), (1, 2, 200.0)]);
+
+        assert_eq!(graph.active_contacts_from(1, 75.0).len(), 2);
+        assert!(graph.active_contacts_from(1, 175.0).is_empty());
+        assert_eq!(
+            graph.next_contact(1, 2, 150.0).map(|c| c.start_time),
+            Some(200.0)
+        );
+        assert!(graph.next_contact(1, 2, 300.0).is_none());
+    }
+
+    #[test]
+    fn newer_contacts_replace_what_they_overlap() {
+        let mut graph = graph();
+        graph.extend([Contact::constant(1, 2, 250.0, 350.0, 1e6)]);
+        let starts: Vec<f64> = graph
+            .contacts_between(1, 2)
+            .iter()
+            .map(|c| c.start_time)
+            .collect();
+        assert_eq!(starts, [0.0, 250.0]);
+    }
+
+    #[test]
+    fn cached_routes_survive_expiry() {
+        let mut graph = graph();
+        let mut clock = SimClock::new(Epoch::from_calendar(2024, 1, 1, 0, 0, 0.0), 160.0);
+        clock.tick();
+        let mut table = RouteTable::new(graph.version());
+        table.insert(
+            3,
+            CGR::from_contact_graph(&graph).find_k_best_routes(&clock, 1, 3, 0.0, 2),
+        );
+
+        assert_eq!(graph.expire(10.0), 0);
+        assert_eq!(graph.expire(160.0), 2);
+        assert!(graph.contacts_overlapping(0.0, 160.0).is_empty());
+
+        // Only contacts that had already closed went, so the routes over the rest still hold
+        assert!(table.is_valid_for(graph.version()));
+        let routes = table.routes_to(3).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].nodes(), [1, 2, 3]);
+    }
+
+    // Node 2 flies past node 1 along x at 7.5 km/s, 1000 km off at its closest, at time 0
+    fn fly_by(node: u32, time: f64) -> Option<StateVector> {
+        match node {
+            1 => Some(StateVector::default()),
+            2 => Some(StateVector {
+                position: Vector3::new(7500.0 * time, 1e6, 0.0),
+                velocity: Vector3::new(7500.0, 0.0, 0.0),
+            }),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn doppler_is_positive_while_closing_in() {
+        let approaching = doppler_shift(2.2e9, -7500.0);
+        assert!((approaching - 2.2e9 * 7500.0 / 299_792_458.0).abs() < 1e-6);
+        assert_eq!(doppler_shift(2.2e9, 7500.0), -approaching);
+
+        let from = fly_by(1, 0.0).unwrap();
+        assert!(range_rate(&from, &fly_by(2, -10.0).unwrap()) < 0.0);
+        assert_eq!(range_rate(&from, &fly_by(2, 0.0).unwrap()), 0.0);
+        assert!(range_rate(&from, &fly_by(2, 10.0).unwrap()) > 0.0);
+    }
+
+    #[test]
+    fn doppler_table_follows_the_fly_by() {
+        let table = doppler_table(
+            &Contact::constant(1, 2, -60.0, 65.0, 1e6),
+            2.2e9,
+            10.0,
+            fly_by,
+        )
+        .unwrap()
+        .unwrap();
+        let times: Vec<f64> = table.samples.iter().map(|s| s.time).collect();
+        assert_eq!(times.len(), 14);
+        assert_eq!((times[0], times[12], times[13]), (-60.0, 60.0, 65.0));
+
+        for sample in &table.samples {
+            let offset = 7500.0 * sample.time;
+            let range = (offset * offset + 1e12).sqrt();
+            assert!((sample.range - range).abs() < 1e-6);
+            assert!((sample.range_rate - 7500.0 * offset / range).abs() < 1e-9);
+        }
+        // Furthest out is where the line of sight lines up with the velocity the most
+        assert_eq!(table.max_doppler(), table.samples[13].doppler.abs());
+        assert!(table.max_doppler_rate() > 0.0);
+
+        assert!(
+            doppler_table(&Contact::constant(1, 3, 0.0, 10.0, 1e6), 2.2e9, 1.0, fly_by)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn doppler_tables_need_a_step_that_reaches_the_end() {
+        let contact = Contact::constant(1, 2, 0.0, 10.0, 1e6);
+        for step in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(
+                matches!(
+                    doppler_table(&contact, 2.2e9, step, fly_by),
+                    Err(ContactPlanError::SampleStep(_))
+                ),
+                "{}",
+                step
+            );
+        }
+    }
 }
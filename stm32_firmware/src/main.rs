@@ -20,6 +20,11 @@ use stm32l4xx_hal::{
 
 static mut TX: Option<Tx<USART2>> = None;
 
+// Ground station this board asks the simulator to downlink to. The simulator models many
+// stations, this is the one that answers to the button.
+const GROUND_STATION_LATITUDE: f32 = 37.7749;
+const GROUND_STATION_LONGITUDE: f32 = -122.4194;
+
 #[entry]
 fn main() -> ! {
     let mut dp = pac::Peripherals::take().unwrap(); // Take ownership of all device peripherals
@@ -137,7 +142,7 @@ fn EXTI0() {
     // we clear the flag to prevent repeated interrupts.
     exti.pr1.write(|w| w.pr0().set_bit());
 
-    send_downlink_request(37.7749, -122.4194);
+    send_downlink_request(GROUND_STATION_LATITUDE, GROUND_STATION_LONGITUDE);
 }
 
 fn send_downlink_request(lat: f32, long: f32) {